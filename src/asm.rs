@@ -0,0 +1,716 @@
+//! Assembler for the source printed by `disasm`.
+//!
+//! ```text
+//! ; Comments start with a semicolon.
+//! :const SPEED 2
+//! :alias x V6
+//!
+//! :macro move reg, amount
+//!     ADD reg, amount
+//! :endm
+//!
+//! start:
+//!     LD I, ball          ; labels can be used before they are defined
+//!     move x, SPEED
+//!     DRW x, V7, 2
+//!     JP start
+//! ball:
+//!     sprite "##......", "##......"
+//! ```
+//!
+//! Mnemonics and operands are the ones `Instruction` prints, case-insensitive.
+//! Numbers are decimal, `0x` hex or `0b` binary, and operands may add or
+//! subtract symbols, e.g. `LD I, table + 2`. The directives are:
+//!
+//! * `:const NAME expr`: a named value.
+//! * `:alias NAME Vx`: another name for a register.
+//! * `:include "file"`: assembles another file in place, relative to this one.
+//! * `:macro NAME param, ...` ... `:endm`: a macro, invoked as `NAME arg, ...`;
+//!   each parameter in its body is replaced by the tokens of its argument.
+//! * `db expr, ...` and `dw expr, ...`: raw bytes and big-endian words.
+//! * `sprite "row", ...`: one sprite row per string of 8 or 16 pixels, `#` for
+//!   a lit pixel and `.` for an unlit one.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::chip::{APPLICATION_MEMORY_LOCATION, MEMORY_SIZE};
+use crate::opcodes::Instruction;
+
+// Guards against macros and includes expanding forever.
+const MAX_NESTING: usize = 32;
+
+/// An assembly error, located in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)
+    }
+}
+
+impl error::Error for AsmError {}
+
+/// Where a token comes from: file, and 1-based line and column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Location {
+    pub file: Rc<str>,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    pub fn error<S: Into<String>>(&self, message: S) -> AsmError {
+        AsmError {
+            file: self.file.to_string(),
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TokenKind {
+    Ident(String),
+    Number(i64),
+    Str(String),
+    Punct(char),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Token {
+    pub kind: TokenKind,
+    pub location: Location,
+}
+
+impl Token {
+    pub fn is_punct(&self, c: char) -> bool {
+        self.kind == TokenKind::Punct(c)
+    }
+
+    pub fn ident(&self) -> Option<&str> {
+        match &self.kind {
+            TokenKind::Ident(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            TokenKind::Ident(name) => write!(f, "`{}`", name),
+            TokenKind::Number(value) => write!(f, "`{}`", value),
+            TokenKind::Str(text) => write!(f, "\"{}\"", text),
+            TokenKind::Punct(c) => write!(f, "`{}`", c),
+        }
+    }
+}
+
+/// Splits one line into tokens, stopping at `comment`.
+pub(crate) fn tokenize(text: &str, file: &Rc<str>, line: usize, comment: char) -> Result<Vec<Token>, AsmError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut at = 0;
+    while at < chars.len() {
+        let c = chars[at];
+        let location = Location { file: file.clone(), line, column: at + 1 };
+        let start = at;
+        let kind = if c == comment {
+            break;
+        } else if c.is_whitespace() {
+            at += 1;
+            continue;
+        } else if c.is_ascii_digit() {
+            while at < chars.len() && (chars[at].is_ascii_alphanumeric() || chars[at] == '_') {
+                at += 1;
+            }
+            let literal: String = chars[start..at].iter().filter(|c| **c != '_').collect();
+            let lower = literal.to_ascii_lowercase();
+            let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+                i64::from_str_radix(hex, 16)
+            } else if let Some(binary) = lower.strip_prefix("0b") {
+                i64::from_str_radix(binary, 2)
+            } else {
+                lower.parse()
+            };
+            TokenKind::Number(parsed.map_err(|_| location.error(format!("invalid number `{}`", literal)))?)
+        } else if c.is_alphabetic() || c == '_' || c == '.' {
+            while at < chars.len() && (chars[at].is_alphanumeric() || chars[at] == '_' || chars[at] == '.') {
+                at += 1;
+            }
+            TokenKind::Ident(chars[start..at].iter().collect())
+        } else if c == '"' {
+            at += 1;
+            while at < chars.len() && chars[at] != '"' {
+                at += 1;
+            }
+            if at == chars.len() {
+                return Err(location.error("unterminated string"));
+            }
+            at += 1;
+            TokenKind::Str(chars[start + 1..at - 1].iter().collect())
+        } else if c.is_ascii_punctuation() {
+            at += 1;
+            TokenKind::Punct(c)
+        } else {
+            return Err(location.error(format!("unexpected character `{}`", c)));
+        };
+        tokens.push(Token { kind, location });
+    }
+    Ok(tokens)
+}
+
+/// Splits tokens on every comma: operands never contain one, not even in
+/// parentheses.
+pub(crate) fn split_commas(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+    tokens.split(|token| token.is_punct(',')).collect()
+}
+
+/// A symbol defined by the source.
+#[derive(Debug, Clone)]
+pub(crate) enum Symbol {
+    Label(u16),
+    Const(Vec<Token>),
+}
+
+/// Symbols of a program, with the expression evaluator that resolves them.
+#[derive(Debug, Default)]
+pub(crate) struct SymbolTable {
+    pub symbols: HashMap<String, Symbol>,
+}
+
+impl SymbolTable {
+    pub fn define(&mut self, name: &Token, symbol: Symbol) -> Result<(), AsmError> {
+        let key = name.ident().unwrap().to_string();
+        if self.symbols.insert(key.clone(), symbol).is_some() {
+            return Err(name.location.error(format!("`{}` is already defined", key)));
+        }
+        Ok(())
+    }
+
+    /// Evaluates `expr := ['-'] term (('+' | '-') term)*`, where a term is a
+    /// number, a symbol or a parenthesised expression.
+    pub fn evaluate(&self, tokens: &[Token], at: &Location) -> Result<i64, AsmError> {
+        self.evaluate_nested(tokens, at, 0)
+    }
+
+    fn evaluate_nested(&self, tokens: &[Token], at: &Location, depth: usize) -> Result<i64, AsmError> {
+        if depth > MAX_NESTING {
+            return Err(at.error("constant defined in terms of itself"));
+        }
+        let mut rest = tokens;
+        let mut value: i64 = 0;
+        let mut sign = 1;
+        loop {
+            if let Some(token) = rest.first().filter(|token| token.is_punct('-') || token.is_punct('+')) {
+                if token.is_punct('-') {
+                    sign = -sign;
+                }
+                rest = &rest[1..];
+                continue;
+            }
+            let location = rest.first().map_or(at, |token| &token.location);
+            let (term, remaining) = self.term(rest, at, depth)?;
+            value = term.checked_mul(sign).and_then(|term| value.checked_add(term))
+                .ok_or_else(|| location.error("expression overflows"))?;
+            rest = remaining;
+            match rest.first() {
+                None => return Ok(value),
+                Some(token) if token.is_punct('+') => sign = 1,
+                Some(token) if token.is_punct('-') => sign = -1,
+                Some(token) => return Err(token.location.error(format!("unexpected {} in expression", token))),
+            }
+            rest = &rest[1..];
+        }
+    }
+
+    fn term<'t>(&self, tokens: &'t [Token], at: &Location, depth: usize) -> Result<(i64, &'t [Token]), AsmError> {
+        let token = tokens.first().ok_or_else(|| at.error("expected a value"))?;
+        match &token.kind {
+            TokenKind::Number(value) => Ok((*value, &tokens[1..])),
+            TokenKind::Ident(name) => match self.symbols.get(name) {
+                Some(Symbol::Label(addr)) => Ok((*addr as i64, &tokens[1..])),
+                Some(Symbol::Const(expr)) => Ok((self.evaluate_nested(expr, &token.location, depth + 1)?, &tokens[1..])),
+                None => Err(token.location.error(format!("unknown symbol `{}`", name))),
+            },
+            TokenKind::Punct('(') => {
+                let mut open = 0;
+                let close = tokens.iter().position(|token| {
+                    if token.is_punct('(') { open += 1 }
+                    if token.is_punct(')') { open -= 1 }
+                    open == 0
+                }).ok_or_else(|| token.location.error("unclosed `(`"))?;
+                Ok((self.evaluate_nested(&tokens[1..close], &token.location, depth)?, &tokens[close + 1..]))
+            },
+            _ => Err(token.location.error(format!("expected a value, found {}", token))),
+        }
+    }
+
+    /// Evaluates an expression that must fit in `min..=max`.
+    pub fn evaluate_in(&self, tokens: &[Token], at: &Location, min: i64, max: i64, what: &str) -> Result<i64, AsmError> {
+        let value = self.evaluate(tokens, at)?;
+        if value < min || value > max {
+            let location = tokens.first().map_or(at, |token| &token.location);
+            return Err(location.error(format!("{} {} out of range ({}..={:#X})", what, value, min, max)));
+        }
+        Ok(value)
+    }
+
+    pub fn byte(&self, tokens: &[Token], at: &Location) -> Result<u8, AsmError> {
+        // Negative bytes are allowed for two's complement arithmetic, e.g. `ADD V0, -1`.
+        Ok(self.evaluate_in(tokens, at, -128, 0xFF, "byte")? as u8)
+    }
+
+    /// Labels sorted by address, for a symbol map.
+    pub fn labels(&self) -> Vec<(String, u16)> {
+        let mut labels: Vec<_> = self.symbols.iter()
+            .filter_map(|(name, symbol)| match symbol {
+                Symbol::Label(addr) => Some((name.clone(), *addr)),
+                Symbol::Const(_) => None,
+            })
+            .collect();
+        labels.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+        labels
+    }
+}
+
+/// Pixels of a `sprite` row string, 8 or 16 of `#` and `.`.
+pub(crate) fn sprite_row(token: &Token) -> Result<Vec<u8>, AsmError> {
+    let row = match &token.kind {
+        TokenKind::Str(row) if row.len() == 8 || row.len() == 16 => row,
+        _ => return Err(token.location.error("expected a sprite row string of 8 or 16 `#` and `.`")),
+    };
+    let mut bytes = vec![0; row.len() / 8];
+    for (bit, pixel) in row.chars().enumerate() {
+        match pixel {
+            '#' => bytes[bit / 8] |= 0x80 >> (bit % 8),
+            '.' => {},
+            _ => return Err(token.location.error(format!("invalid sprite pixel `{}`, expected `#` or `.`", pixel))),
+        }
+    }
+    Ok(bytes)
+}
+
+/// A successfully assembled program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly {
+    /// The ROM, to be loaded at 0x200.
+    pub rom: Vec<u8>,
+    /// Every label with its address, sorted by address.
+    pub labels: Vec<(String, u16)>,
+    /// Octo `:breakpoint`s with their address.
+    pub breakpoints: Vec<(String, u16)>,
+}
+
+impl Assembly {
+    /// One `0xADDR name` line per label.
+    pub fn symbol_map(&self) -> String {
+        self.labels.iter().map(|(name, addr)| format!("0x{:04X} {}\n", addr, name)).collect()
+    }
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Vec<Token>>,
+}
+
+/// What a line assembles to, laid out at its address by the first pass.
+enum Statement {
+    Instruction { mnemonic: Token, operands: Vec<Token> },
+    Data { exprs: Vec<Token>, word: bool },
+    Sprite(Vec<Token>),
+}
+
+#[derive(Default)]
+struct Assembler {
+    // Source lines after includes and macros are expanded.
+    lines: Vec<Vec<Token>>,
+    macros: HashMap<String, Macro>,
+    aliases: HashMap<String, u8>,
+    symbols: SymbolTable,
+}
+
+/// Assembles the file at `path`.
+pub fn assemble_file(path: &Path) -> Result<Assembly, AsmError> {
+    let source = fs::read_to_string(path).map_err(|e| AsmError {
+        file: path.display().to_string(),
+        line: 0,
+        column: 0,
+        message: e.to_string(),
+    })?;
+    assemble(&source, path)
+}
+
+/// Assembles `source`, whose includes are relative to `path`.
+pub fn assemble(source: &str, path: &Path) -> Result<Assembly, AsmError> {
+    let mut assembler = Assembler::default();
+    assembler.read(source, path, 0)?;
+    let statements = assembler.layout()?;
+    let mut rom = Vec::new();
+    for (addr, statement) in &statements {
+        rom.resize(*addr - APPLICATION_MEMORY_LOCATION, 0);
+        assembler.emit(statement, &mut rom)?;
+    }
+    Ok(Assembly { rom, labels: assembler.symbols.labels(), breakpoints: Vec::new() })
+}
+
+impl Assembler {
+    /// Tokenizes a file, expanding includes and macros into `self.lines`.
+    fn read(&mut self, source: &str, path: &Path, depth: usize) -> Result<(), AsmError> {
+        let file: Rc<str> = path.display().to_string().into();
+        let mut lines = Vec::new();
+        for (index, text) in source.lines().enumerate() {
+            lines.push(tokenize(text, &file, index + 1, ';')?);
+        }
+
+        let mut lines = lines.into_iter();
+        while let Some(line) = lines.next() {
+            match directive(&line) {
+                Some("include") => {
+                    let name = match line.get(2).map(|token| &token.kind) {
+                        Some(TokenKind::Str(name)) if line.len() == 3 => name,
+                        _ => return Err(line[0].location.error("expected :include \"file\"")),
+                    };
+                    if depth >= MAX_NESTING {
+                        return Err(line[0].location.error("includes nested too deeply"));
+                    }
+                    let included = path.parent().map_or_else(|| PathBuf::from(name), |dir| dir.join(name));
+                    let source = fs::read_to_string(&included)
+                        .map_err(|e| line[2].location.error(format!("cannot read {}: {}", included.display(), e)))?;
+                    self.read(&source, &included, depth + 1)?;
+                },
+                Some("macro") => {
+                    let name = line.get(2).and_then(Token::ident)
+                        .ok_or_else(|| line[0].location.error("expected a macro name"))?;
+                    let mut params = Vec::new();
+                    for param in split_commas(&line[3..]) {
+                        match param {
+                            [token] if token.ident().is_some() => params.push(token.ident().unwrap().to_string()),
+                            _ => return Err(line[0].location.error("macro parameters must be names separated by commas")),
+                        }
+                    }
+                    let mut body = Vec::new();
+                    loop {
+                        match lines.next() {
+                            Some(body_line) if directive(&body_line) == Some("endm") => break,
+                            Some(body_line) => body.push(body_line),
+                            None => return Err(line[0].location.error("missing :endm")),
+                        }
+                    }
+                    if self.macros.insert(name.to_string(), Macro { params, body }).is_some() {
+                        return Err(line[2].location.error(format!("macro `{}` is already defined", name)));
+                    }
+                },
+                Some("endm") => return Err(line[0].location.error(":endm without :macro")),
+                _ => self.expand(line, 0)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a line to the program, replacing macro invocations by their body.
+    fn expand(&mut self, mut line: Vec<Token>, depth: usize) -> Result<(), AsmError> {
+        // A label may start the line of an invocation.
+        if line.len() > 2 && line[0].ident().is_some() && line[1].is_punct(':') {
+            let rest = line.split_off(2);
+            self.lines.push(line);
+            line = rest;
+        }
+
+        let invoked = line.first().and_then(Token::ident).filter(|name| self.macros.contains_key(*name));
+        let name = match invoked {
+            Some(name) => name.to_string(),
+            None => {
+                if !line.is_empty() {
+                    self.lines.push(line);
+                }
+                return Ok(());
+            },
+        };
+        if depth >= MAX_NESTING {
+            return Err(line[0].location.error("macros nested too deeply"));
+        }
+
+        let args = split_commas(&line[1..]);
+        let (params, body) = {
+            let invoked = &self.macros[&name];
+            (invoked.params.clone(), invoked.body.clone())
+        };
+        if args.len() != params.len() {
+            return Err(line[0].location.error(
+                format!("macro `{}` takes {} arguments, {} given", name, params.len(), args.len())));
+        }
+        for body_line in body {
+            let mut expanded = Vec::new();
+            for token in body_line {
+                match token.ident().and_then(|ident| params.iter().position(|param| param == ident)) {
+                    Some(index) => expanded.extend_from_slice(args[index]),
+                    None => expanded.push(token),
+                }
+            }
+            self.expand(expanded, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    /// First pass: defines symbols and gives every statement its address.
+    fn layout(&mut self) -> Result<Vec<(usize, Statement)>, AsmError> {
+        let mut statements = Vec::new();
+        let mut addr = APPLICATION_MEMORY_LOCATION;
+        let lines = std::mem::take(&mut self.lines);
+        for mut line in lines {
+            if line.len() >= 2 && line[0].ident().is_some() && line[1].is_punct(':') {
+                self.symbols.define(&line[0], Symbol::Label(addr as u16))?;
+                line.drain(..2);
+                if line.is_empty() {
+                    continue;
+                }
+            }
+
+            let location = line[0].location.clone();
+            let statement = match directive(&line) {
+                Some("const") => {
+                    match line.get(2) {
+                        Some(name) if name.ident().is_some() && line.len() > 3 =>
+                            self.symbols.define(name, Symbol::Const(line[3..].to_vec()))?,
+                        _ => return Err(line[0].location.error("expected :const NAME value")),
+                    }
+                    continue;
+                },
+                Some("alias") => {
+                    let register = line.get(3).and_then(Token::ident).and_then(register);
+                    match (line.get(2).and_then(Token::ident), register) {
+                        (Some(name), Some(register)) if line.len() == 4 => {
+                            if self.aliases.insert(name.to_string(), register).is_some() {
+                                return Err(line[2].location.error(format!("alias `{}` is already defined", name)));
+                            }
+                        },
+                        _ => return Err(line[0].location.error("expected :alias NAME Vx")),
+                    }
+                    continue;
+                },
+                Some(other) => return Err(line[0].location.error(format!("unknown directive `:{}`", other))),
+                None => {
+                    let first = line.remove(0);
+                    match first.ident().map(str::to_ascii_lowercase).as_deref() {
+                        Some("db") => Statement::Data { exprs: line, word: false },
+                        Some("dw") => Statement::Data { exprs: line, word: true },
+                        Some("sprite") => Statement::Sprite(line),
+                        Some(_) => Statement::Instruction { mnemonic: first, operands: line },
+                        None => return Err(first.location.error(format!("expected an instruction, found {}", first))),
+                    }
+                },
+            };
+
+            let size = match &statement {
+                Statement::Instruction { operands, .. } =>
+                    if operands.iter().any(|token| token.ident().is_some_and(|name| name.eq_ignore_ascii_case("long"))) { 4 } else { 2 },
+                Statement::Data { exprs, word } => split_commas(exprs).len() * if *word { 2 } else { 1 },
+                Statement::Sprite(rows) => rows.iter()
+                    .map(|row| match &row.kind { TokenKind::Str(text) => text.len() / 8, _ => 0 })
+                    .sum(),
+            };
+            statements.push((addr, statement));
+            addr += size;
+            if addr > MEMORY_SIZE {
+                return Err(location.error("program does not fit in memory"));
+            }
+        }
+        Ok(statements)
+    }
+
+    /// Second pass: encodes a statement.
+    fn emit(&self, statement: &Statement, rom: &mut Vec<u8>) -> Result<(), AsmError> {
+        match statement {
+            Statement::Instruction { mnemonic, operands } => {
+                let instruction = self.instruction(mnemonic, operands)?;
+                rom.extend_from_slice(&instruction.encode_bytes());
+            },
+            Statement::Data { exprs, word } => {
+                for expr in split_commas(exprs) {
+                    let at = expr.first().map_or(&exprs[0].location, |token| &token.location);
+                    if expr.is_empty() {
+                        return Err(at.error("expected a value"));
+                    }
+                    if *word {
+                        let value = self.symbols.evaluate_in(expr, at, -0x8000, 0xFFFF, "word")? as u16;
+                        rom.extend_from_slice(&value.to_be_bytes());
+                    } else {
+                        rom.push(self.symbols.byte(expr, at)?);
+                    }
+                }
+            },
+            Statement::Sprite(rows) => {
+                for row in split_commas(rows) {
+                    match row {
+                        [token] => rom.extend(sprite_row(token)?),
+                        _ => return Err(row.first().map_or(&rows[0].location, |token| &token.location)
+                                        .error("expected a sprite row string")),
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn instruction(&self, mnemonic: &Token, tokens: &[Token]) -> Result<Instruction, AsmError> {
+        let at = &mnemonic.location;
+        let operands: Vec<Operand> = split_commas(tokens).into_iter()
+            .map(|operand| self.operand(operand, at))
+            .collect::<Result<_, _>>()?;
+        let name = mnemonic.ident().unwrap().to_ascii_uppercase();
+
+        let byte = |tokens: &[Token]| self.symbols.byte(tokens, at);
+        let addr = |tokens: &[Token]| self.symbols.evaluate_in(tokens, at, 0, 0xFFF, "address").map(|addr| addr as u16);
+        let nibble = |tokens: &[Token]| self.symbols.evaluate_in(tokens, at, 0, 0xF, "nibble").map(|n| n as u8);
+
+        use Operand::*;
+        let instruction = match (name.as_str(), operands.as_slice()) {
+            ("CLS", []) => Instruction::Cls,
+            ("RET", []) => Instruction::Ret,
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::LowRes,
+            ("HIGH", []) => Instruction::HighRes,
+            ("AUDIO", []) => Instruction::Audio,
+            ("SCD", [Expr(n)]) => Instruction::ScrollDown { n: nibble(n)? },
+            ("SCU", [Expr(n)]) => Instruction::ScrollUp { n: nibble(n)? },
+            ("PLANE", [Expr(n)]) => Instruction::Plane { n: nibble(n)? },
+            ("SYS", [Expr(nnn)]) => Instruction::Sys { nnn: addr(nnn)? },
+            ("JP", [Expr(nnn)]) => Instruction::Jump { nnn: addr(nnn)? },
+            ("JP", [Reg(0), Expr(nnn)]) => Instruction::JumpV0 { nnn: addr(nnn)? },
+            ("CALL", [Expr(nnn)]) => Instruction::Call { nnn: addr(nnn)? },
+            ("SE", [Reg(x), Reg(y)]) => Instruction::SkipEqReg { x: *x, y: *y },
+            ("SE", [Reg(x), Expr(nn)]) => Instruction::SkipEqImm { x: *x, nn: byte(nn)? },
+            ("SNE", [Reg(x), Reg(y)]) => Instruction::SkipNeReg { x: *x, y: *y },
+            ("SNE", [Reg(x), Expr(nn)]) => Instruction::SkipNeImm { x: *x, nn: byte(nn)? },
+            ("SAVE", [Reg(x), Reg(y)]) => Instruction::StoreRange { x: *x, y: *y },
+            ("LOAD", [Reg(x), Reg(y)]) => Instruction::LoadRange { x: *x, y: *y },
+            ("LD", [Reg(x), Reg(y)]) => Instruction::LoadReg { x: *x, y: *y },
+            ("LD", [Reg(x), Expr(nn)]) => Instruction::LoadImm { x: *x, nn: byte(nn)? },
+            ("LD", [I, Expr(nnn)]) => Instruction::LoadI { nnn: addr(nnn)? },
+            ("LD", [I, Long(nnnn)]) => Instruction::LoadILong {
+                nnnn: self.symbols.evaluate_in(nnnn, at, 0, 0xFFFF, "address")? as u16,
+            },
+            ("LD", [Reg(x), Dt]) => Instruction::LoadDelay { x: *x },
+            ("LD", [Reg(x), K]) => Instruction::WaitKey { x: *x },
+            ("LD", [Dt, Reg(x)]) => Instruction::SetDelay { x: *x },
+            ("LD", [St, Reg(x)]) => Instruction::SetSound { x: *x },
+            ("LD", [F, Reg(x)]) => Instruction::LoadFont { x: *x },
+            ("LD", [Hf, Reg(x)]) => Instruction::LoadBigFont { x: *x },
+            ("LD", [Pitch, Reg(x)]) => Instruction::Pitch { x: *x },
+            ("LD", [B, Reg(x)]) => Instruction::StoreBcd { x: *x },
+            ("LD", [IndirectI, Reg(x)]) => Instruction::StoreRegs { x: *x },
+            ("LD", [Reg(x), IndirectI]) => Instruction::LoadRegs { x: *x },
+            ("LD", [R, Reg(x)]) => Instruction::StoreFlags { x: *x },
+            ("LD", [Reg(x), R]) => Instruction::LoadFlags { x: *x },
+            ("ADD", [Reg(x), Reg(y)]) => Instruction::AddReg { x: *x, y: *y },
+            ("ADD", [Reg(x), Expr(nn)]) => Instruction::AddImm { x: *x, nn: byte(nn)? },
+            ("ADD", [I, Reg(x)]) => Instruction::AddI { x: *x },
+            ("OR", [Reg(x), Reg(y)]) => Instruction::Or { x: *x, y: *y },
+            ("AND", [Reg(x), Reg(y)]) => Instruction::And { x: *x, y: *y },
+            ("XOR", [Reg(x), Reg(y)]) => Instruction::Xor { x: *x, y: *y },
+            ("SUB", [Reg(x), Reg(y)]) => Instruction::Sub { x: *x, y: *y },
+            ("SUBN", [Reg(x), Reg(y)]) => Instruction::SubN { x: *x, y: *y },
+            ("SHR", [Reg(x)]) => Instruction::ShiftRight { x: *x, y: *x },
+            ("SHR", [Reg(x), Reg(y)]) => Instruction::ShiftRight { x: *x, y: *y },
+            ("SHL", [Reg(x)]) => Instruction::ShiftLeft { x: *x, y: *x },
+            ("SHL", [Reg(x), Reg(y)]) => Instruction::ShiftLeft { x: *x, y: *y },
+            ("RND", [Reg(x), Expr(nn)]) => Instruction::Rand { x: *x, nn: byte(nn)? },
+            ("DRW", [Reg(x), Reg(y), Expr(n)]) => Instruction::Draw { x: *x, y: *y, n: nibble(n)? },
+            ("SKP", [Reg(x)]) => Instruction::SkipKey { x: *x },
+            ("SKNP", [Reg(x)]) => Instruction::SkipNotKey { x: *x },
+            _ if MNEMONICS.contains(&name.as_str()) =>
+                return Err(at.error(format!("invalid operands for {}", name))),
+            _ => return Err(at.error(format!("unknown instruction `{}`", mnemonic.ident().unwrap()))),
+        };
+        Ok(instruction)
+    }
+
+    fn operand<'t>(&self, tokens: &'t [Token], at: &Location) -> Result<Operand<'t>, AsmError> {
+        let keyword = match tokens {
+            [] => return Err(at.error("missing operand")),
+            [open, i, close] if open.is_punct('[') && close.is_punct(']')
+                && i.ident().is_some_and(|name| name.eq_ignore_ascii_case("i")) => return Ok(Operand::IndirectI),
+            [long, rest @ ..] if long.ident().is_some_and(|name| name.eq_ignore_ascii_case("long")) =>
+                return Ok(Operand::Long(rest)),
+            [token] => match token.ident() {
+                Some(name) => name,
+                None => return Ok(Operand::Expr(tokens)),
+            },
+            _ => return Ok(Operand::Expr(tokens)),
+        };
+
+        if let Some(register) = self.aliases.get(keyword).copied().or_else(|| register(keyword)) {
+            return Ok(Operand::Reg(register));
+        }
+        Ok(match keyword.to_ascii_uppercase().as_str() {
+            "I" => Operand::I,
+            "DT" => Operand::Dt,
+            "ST" => Operand::St,
+            "K" => Operand::K,
+            "F" => Operand::F,
+            "HF" => Operand::Hf,
+            "B" => Operand::B,
+            "R" => Operand::R,
+            "PITCH" => Operand::Pitch,
+            _ => Operand::Expr(tokens),
+        })
+    }
+}
+
+/// Mnemonics known to `Assembler::instruction`, to tell bad operands from typos.
+const MNEMONICS: &[&str] = &[
+    "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "AUDIO", "SCD", "SCU", "PLANE", "SYS", "JP", "CALL",
+    "SE", "SNE", "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL", "RND", "DRW",
+    "SKP", "SKNP",
+];
+
+enum Operand<'t> {
+    Reg(u8),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Pitch,
+    Long(&'t [Token]),
+    Expr(&'t [Token]),
+}
+
+/// The name of a `:name` directive line, without its colon.
+fn directive(line: &[Token]) -> Option<&str> {
+    match line {
+        [colon, name, ..] if colon.is_punct(':') => name.ident(),
+        _ => None,
+    }
+}
+
+/// Register number of `V0`..`VF`.
+pub(crate) fn register(name: &str) -> Option<u8> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => digit.to_digit(16).map(|x| x as u8),
+        _ => None,
+    }
+}
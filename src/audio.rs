@@ -0,0 +1,166 @@
+//! Sound of the CHIP-8 buzzer, as 16-bit samples for any audio backend.
+//!
+//! The buzzer sounds while the sound timer is non-zero. Frontends check
+//! `Chip::is_sounding` once per 60 Hz frame and start or stop a looping
+//! `Tone::loop_samples` buffer accordingly, or render every frame with an
+//! `AudioCapture`.
+
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::chip::{Chip, AUDIO_PATTERN_SIZE, FRAMES_PER_SECOND};
+
+pub const DEFAULT_FREQUENCY: u32 = 440;
+pub const DEFAULT_VOLUME: f64 = 0.25;
+
+// XO-CHIP plays its audio pattern at 4000 bits per second at the default
+// pitch of 64, one octave higher every 48 steps above it.
+const PATTERN_BASE_RATE: f64 = 4000.0;
+const PATTERN_BASE_PITCH: f64 = 64.0;
+const PATTERN_STEPS_PER_OCTAVE: f64 = 48.0;
+const PATTERN_BITS: f64 = (AUDIO_PATTERN_SIZE * 8) as f64;
+
+/// Shape of one period of the tone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+pub const WAVEFORM_NAMES: [&str; 4] = ["square", "triangle", "sawtooth", "sine"];
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Waveform, String> {
+        match name {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" => Ok(Waveform::Sawtooth),
+            "sine" => Ok(Waveform::Sine),
+            _ => Err(format!("unknown waveform `{}`", name)),
+        }
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(WAVEFORM_NAMES[*self as usize])
+    }
+}
+
+/// The note played by the buzzer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    /// In Hz.
+    pub frequency: u32,
+    /// From 0 (silent) to 1 (full scale).
+    pub volume: f64,
+    pub waveform: Waveform,
+}
+
+impl Default for Tone {
+    fn default() -> Tone {
+        Tone { frequency: DEFAULT_FREQUENCY, volume: DEFAULT_VOLUME, waveform: Waveform::Square }
+    }
+}
+
+impl Tone {
+    /// Sample at `phase`, in periods since the tone started.
+    pub fn sample(&self, phase: f64) -> i16 {
+        let phase = phase.fract();
+        let level = match self.waveform {
+            Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+        };
+        (level * self.volume.clamp(0.0, 1.0) * i16::MAX as f64).round() as i16
+    }
+
+    /// One second of the tone, a whole number of periods that loops without
+    /// clicks.
+    pub fn loop_samples(&self, sample_rate: u32) -> Vec<i16> {
+        (0..sample_rate as u64)
+            .map(|n| self.sample((n * self.frequency as u64) as f64 / sample_rate as f64))
+            .collect()
+    }
+}
+
+/// Renders the sound of an emulated session, frame by frame.
+///
+/// Each 60 Hz frame gets exactly its share of samples, so the audio stays in
+/// step with the frames however long the session. While the buzzer sounds,
+/// the XO-CHIP audio pattern is played at its pitch once a ROM has loaded a
+/// non-silent one, and the tone otherwise.
+pub struct AudioCapture {
+    tone: Tone,
+    sample_rate: u32,
+    frames: u64,
+    // Position in the audio pattern, in bits.
+    pattern_position: f64,
+    samples: Vec<i16>,
+}
+
+impl AudioCapture {
+    pub fn new(tone: Tone, sample_rate: u32) -> AudioCapture {
+        AudioCapture { tone, sample_rate, frames: 0, pattern_position: 0.0, samples: Vec::new() }
+    }
+
+    /// Renders the frame `chip` just ran.
+    pub fn capture_frame(&mut self, chip: &Chip) {
+        let rate = self.sample_rate as u64;
+        let start = self.frames * rate / FRAMES_PER_SECOND as u64;
+        let end = (self.frames + 1) * rate / FRAMES_PER_SECOND as u64;
+        self.frames += 1;
+
+        let pattern = chip.audio_pattern();
+        let use_pattern = pattern.iter().any(|byte| *byte != 0);
+        let bits_per_sample = PATTERN_BASE_RATE
+            * 2f64.powf((chip.pitch() as f64 - PATTERN_BASE_PITCH) / PATTERN_STEPS_PER_OCTAVE)
+            / self.sample_rate as f64;
+        let level = (self.tone.volume.clamp(0.0, 1.0) * i16::MAX as f64).round() as i16;
+
+        for n in start..end {
+            let sample = if !chip.is_sounding() {
+                0
+            } else if use_pattern {
+                let bit = self.pattern_position as usize;
+                self.pattern_position = (self.pattern_position + bits_per_sample) % PATTERN_BITS;
+                if pattern[bit / 8] >> (7 - bit % 8) & 1 != 0 { level } else { -level }
+            } else {
+                self.tone.sample((n * self.tone.frequency as u64) as f64 / self.sample_rate as f64)
+            };
+            self.samples.push(sample);
+        }
+    }
+
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    /// The captured samples as a mono 16-bit PCM `.wav` file.
+    pub fn to_wav(&self) -> Vec<u8> {
+        let data_size = self.samples.len() as u32 * 2;
+        let mut wav = Vec::with_capacity(44 + data_size as usize);
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());     // PCM
+        wav.extend_from_slice(&1u16.to_le_bytes());     // Mono
+        wav.extend_from_slice(&self.sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(self.sample_rate * 2).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());     // Bytes per sample
+        wav.extend_from_slice(&16u16.to_le_bytes());    // Bits per sample
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_size.to_le_bytes());
+        for sample in &self.samples {
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+        wav
+    }
+}
@@ -0,0 +1,42 @@
+use sfml::audio::{Sound, SoundBuffer, SoundStatus};
+
+use chip8::audio::Tone;
+
+const SAMPLE_RATE: u32 = 44100;
+
+/// The samples of a tone, loaded for SFML. `Buzzer` borrows them.
+pub fn load(tone: &Tone) -> Option<SoundBuffer> {
+    SoundBuffer::from_samples(&tone.loop_samples(SAMPLE_RATE), 1, SAMPLE_RATE)
+}
+
+/// Plays the tone while the sound timer runs.
+pub struct Buzzer<'a> {
+    sound: Sound<'a>,
+    muted: bool,
+}
+
+impl<'a> Buzzer<'a> {
+    pub fn new(buffer: &'a SoundBuffer) -> Buzzer<'a> {
+        let mut sound = Sound::with_buffer(buffer);
+        sound.set_looping(true);
+        Buzzer { sound, muted: false }
+    }
+
+    /// Starts or stops the tone to follow `Chip::is_sounding`; call it once per frame.
+    pub fn update(&mut self, sounding: bool) {
+        let playing = self.sound.status() == SoundStatus::Playing;
+        let sounding = sounding && !self.muted;
+        if sounding && !playing {
+            self.sound.play();
+        } else if !sounding && playing {
+            // Pausing rather than stopping resumes the wave where it was, without a click.
+            self.sound.pause();
+        }
+    }
+
+    /// Mutes or unmutes, returning whether it is now muted.
+    pub fn toggle_mute(&mut self) -> bool {
+        self.muted = !self.muted;
+        self.muted
+    }
+}
@@ -1,17 +1,32 @@
+use std::fs;
 use std::io;
-use std::io::prelude::*;
-use std::fs::File;
+use std::mem;
+use std::path::Path;
 
-use super::opcodes::*;
+use crate::error::ChipError;
+use crate::opcodes::Instruction;
+use crate::quirks::Quirks;
+use crate::rng::{RandomSource, XorShiftRng};
+use crate::savestate::{self, SaveStateError, StateReader, StateWriter};
+use crate::trace::{self, event, Step, Tracer};
 
-const MEMORY_SIZE: usize = 4096;
+pub const MEMORY_SIZE: usize = 65536;
 const NUMBER_OF_REGISTERS: usize = 16;
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
 const STACK_SIZE: usize = 16;
-const KEYBOARD_SIZE: usize = 16;
+pub const KEYBOARD_SIZE: usize = 16;
+const RPL_FLAGS_SIZE: usize = 16;
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+const DEFAULT_PITCH: u8 = 64;
+const DEFAULT_PLANES: u8 = 0b01;
 
-const APPLICATION_MEMORY_LOCATION: usize = 0x200;
+/// Rate of the delay and sound timers, and of the vertical blank.
+pub const FRAMES_PER_SECOND: u32 = 60;
+
+pub const APPLICATION_MEMORY_LOCATION: usize = 0x200;
 const FONTSET_ELEMENT_SIZE: usize = 5;
 const FONTSET_ELEMENT_NUMBERS: usize = 16;
 const FONTSET: [u8; FONTSET_ELEMENT_SIZE * FONTSET_ELEMENT_NUMBERS] = [
@@ -33,443 +48,910 @@ const FONTSET: [u8; FONTSET_ELEMENT_SIZE * FONTSET_ELEMENT_NUMBERS] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+const BIG_FONTSET_LOCATION: usize = FONTSET_ELEMENT_SIZE * FONTSET_ELEMENT_NUMBERS;
+const BIG_FONTSET_ELEMENT_SIZE: usize = 10;
+const BIG_FONTSET: [u8; BIG_FONTSET_ELEMENT_SIZE * FONTSET_ELEMENT_NUMBERS] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// What a successfully emulated cycle did, beyond advancing the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// An instruction was executed.
+    Executed,
+    /// A sprite was drawn; the framebuffer changed.
+    Draw,
+    /// The screen was cleared.
+    Clear,
+    /// The program is blocked on FX0A until a key is pressed.
+    WaitForKey,
+    /// The program is blocked on DXYN until the next vertical blank (see `Quirks::display_wait`).
+    WaitForVBlank,
+    /// The program asked the interpreter to exit (00FD).
+    Exit,
+}
+
+/// Memory read and written by the last instruction, as half-open
+/// `(start, end)` address ranges. Fetching the instruction itself is not
+/// counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub read: Option<(usize, usize)>,
+    pub written: Option<(usize, usize)>,
+}
+
+impl MemoryAccess {
+    fn extend(range: Option<(usize, usize)>, addr: usize) -> Option<(usize, usize)> {
+        Some(match range {
+            Some((start, end)) => (start.min(addr), end.max(addr + 1)),
+            None => (addr, addr + 1),
+        })
+    }
+}
+
 pub struct Chip {
     opcode: u16,                                        // Current opcode
     memory: [u8; MEMORY_SIZE],                          // Memory layout
     v: [u8; NUMBER_OF_REGISTERS],                       // Registers
     i: u32,                                             // Index register
     pc: usize,                                          // Program counter
-    pub graphics: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],   // Screen display
+    graphics: Vec<u8>,                                  // Screen display, width() * height() pixels
+    hires: bool,                                        // SUPER-CHIP 128x64 mode
+    plane: u8,                                          // XO-CHIP bitplanes selected for drawing
     delay_timer: u32,                                   // Count down delay timer
     sound_timer: u32,                                   // Count down sound timer
     stack: [usize; STACK_SIZE],                         // Memory stack
     sp: usize,                                          // Stack pointer
     key: [bool; KEYBOARD_SIZE],                         // Keyboard state
+    rpl: [u8; RPL_FLAGS_SIZE],                          // SUPER-CHIP RPL user flags
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],            // XO-CHIP 1-bit audio pattern
+    pitch: u8,                                          // XO-CHIP audio pattern pitch
     pub draw_flag: u16,                                 // Draw flag
     pub exit_flag: u16,                                 // Exit flag
     pub clear_flag: u16,                                // Clear screen flag
     pub input_flag: u16,                                // Wait for input
+    vblank_ready: bool,                                 // A vertical blank happened since the last draw
+    sounding: bool,                                     // The buzzer sounded at the last timer tick
+    quirks: Quirks,                                     // Interpretation of ambiguous opcodes
+    rng: Box<dyn RandomSource>,                         // Random numbers for CXNN
+    rom_hash: u64,                                      // Identifies the loaded ROM in save states
+    rom: Vec<u8>,                                       // Loaded ROM, for reset
+    access: MemoryAccess,                               // Memory touched by the last instruction
+    tracer: Option<Box<dyn Tracer>>,                    // Receives every executed instruction
 }
 
 impl Chip {
-    pub fn new() -> Chip {
+    pub fn new(quirks: Quirks) -> Chip {
         let mut chip = Chip {
             opcode: 0,
             memory: [0; MEMORY_SIZE],
             v: [0; NUMBER_OF_REGISTERS],
             i: 0,
             pc: APPLICATION_MEMORY_LOCATION,
-            graphics: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            graphics: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            hires: false,
+            plane: DEFAULT_PLANES,
             delay_timer: 0,
             sound_timer: 0,
             stack: [0; STACK_SIZE],
             sp: 0,
             key: [false; KEYBOARD_SIZE],
+            rpl: [0; RPL_FLAGS_SIZE],
+            audio_pattern: [0; AUDIO_PATTERN_SIZE],
+            pitch: DEFAULT_PITCH,
             draw_flag: 0,
             exit_flag: 0,
             clear_flag: 0,
             input_flag: 0x10,
+            vblank_ready: true,
+            sounding: false,
+            quirks,
+            rng: Box::new(XorShiftRng::new(rand::random())),
+            rom_hash: savestate::fnv1a(&[]),
+            rom: Vec::new(),
+            access: MemoryAccess::default(),
+            tracer: None,
         };
 
-        for iterator in 0..80 {
-            chip.memory[iterator] = FONTSET[iterator];
-        }
+        chip.memory[..FONTSET.len()].copy_from_slice(&FONTSET);
+        chip.memory[BIG_FONTSET_LOCATION..BIG_FONTSET_LOCATION + BIG_FONTSET.len()]
+            .copy_from_slice(&BIG_FONTSET);
 
-        return chip;
+        chip
     }
 
-    pub fn load_game(&mut self, path: &std::string::String) -> Result<(), io::Error> {
+    pub fn load_game<P: AsRef<Path>>(&mut self, path: P) -> Result<(), io::Error> {
         // Copy the file at 'path' to the 0x200.. memory space
-        println!("Loading game: {}", path);
-        let mut buffer = vec![0; MEMORY_SIZE - APPLICATION_MEMORY_LOCATION as usize];
-        let mut f = File::open(path)?;
-        f.read(&mut buffer)?;
-        self.memory[(APPLICATION_MEMORY_LOCATION as usize)..].clone_from_slice(&buffer);
-        println!("Game {} properly loaded.", path);
+        let rom = fs::read(path)?;
+        self.load_rom(&rom)
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), io::Error> {
+        if rom.len() > MEMORY_SIZE - APPLICATION_MEMORY_LOCATION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("ROM is too large: {} bytes (at most {} bytes fit in memory)",
+                        rom.len(), MEMORY_SIZE - APPLICATION_MEMORY_LOCATION)));
+        }
+
+        let end = APPLICATION_MEMORY_LOCATION + rom.len();
+        self.memory[APPLICATION_MEMORY_LOCATION..end].copy_from_slice(rom);
+        self.memory[end..].iter_mut().for_each(|byte| *byte = 0);
+        self.rom_hash = savestate::fnv1a(rom);
+        self.rom = rom.to_vec();
         Ok(())
     }
 
-    pub fn emulate_cycle(&mut self) {
-        // Fetch opcode
-        self.opcode = (self.memory[self.pc] as u16) << 8 | self.memory[self.pc + 1] as u16;
-        if self.opcode & 0xF0FF != 0xF00A {
-            print!("[{:0>4x?}] INSTRUCTION: {:0>4x?}: ", self.pc, self.opcode);
+    /// Restarts the loaded ROM from power-on, keeping the quirks and the
+    /// random number generator.
+    pub fn reset(&mut self) {
+        let mut fresh = Chip::new(self.quirks);
+        mem::swap(&mut fresh.rng, &mut self.rng);
+        mem::swap(&mut fresh.tracer, &mut self.tracer);
+        let rom = mem::take(&mut self.rom);
+        *self = fresh;
+        self.load_rom(&rom).expect("the ROM fitted in memory when it was first loaded");
+    }
+
+    /// FNV-1a hash of the loaded ROM.
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    /// Serializes the complete machine state, random number generator included.
+    ///
+    /// The state is tied to the loaded ROM and to the current save state
+    /// `savestate::VERSION`; `load_state` refuses anything else.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.put_bytes(savestate::MAGIC);
+        writer.put_u16(savestate::VERSION);
+        writer.put_u64(self.rom_hash);
+
+        writer.put_u16(self.opcode);
+        writer.put_bytes(&self.memory);
+        writer.put_bytes(&self.v);
+        writer.put_u32(self.i);
+        writer.put_u32(self.pc as u32);
+        writer.put_u8(self.hires as u8);
+        writer.put_bytes(&self.graphics);
+        writer.put_u8(self.plane);
+        writer.put_u32(self.delay_timer);
+        writer.put_u32(self.sound_timer);
+        for address in self.stack.iter() {
+            writer.put_u32(*address as u32);
         }
-        
-        // Decode opcode
-        match self.opcode & 0xF000 {
-            // 0x0--- family: Misceallenous
-            // - 0x0NNN: Call RCA 1802 program at address NNN
-            // - 0x00E0: Clear the screen
-            // - 0x00EE: Return from subroutine
-            FAMILY_MISCEALLENOUS => {
-                match self.opcode & 0x0FFF {
-                    OPCODE_CLEAR_SCREEN => {
-                        println!("CLEARING SCREEN");
-                        self.graphics = [0; SCREEN_WIDTH * SCREEN_HEIGHT];
-                        self.clear_flag = 1;
-                        self.pc += 2;
-                    },
-                    OPCODE_RETURN_FROM_SUBROUTINE => {
-                        println!("RETURNING FROM SUBROUTINE TO {:0>4x?}", self.stack[self.sp - 1]);
-                        self.pc = self.stack[self.sp];
-                        self.sp -= 1;
-                    },
-                    _      => {
-                        panic!("RCA 1802 calls are not supported");
-                        self.pc += 2;
-                    }
+        writer.put_u8(self.sp as u8);
+        writer.put_u16(self.key.iter().enumerate().fold(0, |keys, (index, pressed)| keys | (*pressed as u16) << index));
+        writer.put_bytes(&self.rpl);
+        writer.put_bytes(&self.audio_pattern);
+        writer.put_u8(self.pitch);
+        writer.put_u16(self.draw_flag);
+        writer.put_u16(self.exit_flag);
+        writer.put_u16(self.clear_flag);
+        writer.put_u16(self.input_flag);
+        writer.put_u8(self.vblank_ready as u8);
+        let rng_state = self.rng.save_state();
+        writer.put_u16(rng_state.len() as u16);
+        writer.put_bytes(&rng_state);
+
+        writer.into_bytes()
+    }
+
+    /// Restores a state produced by `save_state`. On error the chip is left untouched.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = StateReader::new(state);
+        if reader.bytes(savestate::MAGIC.len()).ok() != Some(&savestate::MAGIC[..]) {
+            return Err(SaveStateError::NotASaveState);
+        }
+        let version = reader.u16()?;
+        if version != savestate::VERSION {
+            return Err(SaveStateError::UnsupportedVersion { found: version, expected: savestate::VERSION });
+        }
+        let rom_hash = reader.u64()?;
+        if rom_hash != self.rom_hash {
+            return Err(SaveStateError::RomMismatch { expected: self.rom_hash, found: rom_hash });
+        }
+
+        let opcode = reader.u16()?;
+        let memory = reader.bytes(MEMORY_SIZE)?;
+        let v = reader.bytes(NUMBER_OF_REGISTERS)?;
+        let i = reader.u32()?;
+        let pc = reader.u32()? as usize;
+        let hires = match reader.u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(SaveStateError::Corrupted("resolution")),
+        };
+        let graphics = if hires {
+            reader.bytes(HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT)?
+        } else {
+            reader.bytes(SCREEN_WIDTH * SCREEN_HEIGHT)?
+        };
+        let plane = reader.u8()?;
+        let delay_timer = reader.u32()?;
+        let sound_timer = reader.u32()?;
+        let mut stack = [0; STACK_SIZE];
+        for address in stack.iter_mut() {
+            *address = reader.u32()? as usize;
+        }
+        let sp = reader.u8()? as usize;
+        if sp > STACK_SIZE {
+            return Err(SaveStateError::Corrupted("stack pointer"));
+        }
+        let keys = reader.u16()?;
+        let rpl = reader.bytes(RPL_FLAGS_SIZE)?;
+        let audio_pattern = reader.bytes(AUDIO_PATTERN_SIZE)?;
+        let pitch = reader.u8()?;
+        let draw_flag = reader.u16()?;
+        let exit_flag = reader.u16()?;
+        let clear_flag = reader.u16()?;
+        let input_flag = reader.u16()?;
+        let vblank_ready = reader.u8()? != 0;
+        let rng_state_length = reader.u16()? as usize;
+        let rng_state = reader.bytes(rng_state_length)?;
+        if !reader.is_empty() {
+            return Err(SaveStateError::Corrupted("length"));
+        }
+        self.rng.load_state(rng_state)?;
+
+        self.opcode = opcode;
+        self.memory.copy_from_slice(memory);
+        self.v.copy_from_slice(v);
+        self.i = i;
+        self.pc = pc;
+        self.hires = hires;
+        self.graphics = graphics.to_vec();
+        self.plane = plane;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.sounding = sound_timer > 0;
+        self.stack = stack;
+        self.sp = sp;
+        for (index, pressed) in self.key.iter_mut().enumerate() {
+            *pressed = keys >> index & 1 != 0;
+        }
+        self.rpl.copy_from_slice(rpl);
+        self.audio_pattern.copy_from_slice(audio_pattern);
+        self.pitch = pitch;
+        self.draw_flag = draw_flag;
+        self.exit_flag = exit_flag;
+        self.clear_flag = clear_flag;
+        self.input_flag = input_flag;
+        self.vblank_ready = vblank_ready;
+        Ok(())
+    }
+
+    pub fn registers(&self) -> &[u8; NUMBER_OF_REGISTERS] {
+        &self.v
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn i(&self) -> u32 {
+        self.i
+    }
+
+    pub fn opcode(&self) -> u16 {
+        self.opcode
+    }
+
+    pub fn stack(&self) -> &[usize] {
+        &self.stack
+    }
+
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    pub fn delay_timer(&self) -> u32 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u32 {
+        self.sound_timer
+    }
+
+    /// Sets register VX.
+    ///
+    /// Panics if `x` is not a register, i.e. 16 or more.
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.v[x] = value;
+    }
+
+    pub fn set_i(&mut self, value: u32) {
+        self.i = value;
+    }
+
+    pub fn set_pc(&mut self, value: usize) {
+        self.pc = value;
+    }
+
+    /// Sets the stack pointer, i.e. the number of return addresses on the stack.
+    ///
+    /// Panics if `value` is larger than the stack.
+    pub fn set_sp(&mut self, value: usize) {
+        assert!(value <= STACK_SIZE, "stack pointer {} out of range", value);
+        self.sp = value;
+    }
+
+    /// Sets the return address at `index` of the stack, from the bottom.
+    ///
+    /// Panics if `index` is past the end of the stack.
+    pub fn set_stack_entry(&mut self, index: usize, address: usize) {
+        self.stack[index] = address;
+    }
+
+    pub fn set_delay_timer(&mut self, value: u32) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u32) {
+        self.sound_timer = value;
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Overwrites memory from `addr`, failing without writing anything if
+    /// `bytes` do not all fit in the address space.
+    pub fn set_memory(&mut self, addr: usize, bytes: &[u8]) -> Result<(), ChipError> {
+        match self.memory.get_mut(addr..addr.saturating_add(bytes.len())) {
+            Some(memory) => { memory.copy_from_slice(bytes); Ok(()) },
+            None => Err(ChipError::MemoryOutOfBounds { pc: self.pc, addr: addr.max(self.memory.len()) }),
+        }
+    }
+
+    /// The framebuffer, one byte per pixel in row-major order (`width() * height()` bytes).
+    ///
+    /// Each pixel is a bitmask of the XO-CHIP planes it is lit in: bit 0 for
+    /// the first plane, bit 1 for the second, so a pixel is one of four colours.
+    pub fn graphics(&self) -> &[u8] {
+        &self.graphics
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_SCREEN_WIDTH } else { SCREEN_WIDTH }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_SCREEN_HEIGHT } else { SCREEN_HEIGHT }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    pub fn rpl_flags(&self) -> &[u8] {
+        &self.rpl
+    }
+
+    pub fn planes(&self) -> u8 {
+        self.plane
+    }
+
+    pub fn audio_pattern(&self) -> &[u8; AUDIO_PATTERN_SIZE] {
+        &self.audio_pattern
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// Whether the buzzer sounded during the last frame, i.e. the sound timer
+    /// was non-zero when `tick_timers` last ran.
+    pub fn is_sounding(&self) -> bool {
+        self.sounding
+    }
+
+    pub fn keys(&self) -> &[bool; KEYBOARD_SIZE] {
+        &self.key
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    /// Replaces the random number source used by CXNN. `Chip::new` seeds an
+    /// `XorShiftRng` from the system; set one with a fixed seed for
+    /// reproducible runs.
+    pub fn set_rng(&mut self, rng: Box<dyn RandomSource>) {
+        self.rng = rng;
+    }
+
+    pub fn rng(&self) -> &dyn RandomSource {
+        self.rng.as_ref()
+    }
+
+    /// Hands every instruction executed from now on to `tracer`, see
+    /// `trace` for the format. Tracing costs nothing while no tracer is set.
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer>) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Stops tracing, giving the tracer back so it can be finished.
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.take()
+    }
+
+    fn trace_registers(&self) -> trace::Registers {
+        trace::Registers {
+            v: self.v,
+            i: self.i,
+            sp: self.sp,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+        }
+    }
+
+    /// Advances the 60 Hz delay and sound timers by one tick.
+    ///
+    /// This is also the vertical blank: frontends must call it exactly 60
+    /// times per second, independently of how many instructions they run.
+    pub fn tick_timers(&mut self) {
+        self.vblank_ready = true;
+        self.sounding = self.sound_timer > 0;
+
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+
+        if self.sound_timer > 0 {
+            if self.sound_timer == 1 {
+                event!(Timers, Debug, "sound timer expired");
+            }
+            self.sound_timer -= 1;
+        }
+        event!(Timers, Trace, "tick: dt={} st={}", self.delay_timer, self.sound_timer);
+    }
+
+    /// Emulates one 60 Hz frame: up to `cycles_per_frame` instructions, then a
+    /// timer tick.
+    ///
+    /// The frame ends early when the program exits or waits for the vertical
+    /// blank, since no further instruction can make progress before the tick.
+    pub fn run_frame(&mut self, cycles_per_frame: usize) -> Result<(), ChipError> {
+        for _ in 0..cycles_per_frame {
+            match self.emulate_cycle()? {
+                StepOutcome::Exit => return Ok(()),
+                StepOutcome::WaitForVBlank => break,
+                _ => {}
+            }
+        }
+
+        self.tick_timers();
+        Ok(())
+    }
+
+    fn memory_byte(&self, addr: usize) -> Result<u8, ChipError> {
+        match self.memory.get(addr) {
+            Some(byte) => Ok(*byte),
+            None => Err(ChipError::MemoryOutOfBounds { pc: self.pc, addr }),
+        }
+    }
+
+    /// Reads data for the current instruction, recording it in `last_access`.
+    fn read_memory(&mut self, addr: usize) -> Result<u8, ChipError> {
+        let byte = self.memory_byte(addr)?;
+        self.access.read = MemoryAccess::extend(self.access.read, addr);
+        Ok(byte)
+    }
+
+    fn write_memory(&mut self, addr: usize, value: u8) -> Result<(), ChipError> {
+        let pc = self.pc;
+        match self.memory.get_mut(addr) {
+            Some(byte) => { *byte = value; },
+            None => return Err(ChipError::MemoryOutOfBounds { pc, addr }),
+        }
+        self.access.written = MemoryAccess::extend(self.access.written, addr);
+        Ok(())
+    }
+
+    /// Memory read and written by the last `emulate_cycle`, for watchpoints.
+    pub fn last_access(&self) -> MemoryAccess {
+        self.access
+    }
+
+    /// Fetches and decodes the opcode at `pc`.
+    fn fetch(&self) -> Result<(u16, Instruction), ChipError> {
+        let opcode = (self.memory_byte(self.pc)? as u16) << 8 | self.memory_byte(self.pc + 1)? as u16;
+        let mut instruction = Instruction::decode(opcode)
+            .map_err(|e| ChipError::UnknownOpcode { pc: self.pc, opcode: e.opcode })?;
+        if let Instruction::LoadILong { .. } = instruction {
+            let nnnn = (self.memory_byte(self.pc + 2)? as u16) << 8 | self.memory_byte(self.pc + 3)? as u16;
+            instruction = Instruction::LoadILong { nnnn };
+        }
+        Ok((opcode, instruction))
+    }
+
+    /// The instruction `emulate_cycle` will execute next, without executing it.
+    pub fn current_instruction(&self) -> Result<Instruction, ChipError> {
+        self.fetch().map(|(_, instruction)| instruction)
+    }
+
+    /// Fetches, decodes and executes a single instruction. Timers are left
+    /// untouched: they only move on `tick_timers`.
+    pub fn emulate_cycle(&mut self) -> Result<StepOutcome, ChipError> {
+        let (opcode, instruction) = self.fetch()?;
+        self.opcode = opcode;
+        self.access = MemoryAccess::default();
+        // FX0A runs again every cycle until a key comes, only its first run is reported.
+        let repeated = matches!(instruction, Instruction::WaitKey { .. }) && self.input_flag <= 0xf;
+        if !repeated {
+            event!(Cpu, Trace, "[{:04X}] {:04X}: {}", self.pc, self.opcode, instruction);
+        }
+        let before = self.tracer.as_ref().map(|_| self.trace_registers());
+        let pc = self.pc;
+
+        // Execute opcode
+        let outcome = self.execute(instruction)?;
+
+        if let Some(before) = before {
+            if !repeated && outcome != StepOutcome::WaitForVBlank {
+                let step = Step { pc, opcode: self.opcode, before, after: self.trace_registers() };
+                if let Some(tracer) = &mut self.tracer {
+                    tracer.step(&step);
                 }
+            }
+        }
+        Ok(outcome)
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> Result<StepOutcome, ChipError> {
+        let mut outcome = StepOutcome::Executed;
+        let mut next_pc = self.pc + instruction.size();
+
+        match instruction {
+            Instruction::Sys { .. } => {
+                return Err(ChipError::UnsupportedOpcode { pc: self.pc, opcode: self.opcode });
             },
-            // 0x1NNN: Jump to NNN
-            OPCODE_JMP => {
-                println!("JUMP TO {:0>4x?}", self.opcode & 0x0FFF);
-                self.pc = (self.opcode & 0x0FFF).into();
+            Instruction::ScrollDown { n } => {
+                self.scroll(0, n as isize);
+                self.draw_flag = 1;
+                outcome = StepOutcome::Draw;
             },
-            // 0x2NNN: Call subroutine at NNN
-            OPCODE_CALL_SUBROUTINE => {
-                println!("CALL SUBROUTINE AT {:0>4x?}", self.opcode & 0xFFF);
-                self.sp += 1;
-                self.stack[self.sp] = self.pc + 2;
-                self.pc = (self.opcode & 0x0FFF).into();
-            },
-            // 0x3XNN: Skip the next instruction if VX equals NN
-            OPCODE_SKIP_IF_EQ_NN => {
-                let x = (self.opcode as usize & 0x0F00) >> 8;
-                let vx = self.v[x];
-                let nn = self.opcode as u8 & 0x00FF;
-                println!("SKIP NEXT INSTRUCTION IF V{:x?} ({:x?}) == {:x?}", x, vx, nn);
-                if vx == nn {
-                    self.pc += 4;
-                } else {
-                    self.pc += 2;
+            Instruction::ScrollUp { n } => {
+                self.scroll(0, -(n as isize));
+                self.draw_flag = 1;
+                outcome = StepOutcome::Draw;
+            },
+            Instruction::Cls => {
+                let plane = self.plane;
+                self.graphics.iter_mut().for_each(|pixel| *pixel &= !plane);
+                event!(Render, Debug, "clear planes {:02b}", plane);
+                self.clear_flag = 1;
+                outcome = StepOutcome::Clear;
+            },
+            Instruction::Ret => {
+                if self.sp == 0 {
+                    return Err(ChipError::StackUnderflow { pc: self.pc });
                 }
+                self.sp -= 1;
+                next_pc = self.stack[self.sp];
+            },
+            Instruction::ScrollRight => {
+                self.scroll(4, 0);
+                self.draw_flag = 1;
+                outcome = StepOutcome::Draw;
             },
-            // 0x4XNN: Skip the next instruction if VX doesn't equal NN
-            OPCODE_SKIP_IF_NEQ_NN => {
-                let x  = (self.opcode as usize & 0x0F00) >> 8;
-                let vx = self.v[x];
-                let nn = self.opcode as u8 & 0x00FF;
-                println!("SKIP NEXT INSTRUCTION IF V{:x?} ({:x?}) != {:x?}", x, vx, nn);
-                if vx != nn {
-                    self.pc += 4;
-                } else {
-                    self.pc += 2;
+            Instruction::ScrollLeft => {
+                self.scroll(-4, 0);
+                self.draw_flag = 1;
+                outcome = StepOutcome::Draw;
+            },
+            Instruction::Exit => {
+                self.exit_flag = 1;
+                return Ok(StepOutcome::Exit);
+            },
+            Instruction::LowRes => {
+                self.set_hires(false);
+                outcome = StepOutcome::Draw;
+            },
+            Instruction::HighRes => {
+                self.set_hires(true);
+                outcome = StepOutcome::Draw;
+            },
+            Instruction::Jump { nnn } => {
+                next_pc = nnn as usize;
+            },
+            Instruction::Call { nnn } => {
+                if self.sp >= STACK_SIZE {
+                    return Err(ChipError::StackOverflow { pc: self.pc });
                 }
+                self.stack[self.sp] = next_pc;
+                self.sp += 1;
+                next_pc = nnn as usize;
+            },
+            Instruction::SkipEqImm { x, nn } => {
+                if self.v[x as usize] == nn { next_pc += self.instruction_length(next_pc); }
             },
-            // 0x5XY0: Skip the next instruction if VX equals VY
-            OPCODE_SKIP_IF_EQ_XY => {
-                let x = (self.opcode as usize & 0x0F00) >> 8;
-                let y = (self.opcode as usize & 0x00F0) >> 4;
-                let vx = self.v[x];
-                let vy = self.v[y];
-                println!("SKIP NEXT INSTRUCTION IF V{:x?} ({:x?}) == V{:x?} ({:x?})", x, vx, y, vy);
-                if vx == vy {
-                    self.pc += 4;
-                } else {
-                    self.pc += 2;
+            Instruction::SkipNeImm { x, nn } => {
+                if self.v[x as usize] != nn { next_pc += self.instruction_length(next_pc); }
+            },
+            Instruction::SkipEqReg { x, y } => {
+                if self.v[x as usize] == self.v[y as usize] { next_pc += self.instruction_length(next_pc); }
+            },
+            Instruction::StoreRange { x, y } => {
+                for (offset, register) in Chip::register_range(x, y).enumerate() {
+                    self.write_memory(self.i as usize + offset, self.v[register])?;
                 }
             },
-            // 0x6XNN: Set VX to NN
-            OPCODE_SET_VX_TO_NN => {
-                let x  = (self.opcode as usize & 0x0F00) >> 8;
-                let nn = self.opcode as u8 & 0x00FF;
-                println!("SET V{:x?} ({:x?}) TO {:x?}", x, self.v[x], nn);
-                self.v[x] = nn;
-                self.pc += 2;
-            },
-            // 0x7XNN: Add NN to VX (carry flag is not changed)
-            OPCODE_ADD_NN_TO_VX => {
-                let nn = self.opcode as u8 & 0x00FF;
-                let x = (self.opcode as usize & 0x0F00) >> 8;
-                println!("ADD {} TO V{} ({}) = {}", nn, x, self.v[x], self.v[x].wrapping_add(nn));
-                self.v[x] = self.v[x].wrapping_add(nn);
-                self.pc += 2;
-            },
-            // 0x8--- family: Arithmetics
-            // - 0x8XY0: Set VX to the value of VY
-            // - 0x8XY1: Set VX to VX | VY
-            // - 0x8XY2: Set VX to VX & VY
-            // - 0x8XY3: Set VX to VX ^ VY
-            // - 0x8XY4: Add VY to VX (VF is set to 1 if there is a carry, else 0)
-            // - 0x8XY5: Substract VY from VX (VF is set to 0 if there is a borrow, else 1)
-            // - 0x8XY6: Store the LSB of VX in VF and then shift VX right by 1
-            // - 0x8XY7: Set VX to VY minus VX (VF is set to 0 if there is a borrow, else 1)
-            // - 0x8XYE: Store the MSB of VX in VF and then shift VX left by 1
-            FAMILY_ARITHMETICS => {
-                let x = (self.opcode as usize & 0x0F00) >> 8;
-                let y = (self.opcode as usize & 0x00F0) >> 4;
-                let vx = self.v[x];
-                let vy = self.v[y];
-                match self.opcode & 0xF00F {
-                    OPCODE_SET_VX_TO_VY => {
-                        println!("SET V{:x?} ({:x?}) to V{:x?} ({:x?})", x, vx, y, vy);
-                        self.v[x] = vy;
-                    },
-                    OPCODE_SET_VX_TO_VX_OR_VY => {
-                        println!("SET V{:x?} ({:x?}) to V{:x?} ({:x?}) | V{:x?} ({:x?})", x, vx, x, vx, y, vy);
-                        self.v[x] = vx | vy;
-                    },
-                    OPCODE_SET_VX_TO_VX_AND_VY => {
-                        println!("SET V{:x?} ({:x?}) to V{:x?} ({:x?}) & V{:x?} ({:x?})", x, vx, x, vx, y, vy);
-                        self.v[x] = vx & vy;
-                    },
-                    OPCODE_SET_VX_TO_VX_XOR_VY => {
-                        println!("SET V{:x?} ({:x?}) to V{:x?} ({:x?}) ^ V{:x?} ({:x?})", x, vx, x, vx, y, vy);
-                        self.v[x] = vx ^ vy;
-                    },
-                    OPCODE_SET_VX_TO_VX_PLUS_VY => {
-                        println!("ADDITION: V{:x?} = V{:x?} ({:x?}) + V{:x?} ({:x?}) = {:x?}",
-                                 x, x, vx, y, vy, vx.wrapping_add(vy));
-                        if vy > (0xFF - vx) {
-                            self.v[0xF] = 1;
-                        } else {
-                            self.v[0xF] = 0;
-                        }
-                        self.v[x] = vx.wrapping_add(vy);
-                        self.pc += 2;
-                    },
-                    OPCODE_SUBSTRACT_VY_FROM_VX => {
-                        println!("SUBSTRACTION: V{:x?} = V{:x?} - V{:x?} = {:x?}",
-                                 x, x, y, vx.wrapping_sub(vy));
-                        if vx > vy { self.v[0xf] = 1; }
-                        else { self.v[0xf] = 0; }
-                        self.v[x] = vx.wrapping_sub(vy);
-                    },
-                    OPCODE_STORE_LSB_OF_VX_IN_VF_AND_RSHIFT_VX => {
-                        println!("STORING LSB OF V{:x?} ({:x?}) IN VF: {:x?}", x, vx, vx & 1);
-                        self.v[0xf] = vx & 1;
-                        self.v[x] = vx >> 1;
-                    },
-                    OPCODE_SET_VX_TO_VY_MINUS_VX => {
-                        println!("SUBSTRACTION: V{:x?} = V{:x?} - V{:x?} = {:x?}",
-                                 x, y, x, vy.wrapping_sub(vx));
-                        if vy > vx { self.v[0xf] = 1; }
-                        else { self.v[0xf] = 0; }
-                        self.v[x] = vy.wrapping_sub(vx);
-                    },
-                    OPCODE_STORE_MSB_OF_VX_IN_VF_AND_LSHIFT_VX => {
-                        println!("STORING MSB OF V{:x?} in VF: {:x?}", x, vx & 0x80);
-                        self.v[0xf] = vx & 0x80;
-                        self.v[x] = vx << 1;
-                    },
-                    _      => {
-                        panic!("UNKNOWN OPCODE: {:0>4x?}", self.opcode);
+            Instruction::LoadRange { x, y } => {
+                for (offset, register) in Chip::register_range(x, y).enumerate() {
+                    self.v[register] = self.read_memory(self.i as usize + offset)?;
+                }
+            },
+            Instruction::LoadImm { x, nn } => {
+                self.v[x as usize] = nn;
+            },
+            Instruction::AddImm { x, nn } => {
+                self.v[x as usize] = self.v[x as usize].wrapping_add(nn);
+            },
+            Instruction::LoadReg { x, y } => {
+                self.v[x as usize] = self.v[y as usize];
+            },
+            Instruction::Or { x, y } => {
+                self.v[x as usize] |= self.v[y as usize];
+                if self.quirks.logic_resets_vf { self.v[0xf] = 0; }
+            },
+            Instruction::And { x, y } => {
+                self.v[x as usize] &= self.v[y as usize];
+                if self.quirks.logic_resets_vf { self.v[0xf] = 0; }
+            },
+            Instruction::Xor { x, y } => {
+                self.v[x as usize] ^= self.v[y as usize];
+                if self.quirks.logic_resets_vf { self.v[0xf] = 0; }
+            },
+            Instruction::AddReg { x, y } => {
+                let (result, carry) = self.v[x as usize].overflowing_add(self.v[y as usize]);
+                self.v[x as usize] = result;
+                self.v[0xf] = carry as u8;
+            },
+            Instruction::Sub { x, y } => {
+                let (result, borrow) = self.v[x as usize].overflowing_sub(self.v[y as usize]);
+                self.v[x as usize] = result;
+                self.v[0xf] = !borrow as u8;
+            },
+            Instruction::ShiftRight { x, y } => {
+                let source = if self.quirks.shift_uses_vy { self.v[y as usize] } else { self.v[x as usize] };
+                self.v[x as usize] = source >> 1;
+                self.v[0xf] = source & 0x1;
+            },
+            Instruction::SubN { x, y } => {
+                let (result, borrow) = self.v[y as usize].overflowing_sub(self.v[x as usize]);
+                self.v[x as usize] = result;
+                self.v[0xf] = !borrow as u8;
+            },
+            Instruction::ShiftLeft { x, y } => {
+                let source = if self.quirks.shift_uses_vy { self.v[y as usize] } else { self.v[x as usize] };
+                self.v[x as usize] = source << 1;
+                self.v[0xf] = source >> 7;
+            },
+            Instruction::SkipNeReg { x, y } => {
+                if self.v[x as usize] != self.v[y as usize] { next_pc += self.instruction_length(next_pc); }
+            },
+            Instruction::LoadI { nnn } => {
+                self.i = nnn.into();
+            },
+            Instruction::JumpV0 { nnn } => {
+                let offset = if self.quirks.jump_uses_vx { self.v[(nnn >> 8) as usize] } else { self.v[0] };
+                next_pc = nnn as usize + offset as usize;
+            },
+            Instruction::Rand { x, nn } => {
+                self.v[x as usize] = self.rng.next_byte() & nn;
+            },
+            Instruction::Draw { x, y, n } => {
+                if self.quirks.display_wait {
+                    if !self.vblank_ready {
+                        return Ok(StepOutcome::WaitForVBlank);
                     }
+                    self.vblank_ready = false;
                 }
 
-                self.pc += 2;
-            },
-            // 0x9XY0: Skip the next instruction if VX doesn't equal VY
-            OPCODE_SKIP_IF_NEQ_XY => {
-                let x = (self.opcode as usize & 0x0F00) >> 8;
-                let y = (self.opcode as usize & 0x00F0) >> 4;
-                let vx = self.v[x];
-                let vy = self.v[y];
-                println!("SKIP NEXT INSTRUCTION IF V{:x?} ({}) != V{:x?} ({})", x, vx, y, vy);
-                if vx != vy {
-                    self.pc += 4;
-                } else {
-                    self.pc += 2;
-                }
-            },
-            // 0xANNN: Set I to the address NNN
-            OPCODE_SET_I_TO_NNN => {
-                println!("SET I TO {:0>4x?}", self.opcode & 0x0FFF);
-                self.i = (self.opcode & 0x0FFF).into();
-                self.pc += 2;
-            },
-            // 0xBNNN: Jump to the address NNN + V0
-            OPCODE_JUMP_TO_NNN_PLUS_V0 =>  {
-                let nnn = self.opcode as usize & 0x0FFF;
-                println!("JUMPING TO NNN ({:0>4x?}) PLUS V0 ({:x?})", nnn, self.v[0]);
-                self.pc = nnn + self.v[0] as usize;
-            },
-            // 0xCXNN: Set VX to the result of NN & rand()[0..255]
-            OPCODE_SET_VX_TO_NN_AND_RAND => {
-                let r = rand::random::<u8>();
-                let x = (self.opcode as usize & 0x0F00) >> 8;
-                let nn = self.opcode as u8 & 0x00FF;
-                println!("RANDOM: SET V{:x?} TO {:x?} & {:x?} = {:x?}",
-                         x, r, nn, r & nn);
-                self.v[x] = r & nn;
-                self.pc += 2;
-            },
-            // 0xDXYN: Read N bytes from memory, starting in I. Those bytes are then displayed
-            //         as sprites on screen at coordinates (VX, VY). Sprites are XORed onto the
-            //         existing screen. If this causes any pixels to be erased, VF is set to 1,
-            //         otherwise it is set to 0.
-            OPCODE_DRAW_SPRITE => {
-                let x = (self.opcode as usize & 0x0F00) >> 8;
-                let y = (self.opcode as usize & 0x00F0) >> 4;
-                let vx = self.v[x] as usize;
-                let vy = self.v[y] as usize;
-                let n = (self.opcode as usize & 0x000F) >> 0;
-                println!("DRAW SPRITE (V{:x?} = {}, V{:x?} = {}), HEIGHT {})", x, vx, y, vy, n);
+                // The origin always wraps; pixels past the edges wrap or clip depending on the quirk.
+                let (width, height) = (self.width(), self.height());
+                let vx = self.v[x as usize] as usize % width;
+                let vy = self.v[y as usize] as usize % height;
+                // DXY0 draws a 16x16 sprite stored as two bytes per row.
+                let (rows, bytes_per_row) = if n == 0 { (16, 2) } else { (n as usize, 1) };
 
+                // With both XO-CHIP planes selected, the second plane's sprite
+                // data directly follows the first one's.
                 let mut flipped = false;
-                for i in 0..n {
-                    for j in 0..8 {
-                        let mut pos = (vy + i) * SCREEN_WIDTH + vx + j;
-                        if pos >= SCREEN_WIDTH * SCREEN_HEIGHT { pos %= SCREEN_WIDTH * SCREEN_HEIGHT; }
-                        let bit = self.memory[self.i as usize + i] >> (7 - j) & 0x1;
-                        if self.graphics[pos] == 1 && bit == 1 { flipped = true; self.v[0xf] = 1; }
-                        self.graphics[pos] ^= bit;
+                let mut address = self.i as usize;
+                let selected = self.plane;
+                for plane in [0b01, 0b10].iter().filter(|plane| selected & *plane != 0) {
+                    for i in 0..rows {
+                        for byte in 0..bytes_per_row {
+                            let row = self.read_memory(address + i * bytes_per_row + byte)?;
+                            if self.quirks.clip_sprites && vy + i >= height { continue; }
+                            for j in 0..8 {
+                                let column = vx + byte * 8 + j;
+                                if self.quirks.clip_sprites && column >= width { break; }
+                                let pos = ((vy + i) % height) * width + column % width;
+                                if row >> (7 - j) & 0x1 == 0 { continue; }
+                                if self.graphics[pos] & plane != 0 { flipped = true; }
+                                self.graphics[pos] ^= plane;
+                            }
+                        }
                     }
+                    address += rows * bytes_per_row;
                 }
-
-                if !flipped { self.v[0xf] = 0; }
+                self.v[0xf] = flipped as u8;
+                event!(Render, Trace, "sprite {:04X} at ({}, {}), {} rows, planes {:02b}{}",
+                       self.i, vx, vy, rows, selected, if flipped { ", collision" } else { "" });
 
                 self.draw_flag = 1;
-                self.pc += 2;
-            },
-            // 0xE--- family: Input conditionals
-            // - 0xEX9E: Skip the next instruction if the key stored in VX is pressed
-            // - 0xEXA1: Skip the next instruction if the key stored in VX isn't pressed
-            FAMILY_INPUT_CONDITIONALS => {
-                let x = (self.opcode as usize & 0x0F00) >> 8;
-                let vx = self.v[x] as usize;
-
-                match self.opcode & 0xF0FF {
-                    OPCODE_SKIP_IF_VX_IS_PRESSED => {
-                        println!("SKIP NEXT INSTRUCTION IF KEY {:x?} IS PRESSED", vx);
-                        if self.key[vx] == true {
-                            self.pc += 2;
-                        }
-                    },
-                    OPCODE_SKIP_IF_VX_IS_NOT_PRESSED => {
-                        println!("SKIP NEXT INSTRUCTION IF KEY V{:x} ({:x?}) IS NOT PRESSED", x, vx);
-                        if self.key[vx] == false {
-                            self.pc += 2;
-                        }
-                    },
-                    _      => {
-                        panic!("UNKNOWN OPCODE: {:0>4x?}", self.opcode);
-                    }
+                outcome = StepOutcome::Draw;
+            },
+            Instruction::SkipKey { x } => {
+                if self.key[self.v[x as usize] as usize & 0xf] { next_pc += self.instruction_length(next_pc); }
+            },
+            Instruction::SkipNotKey { x } => {
+                if !self.key[self.v[x as usize] as usize & 0xf] { next_pc += self.instruction_length(next_pc); }
+            },
+            Instruction::LoadILong { nnnn } => {
+                self.i = nnnn.into();
+            },
+            Instruction::Plane { n } => {
+                self.plane = n & 0b11;
+            },
+            Instruction::Audio => {
+                for offset in 0..AUDIO_PATTERN_SIZE {
+                    self.audio_pattern[offset] = self.read_memory(self.i as usize + offset)?;
+                }
+            },
+            Instruction::LoadDelay { x } => {
+                self.v[x as usize] = self.delay_timer as u8;
+            },
+            Instruction::WaitKey { x } => {
+                // `set_key` stores the key and moves past this instruction.
+                if self.input_flag > 0xf {
+                    event!(Input, Debug, "waiting for a key into V{:X}", x);
+                }
+                self.input_flag = x as u16;
+                outcome = StepOutcome::WaitForKey;
+                next_pc = self.pc;
+            },
+            Instruction::SetDelay { x } => {
+                self.delay_timer = self.v[x as usize].into();
+                event!(Timers, Debug, "delay timer set to {}", self.delay_timer);
+            },
+            Instruction::SetSound { x } => {
+                self.sound_timer = self.v[x as usize].into();
+                event!(Timers, Debug, "sound timer set to {}", self.sound_timer);
+            },
+            Instruction::AddI { x } => {
+                self.i += self.v[x as usize] as u32;
+            },
+            Instruction::LoadFont { x } => {
+                self.i = ((self.v[x as usize] & 0xf) as usize * FONTSET_ELEMENT_SIZE) as u32;
+            },
+            Instruction::LoadBigFont { x } => {
+                self.i = (BIG_FONTSET_LOCATION + (self.v[x as usize] & 0xf) as usize * BIG_FONTSET_ELEMENT_SIZE) as u32;
+            },
+            Instruction::Pitch { x } => {
+                self.pitch = self.v[x as usize];
+            },
+            Instruction::StoreBcd { x } => {
+                let vx = self.v[x as usize];
+                self.write_memory(self.i as usize, vx / 100)?;
+                self.write_memory(self.i as usize + 1, (vx % 100) / 10)?;
+                self.write_memory(self.i as usize + 2, vx % 10)?;
+            },
+            Instruction::StoreRegs { x } => {
+                for i in 0x0..=x as usize {
+                    self.write_memory(self.i as usize + i, self.v[i])?;
                 }
+                if self.quirks.load_store_increments_i { self.i += x as u32 + 1; }
+            },
+            Instruction::LoadRegs { x } => {
+                for i in 0x0..=x as usize {
+                    self.v[i] = self.read_memory(self.i as usize + i)?;
+                }
+                if self.quirks.load_store_increments_i { self.i += x as u32 + 1; }
+            },
+            Instruction::StoreFlags { x } => {
+                self.rpl[..=x as usize].copy_from_slice(&self.v[..=x as usize]);
+            },
+            Instruction::LoadFlags { x } => {
+                self.v[..=x as usize].copy_from_slice(&self.rpl[..=x as usize]);
+            },
+        }
 
-                self.pc += 2;
-            },
-            // 0xF--- family: Timers, input query, and others
-            // - 0xFX07: Set VX to the value of the delay timer
-            // - 0xFX0A: A key press is awaited, and then stored in VX (blocking)
-            // - 0xFX15: Set the delay timer to VX
-            // - 0xFX18: Set the sound timer to VX
-            // - 0xFX1E: Add VX to I
-            // - 0xFX29: Set I to the location of the sprite for the character in VX.
-            //           Characters 0-F (in hexadecimal) are represented by a 4x5 font.
-            // - 0xFX33: Store the binary-coded decimal representation of VX, with the most
-            //           significant of three digit at the address in I, the middle digit
-            //           at I+1, and the least significant digit at I+2.
-            //           -> Take the decimal representation of VX, place the hundreds digit
-            //           at I, the tens digit at I+1, and the ones digit at I+2.
-            // - 0xFX55: Store V0 to VX (including VX) in memory starting at address I.
-            //           The offset from I is increased by 1 for each value written, but I
-            //           itself is left unmodified.
-            // - 0xFX65: Fill V0 to VX (including VX) with values from memory starting at
-            //           address I. The offset from I is increased by 1 for each value written,
-            //           but I itself is left unmodified.
-            FAMILY_TIMERS_INPUT_QUERY_ETC => {
-                let x  = ((self.opcode & 0x0F00) >> 8) as usize;
-                let vx = self.v[x];
-
-                match self.opcode & 0xF0FF {
-                    OPCODE_SET_VX_TO_DELAY_TIMER => {
-                        println!("SET V{:x?} TO DELAY_TIMER", x);
-                        self.v[x] = self.delay_timer as u8;
-
-                        self.pc += 2;
-                    },
-                    OPCODE_WAIT_FOR_INPUT_AND_SET_TO_VX => {
-                        if self.input_flag > 0xf {
-                            print!("[{:0>4x?}] INSTRUCTION: {:0>4x?}: ", self.pc, self.opcode);
-                            println!("WAIT FOR INPUT AND SET TO V{:x?}", x);
-                        }
-                        self.input_flag = x as u16;
-                    },
-                    OPCODE_SET_DELAY_TIMER_TO_VX => {
-                        println!("SET DELAY_TIMER TO V{:x?}", x);
-                        self.delay_timer = vx.into();
-
-                        self.pc += 2;
-                    },
-                    OPCODE_SET_SOUND_TIMER_TO_VX => {
-                        println!("SET SOUND_TIMER TO V{:x?}", x);
-                        self.sound_timer = vx.into();
-
-                        self.pc += 2;
-                    },
-                    OPCODE_ADD_VX_TO_I => {
-                        println!("SET I TO I ({:x?}) + V{:x?} ({:x?})", self.i, x, vx);
-                        self.i += vx as u32;
-
-                        self.pc += 2;
-                    },
-                    OPCODE_SET_I_TO_SPRITE_IN_VX => {
-                        println!("SET I TO LOCATION OF SPRITE IN V{:x?} ({:x?})", x, vx);
-                        self.i = (vx * FONTSET_ELEMENT_SIZE as u8).into();
-
-                        self.pc += 2;
-                    },
-                    OPCODE_STORE_VX_AS_DIGITS_AT_I => {
-                        let digit_hundred: u8 = vx / 100;
-                        let digit_decimal: u8 = (vx % 100) / 10;
-                        let digit_unit: u8 = vx % 10;
-                        println!("STORE DECIMAL OF V{:x?} ({:x?}) AT {:x?}: {} {} {}",
-                            x, vx, self.i, digit_hundred, digit_decimal, digit_unit);
-                        self.memory[self.i as usize + 0] = vx / 100;
-                        self.memory[self.i as usize + 1] = (vx % 100) / 10;
-                        self.memory[self.i as usize + 2] = vx % 10;
-
-                        self.pc += 2;
-                    },
-                    OPCODE_STORE_REGISTERS_AT_I => {
-                        println!("STORE REGISTERS AT {:x?}", self.i);
-                        for i in 0x0..(x + 1) as usize {
-                            self.memory[self.i as usize + i] = self.v[i];
-                        }
+        self.pc = next_pc;
+        Ok(outcome)
+    }
 
-                        self.pc += 2;
-                    },
-                    OPCODE_RESTORE_REGISTERS_FROM_I => {
-                        println!("RESTORE REGISTERS FROM {:x?}", self.i);
-                        for i in 0x0..(x + 1) as usize {
-                            self.v[i] = self.memory[self.i as usize + i];
-                        }
+    fn set_hires(&mut self, hires: bool) {
+        // Switching resolution clears the screen, as Octo does.
+        self.hires = hires;
+        event!(Render, Debug, "{} mode", if hires { "hires 128x64" } else { "lores 64x32" });
+        self.graphics = vec![0; self.width() * self.height()];
+        self.draw_flag = 1;
+    }
 
-                        self.pc += 2;
-                    },
-                    _ =>      {
-                        panic!("UNKNOWN OPCODE: {:0>4x?}", self.opcode);
-                    }
+    /// Shifts the selected planes by (dx, dy) pixels; pixels scrolled in are blank.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        event!(Render, Debug, "scroll by ({}, {})", dx, dy);
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let plane = self.plane;
+        let mut scrolled: Vec<u8> = self.graphics.iter().map(|pixel| pixel & !plane).collect();
+        for y in 0..height {
+            for x in 0..width {
+                let (source_x, source_y) = (x - dx, y - dy);
+                if source_x >= 0 && source_x < width && source_y >= 0 && source_y < height {
+                    scrolled[(y * width + x) as usize] |=
+                        self.graphics[(source_y * width + source_x) as usize] & plane;
                 }
             }
-            _ => {
-                panic!("UNKNOWN OPCODE: {:0>4x?}", self.opcode);
-            }
         }
-        
-        // Update timers
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
+        self.graphics = scrolled;
+    }
+
+    /// Registers VX to VY inclusive, counting down when X > Y (5XY2/5XY3).
+    fn register_range(x: u8, y: u8) -> Box<dyn Iterator<Item = usize>> {
+        if x <= y {
+            Box::new(x as usize..=y as usize)
+        } else {
+            Box::new((y as usize..=x as usize).rev())
         }
+    }
 
-        if self.sound_timer > 0 {
-            if self.sound_timer == 1 {
-                // TODO: Actual beep handling.
-                println!("BEEP");
-            }
-            self.sound_timer -= 1;
+    /// Length of the instruction at `addr`, so skips jump over both words of F000 NNNN.
+    fn instruction_length(&self, addr: usize) -> usize {
+        match Instruction::decode_at(&self.memory, addr) {
+            Ok(instruction) => instruction.size(),
+            Err(_) => 2,
         }
     }
 
+    /// Presses or releases key `index`, completing a pending FX0A.
+    ///
+    /// Panics if `index` is not a key, i.e. 16 or more.
     pub fn set_key(&mut self, index: usize, state: bool) {
         self.key[index] = state;
+        event!(Input, Debug, "key {:X} {}", index, if state { "pressed" } else { "released" });
 
         if self.input_flag <= 0xf {
+            event!(Input, Debug, "V{:X} = key {:X}", self.input_flag, index);
             self.v[self.input_flag as usize] = index as u8;
             self.input_flag = 0x10;
             self.pc += 2;
         }
     }
+
+    /// Sets the whole keypad at once from a bitmask (bit N = key N held),
+    /// going through `set_key` for every key that changed.
+    pub fn set_keys(&mut self, mask: u16) {
+        for index in 0..KEYBOARD_SIZE {
+            let state = mask & (1 << index) != 0;
+            if self.key[index] != state {
+                self.set_key(index, state);
+            }
+        }
+    }
+}
+
+impl Default for Chip {
+    fn default() -> Chip {
+        Chip::new(Quirks::default())
+    }
 }
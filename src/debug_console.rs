@@ -0,0 +1,408 @@
+use std::io::{self, BufRead, Write};
+
+use chip8::chip::{Chip, MEMORY_SIZE};
+use chip8::debugger::{Condition, Debugger, Resume, Stop, Watchpoint};
+use chip8::error::ChipError;
+use chip8::opcodes::Instruction;
+use chip8::screenshot;
+
+// Bytes shown by `x` when no length is given, and per line of its dump.
+const DEFAULT_DUMP_LENGTH: usize = 64;
+const DUMP_BYTES_PER_LINE: usize = 16;
+// Instructions shown by `list` when no count is given.
+const DEFAULT_LIST_LENGTH: usize = 8;
+
+const HELP: &str = "\
+Commands (numbers are decimal, or hexadecimal with 0x; addresses may be labels):
+  b, break ADDR [if COND]
+                      Stop when pc reaches ADDR and COND holds, e.g. `V3 == 0x10 && I > 0x300`
+                      or `hits >= 5`; `[ADDR]` in COND reads a byte of memory
+  watch ADDR [LEN]    Stop after an instruction writes LEN bytes from ADDR (default: 1)
+  rwatch ADDR [LEN]   The same for reads (FX65, DXYN sprites...); awatch for both
+  d, delete [ADDR]    Remove the breakpoint and watchpoints at ADDR, or all of them
+  breakpoints         List the breakpoints and watchpoints
+  s, step [N]         Execute N instructions (default: 1)
+  n, next             Execute one instruction, running over a CALL without stopping in it
+  c, continue         Resume until the next breakpoint
+  p, print [REG]      Print a register (V0-VF, I, pc, sp, dt, st, stack), or all of them
+  set REG VALUE       Change a register; `set stack N VALUE` changes a stack entry
+  x ADDR [LEN]        Hex dump LEN bytes of memory (default: 64)
+  l, list [N]         Disassemble N instructions from pc (default: 8)
+  screen              Print the screen as text
+  h, help             Show this help
+  q, quit             Stop the emulator
+An empty line repeats the previous command.";
+
+/// Registers the `print` and `set` commands know about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Register {
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    DelayTimer,
+    SoundTimer,
+    Stack,
+}
+
+impl Register {
+    fn parse(name: &str) -> Option<Register> {
+        let name = name.to_ascii_lowercase();
+        Some(match name.as_str() {
+            "i" => Register::I,
+            "pc" => Register::Pc,
+            "sp" => Register::Sp,
+            "dt" => Register::DelayTimer,
+            "st" => Register::SoundTimer,
+            "stack" => Register::Stack,
+            _ => match name.strip_prefix('v').and_then(|digit| u8::from_str_radix(digit, 16).ok()) {
+                Some(x) if x < 16 && name.len() == 2 => Register::V(x as usize),
+                _ => return None,
+            },
+        })
+    }
+}
+
+/// The `--debug` command prompt, reading commands from stdin.
+pub struct Console {
+    debugger: Debugger,
+    // Labels of the assembled source, if the game is one.
+    symbols: Vec<(String, u16)>,
+    break_requested: bool,
+    // Set once stdin is closed: the program then runs on without stopping.
+    detached: bool,
+    last_command: String,
+}
+
+impl Console {
+    /// A console stopping before the first instruction, with breakpoints at `breakpoints`.
+    pub fn new(mut debugger: Debugger, mut symbols: Vec<(String, u16)>, breakpoints: &[(String, u16)]) -> Console {
+        for (name, addr) in breakpoints {
+            debugger.add_breakpoint(*addr as usize);
+            // Name the breakpoint after its `:breakpoint` unless a label already does.
+            if !symbols.iter().any(|(_, label)| label == addr) {
+                symbols.push((name.clone(), *addr));
+            }
+        }
+        Console {
+            debugger,
+            symbols,
+            break_requested: true,
+            detached: false,
+            last_command: String::new(),
+        }
+    }
+
+    /// Drops to the prompt before the next instruction.
+    #[cfg_attr(not(feature = "sfml"), allow(dead_code))]
+    pub fn request_break(&mut self) {
+        self.break_requested = true;
+    }
+
+    /// Runs one frame under the debugger, prompting whenever it stops.
+    /// `redraw` shows the screen after the prompt's commands changed it.
+    pub fn run_frame(&mut self, chip: &mut Chip, redraw: &mut dyn FnMut(&Chip)) -> Result<Resume, ChipError> {
+        if self.break_requested {
+            self.break_requested = false;
+            if self.prompt(chip, redraw) == Resume::Quit {
+                return Ok(Resume::Quit);
+            }
+        }
+        while let Some(stop) = self.debugger.run_frame(chip)? {
+            report(chip, stop);
+            if self.prompt(chip, redraw) == Resume::Quit {
+                return Ok(Resume::Quit);
+            }
+        }
+        Ok(Resume::Continue)
+    }
+
+    fn prompt(&mut self, chip: &mut Chip, redraw: &mut dyn FnMut(&Chip)) -> Resume {
+        if self.detached {
+            return Resume::Continue;
+        }
+        self.show_current(chip);
+        let stdin = io::stdin();
+        loop {
+            print!("(chip8) ");
+            io::stdout().flush().ok();
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                println!();
+                println!("Input closed, running on without the debugger");
+                self.detached = true;
+                self.debugger.clear_breakpoints();
+                self.debugger.clear_watchpoints();
+                self.debugger.resume(chip);
+                return Resume::Continue;
+            }
+            let line = match line.trim() {
+                "" => self.last_command.clone(),
+                line => line.to_string(),
+            };
+            self.last_command = line.clone();
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match self.execute(chip, &words, redraw) {
+                Ok(Some(resume)) => {
+                    self.debugger.resume(chip);
+                    return resume;
+                },
+                Ok(None) => {},
+                Err(message) => println!("{}", message),
+            }
+        }
+    }
+
+    /// Runs a command, returning whether to leave the prompt.
+    fn execute(&mut self, chip: &mut Chip, words: &[&str], redraw: &mut dyn FnMut(&Chip))
+               -> Result<Option<Resume>, String> {
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return Ok(None),
+        };
+        match (command, args) {
+            ("b", [addr, rest @ ..]) | ("break", [addr, rest @ ..]) => {
+                let addr = self.address(addr)?;
+                let condition = match rest {
+                    [] => None,
+                    ["if", condition @ ..] if !condition.is_empty() => Some(
+                        Condition::parse(&condition.join(" ")).map_err(|e| format!("Invalid condition: {}", e))?),
+                    _ => return Err("Expected `break ADDR [if CONDITION]`".to_string()),
+                };
+                if self.debugger.add_conditional_breakpoint(addr, condition) {
+                    println!("Breakpoint at {}", self.describe(addr));
+                } else {
+                    println!("Changed the breakpoint at {}", self.describe(addr));
+                }
+            },
+            ("watch", _) | ("rwatch", _) | ("awatch", _) if !args.is_empty() && args.len() <= 2 => {
+                let start = self.address(args[0])?;
+                let length = match args.get(1) {
+                    Some(length) => parse_number(length)?.max(1),
+                    None => 1,
+                };
+                let watchpoint = Watchpoint {
+                    start,
+                    end: start.saturating_add(length).min(MEMORY_SIZE),
+                    reads: command != "watch",
+                    writes: command != "rwatch",
+                };
+                self.debugger.add_watchpoint(watchpoint);
+                println!("Watching {}", self.describe_watchpoint(&watchpoint));
+            },
+            ("d", []) | ("delete", []) => {
+                self.debugger.clear_breakpoints();
+                self.debugger.clear_watchpoints();
+            },
+            ("d", [addr]) | ("delete", [addr]) => {
+                let addr = self.address(addr)?;
+                let removed = self.debugger.remove_breakpoint(addr);
+                if !self.debugger.remove_watchpoint(addr) && !removed {
+                    return Err(format!("No breakpoint or watchpoint at {}", self.describe(addr)));
+                }
+            },
+            ("breakpoints", []) => {
+                for (addr, breakpoint) in self.debugger.breakpoints() {
+                    let condition = match &breakpoint.condition {
+                        Some(condition) => format!(" if {}", condition),
+                        None => String::new(),
+                    };
+                    println!("  break {}{} (reached {} times)", self.describe(addr), condition, breakpoint.hits);
+                }
+                for watchpoint in self.debugger.watchpoints() {
+                    println!("  watch {}", self.describe_watchpoint(watchpoint));
+                }
+            },
+            ("s", _) | ("step", _) if args.len() <= 1 => {
+                let count = match args.first() {
+                    Some(count) => parse_number(count)?,
+                    None => 1,
+                };
+                self.step(chip, count)?;
+                redraw(chip);
+                self.show_current(chip);
+            },
+            ("n", []) | ("next", []) => {
+                if self.debugger.step_over(chip) {
+                    return Ok(Some(Resume::Continue));
+                }
+                self.step(chip, 1)?;
+                redraw(chip);
+                self.show_current(chip);
+            },
+            ("c", []) | ("continue", []) => return Ok(Some(Resume::Continue)),
+            ("p", []) | ("print", []) => print_registers(chip),
+            ("p", [name]) | ("print", [name]) => {
+                let register = Register::parse(name).ok_or_else(|| format!("Unknown register: {}", name))?;
+                print_register(chip, register);
+            },
+            ("set", ["stack", index, value]) => {
+                let index = parse_number(index)?;
+                if index >= chip.stack().len() {
+                    return Err(format!("The stack only has {} entries", chip.stack().len()));
+                }
+                chip.set_stack_entry(index, self.address(value)?);
+            },
+            ("set", [name, value]) => {
+                let register = Register::parse(name).ok_or_else(|| format!("Unknown register: {}", name))?;
+                set_register(chip, register, parse_number(value)?)?;
+                print_register(chip, register);
+            },
+            ("x", _) if !args.is_empty() && args.len() <= 2 => {
+                let addr = self.address(args[0])?;
+                let length = match args.get(1) {
+                    Some(length) => parse_number(length)?,
+                    None => DEFAULT_DUMP_LENGTH,
+                };
+                hex_dump(chip.memory(), addr, length);
+            },
+            ("l", _) | ("list", _) if args.len() <= 1 => {
+                let count = match args.first() {
+                    Some(count) => parse_number(count)?,
+                    None => DEFAULT_LIST_LENGTH,
+                };
+                self.list(chip.memory(), chip.pc(), count);
+            },
+            ("screen", []) => print!("{}", screenshot::to_text(chip.graphics(), chip.width(), chip.height())),
+            ("h", []) | ("help", []) => println!("{}", HELP),
+            ("q", []) | ("quit", []) => return Ok(Some(Resume::Quit)),
+            _ => return Err(format!("Invalid command: {} (type `help` for the list)", words.join(" "))),
+        }
+        Ok(None)
+    }
+
+    /// Executes `count` instructions, stopping early at a breakpoint or exit.
+    fn step(&mut self, chip: &mut Chip, count: usize) -> Result<(), String> {
+        match self.debugger.step_many(chip, count) {
+            Ok(Some(stop)) => report(chip, stop),
+            Ok(None) if chip.exit_flag != 0 => println!("The program exited"),
+            Ok(None) => {},
+            Err(e) => return Err(format!("Emulation error: {}", e)),
+        }
+        Ok(())
+    }
+
+    fn show_current(&self, chip: &Chip) {
+        self.list(chip.memory(), chip.pc(), 1);
+    }
+
+    fn list(&self, memory: &[u8], mut addr: usize, count: usize) {
+        for _ in 0..count {
+            if let Some((name, _)) = self.symbols.iter().find(|(_, label)| *label as usize == addr) {
+                println!("{}:", name);
+            }
+            // decode_at pads past the end of memory, so stop before an instruction that does not fit.
+            match Instruction::decode_at(memory, addr) {
+                Ok(instruction) if addr + instruction.size() <= memory.len() => {
+                    let bytes: Vec<_> = memory[addr..addr + instruction.size()].iter()
+                        .map(|byte| format!("{:02X}", byte)).collect();
+                    println!("  0x{:04X}  {:<9} {}", addr, bytes.concat(), instruction);
+                    addr += instruction.size();
+                },
+                Err(_) if addr + 1 < memory.len() => {
+                    println!("  0x{:04X}  {:02X}{:02X}      (unknown opcode)", addr, memory[addr], memory[addr + 1]);
+                    addr += 2;
+                },
+                _ => break,
+            }
+        }
+    }
+
+    /// Parses an address given as a number or a label.
+    fn address(&self, text: &str) -> Result<usize, String> {
+        let addr = match self.symbols.iter().find(|(name, _)| name == text) {
+            Some((_, addr)) => *addr as usize,
+            None => parse_number(text)?,
+        };
+        if addr >= MEMORY_SIZE {
+            return Err(format!("Address out of memory: 0x{:X}", addr));
+        }
+        Ok(addr)
+    }
+
+    fn describe_watchpoint(&self, watchpoint: &Watchpoint) -> String {
+        let access = match (watchpoint.reads, watchpoint.writes) {
+            (true, true) => "reads and writes",
+            (true, false) => "reads",
+            _ => "writes",
+        };
+        format!("{} of {} byte(s) at {}", access, watchpoint.end - watchpoint.start, self.describe(watchpoint.start))
+    }
+
+    fn describe(&self, addr: usize) -> String {
+        match self.symbols.iter().find(|(_, label)| *label as usize == addr) {
+            Some((name, _)) => format!("0x{:04X} ({})", addr, name),
+            None => format!("0x{:04X}", addr),
+        }
+    }
+}
+
+fn report(chip: &Chip, stop: Stop) {
+    match stop {
+        Stop::Watchpoint { addr, .. } => println!("Stopped: {} (now 0x{:02X})", stop, chip.memory()[addr]),
+        _ => println!("Stopped: {}", stop),
+    }
+}
+
+fn parse_number(text: &str) -> Result<usize, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("Invalid number: {}", text))
+}
+
+fn print_registers(chip: &Chip) {
+    println!("pc 0x{:04X}  i 0x{:04X}  sp {}  dt {}  st {}",
+             chip.pc(), chip.i(), chip.sp(), chip.delay_timer(), chip.sound_timer());
+    for (row, values) in chip.registers().chunks(8).enumerate() {
+        let values: Vec<_> = values.iter().enumerate()
+            .map(|(x, value)| format!("V{:X} {:02X}", row * 8 + x, value))
+            .collect();
+        println!("{}", values.join("  "));
+    }
+    print_register(chip, Register::Stack);
+}
+
+fn print_register(chip: &Chip, register: Register) {
+    match register {
+        Register::V(x) => println!("V{:X} = 0x{:02X} ({})", x, chip.registers()[x], chip.registers()[x]),
+        Register::I => println!("I = 0x{:04X}", chip.i()),
+        Register::Pc => println!("pc = 0x{:04X}", chip.pc()),
+        Register::Sp => println!("sp = {}", chip.sp()),
+        Register::DelayTimer => println!("dt = {}", chip.delay_timer()),
+        Register::SoundTimer => println!("st = {}", chip.sound_timer()),
+        Register::Stack => {
+            let entries: Vec<_> = chip.stack()[..chip.sp()].iter().map(|addr| format!("0x{:04X}", addr)).collect();
+            println!("stack = [{}]", entries.join(", "));
+        },
+    }
+}
+
+fn set_register(chip: &mut Chip, register: Register, value: usize) -> Result<(), String> {
+    let out_of_range = |max: usize| if value > max {
+        Err(format!("Value out of range: {} (at most 0x{:X})", value, max))
+    } else {
+        Ok(())
+    };
+    match register {
+        Register::V(x) => { out_of_range(0xFF)?; chip.set_register(x, value as u8); },
+        Register::I => { out_of_range(MEMORY_SIZE - 1)?; chip.set_i(value as u32); },
+        Register::Pc => { out_of_range(MEMORY_SIZE - 2)?; chip.set_pc(value); },
+        Register::Sp => { out_of_range(chip.stack().len())?; chip.set_sp(value); },
+        Register::DelayTimer => { out_of_range(0xFF)?; chip.set_delay_timer(value as u32); },
+        Register::SoundTimer => { out_of_range(0xFF)?; chip.set_sound_timer(value as u32); },
+        Register::Stack => return Err("Use `set stack N VALUE` to change a stack entry".to_string()),
+    }
+    Ok(())
+}
+
+fn hex_dump(memory: &[u8], addr: usize, length: usize) {
+    let end = addr.saturating_add(length).min(memory.len());
+    for start in (addr..end).step_by(DUMP_BYTES_PER_LINE) {
+        let bytes: Vec<_> = memory[start..(start + DUMP_BYTES_PER_LINE).min(end)].iter()
+            .map(|byte| format!("{:02X}", byte)).collect();
+        println!("0x{:04X}  {}", start, bytes.join(" "));
+    }
+}
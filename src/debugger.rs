@@ -0,0 +1,501 @@
+//! Breakpoints, watchpoints and single stepping on top of `Chip::emulate_cycle`.
+//!
+//! A `Debugger` runs the program with the same frame timing as
+//! `Chip::run_frame`, but can stop in the middle of a frame: it remembers how
+//! many cycles of the current frame have run, so resuming finishes the frame
+//! exactly as an uninterrupted run would have.
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+
+use crate::chip::{Chip, StepOutcome};
+use crate::error::ChipError;
+use crate::opcodes::Instruction;
+
+/// Whether the emulator should go on once the debugger hands control back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    Quit,
+}
+
+/// Why the debugger stopped the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// `pc` reached a breakpoint; the instruction there has not run yet.
+    Breakpoint(usize),
+    /// The subroutine being stepped over returned to this address.
+    StepOver(usize),
+    /// The instruction at `pc` accessed the watched address `addr`.
+    Watchpoint { pc: usize, addr: usize, access: Access },
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Stop::Breakpoint(pc) => write!(f, "breakpoint at 0x{:04X}", pc),
+            Stop::StepOver(pc) => write!(f, "returned to 0x{:04X}", pc),
+            Stop::Watchpoint { pc, addr, access } => write!(f, "0x{:04X} {} 0x{:04X}", pc, access, addr),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Access::Read => "read",
+            Access::Write => "wrote",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    /// Only stop when this holds, if given.
+    pub condition: Option<Condition>,
+    /// How many times `pc` reached the breakpoint, whether it stopped or not.
+    pub hits: u32,
+}
+
+/// Stops the program after an instruction reads or writes memory in
+/// `start..end`: FX55, FX65, FX33, the sprite data of DXYN, and the XO-CHIP
+/// 5XY2, 5XY3 and F002.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: usize,
+    pub end: usize,
+    pub reads: bool,
+    pub writes: bool,
+}
+
+pub struct Debugger {
+    cycles_per_frame: usize,
+    // Cycles already run in the current frame.
+    cycles: usize,
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    // (return address, stack depth) of the CALL being stepped over.
+    step_over: Option<(usize, usize)>,
+    // Address resumed from, whose breakpoint must not stop the program again.
+    resume_pc: Option<usize>,
+    // The last instruction was FX0A still waiting for a key: it is not reached anew.
+    waiting: bool,
+}
+
+impl Debugger {
+    pub fn new(cycles_per_frame: usize) -> Debugger {
+        Debugger {
+            cycles_per_frame,
+            cycles: 0,
+            breakpoints: BTreeMap::new(),
+            watchpoints: Vec::new(),
+            step_over: None,
+            resume_pc: None,
+            waiting: false,
+        }
+    }
+
+    /// Adds a breakpoint, returning false if there already was one at `addr`.
+    pub fn add_breakpoint(&mut self, addr: usize) -> bool {
+        self.add_conditional_breakpoint(addr, None)
+    }
+
+    /// Adds a breakpoint that only stops when `condition` holds, replacing the
+    /// condition of an existing one. Returns false if there already was one.
+    pub fn add_conditional_breakpoint(&mut self, addr: usize, condition: Option<Condition>) -> bool {
+        let added = !self.breakpoints.contains_key(&addr);
+        self.breakpoints.entry(addr).or_insert(Breakpoint { condition: None, hits: 0 }).condition = condition;
+        added
+    }
+
+    /// Removes a breakpoint, returning false if there was none at `addr`.
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.remove(&addr).is_some()
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Breakpoints by address, in increasing order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = (usize, &Breakpoint)> + '_ {
+        self.breakpoints.iter().map(|(addr, breakpoint)| (*addr, breakpoint))
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Removes the watchpoints starting at `addr`, returning false if there were none.
+    pub fn remove_watchpoint(&mut self, addr: usize) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|watchpoint| watchpoint.start != addr);
+        self.watchpoints.len() != count
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Executes a single instruction, ticking the timers when it ends the frame.
+    pub fn step(&mut self, chip: &mut Chip) -> Result<StepOutcome, ChipError> {
+        let outcome = chip.emulate_cycle()?;
+        self.resume_pc = None;
+        self.waiting = outcome == StepOutcome::WaitForKey;
+        self.cycles += 1;
+        if outcome == StepOutcome::WaitForVBlank
+            || (outcome != StepOutcome::Exit && self.cycles >= self.cycles_per_frame) {
+            chip.tick_timers();
+            self.cycles = 0;
+        }
+        Ok(outcome)
+    }
+
+    /// Executes up to `count` instructions, stopping early at the program's
+    /// exit or where `run_frame` would. The instruction at `pc` always runs,
+    /// even if there is a breakpoint on it.
+    pub fn step_many(&mut self, chip: &mut Chip, count: usize) -> Result<Option<Stop>, ChipError> {
+        self.resume(chip);
+        for _ in 0..count {
+            match self.advance(chip)? {
+                Advance::Stopped(stop) => return Ok(Some(stop)),
+                Advance::Ran(StepOutcome::Exit) => break,
+                Advance::Ran(_) => {},
+            }
+        }
+        Ok(None)
+    }
+
+    /// Arms a step over the subroutine called by the instruction at `pc`:
+    /// `run_frame` will stop with `Stop::StepOver` once it returns. Returns
+    /// false, arming nothing, if that instruction is not a CALL.
+    pub fn step_over(&mut self, chip: &Chip) -> bool {
+        match chip.current_instruction() {
+            Ok(call @ Instruction::Call { .. }) => {
+                self.step_over = Some((chip.pc() + call.size(), chip.sp()));
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Marks the program as resumed from its current address, so that a
+    /// breakpoint there lets it go instead of stopping it again at once.
+    pub fn resume(&mut self, chip: &Chip) {
+        self.resume_pc = Some(chip.pc());
+    }
+
+    /// Runs the rest of the current frame, like `Chip::run_frame`, unless a
+    /// breakpoint, a watchpoint or an armed step over stops it first.
+    pub fn run_frame(&mut self, chip: &mut Chip) -> Result<Option<Stop>, ChipError> {
+        loop {
+            match self.advance(chip)? {
+                Advance::Stopped(stop) => return Ok(Some(stop)),
+                Advance::Ran(StepOutcome::Exit) => return Ok(None),
+                Advance::Ran(_) if self.cycles == 0 => return Ok(None),
+                Advance::Ran(_) => {},
+            }
+        }
+    }
+
+    /// Executes the next instruction unless a breakpoint stops the program
+    /// before it, and checks the watchpoints after it.
+    fn advance(&mut self, chip: &mut Chip) -> Result<Advance, ChipError> {
+        if let Some(stop) = self.check_breakpoints(chip) {
+            self.resume(chip);
+            return Ok(Advance::Stopped(stop));
+        }
+        let pc = chip.pc();
+        let outcome = self.step(chip)?;
+        if let Some(stop) = self.check_watchpoints(chip, pc) {
+            self.resume(chip);
+            return Ok(Advance::Stopped(stop));
+        }
+        Ok(Advance::Ran(outcome))
+    }
+
+    fn check_breakpoints(&mut self, chip: &Chip) -> Option<Stop> {
+        let pc = chip.pc();
+        if self.resume_pc == Some(pc) || self.waiting {
+            return None;
+        }
+        if self.step_over == Some((pc, chip.sp())) {
+            self.step_over = None;
+            return Some(Stop::StepOver(pc));
+        }
+        let breakpoint = self.breakpoints.get_mut(&pc)?;
+        breakpoint.hits += 1;
+        if breakpoint.condition.as_ref().is_some_and(|condition| !condition.holds(chip, breakpoint.hits)) {
+            return None;
+        }
+        // Like in gdb, hitting a breakpoint abandons the step over.
+        self.step_over = None;
+        Some(Stop::Breakpoint(pc))
+    }
+
+    fn check_watchpoints(&self, chip: &Chip, pc: usize) -> Option<Stop> {
+        let access = chip.last_access();
+        // First watched address in `range`, if any.
+        let overlap = |watchpoint: &Watchpoint, range: Option<(usize, usize)>| match range {
+            Some((start, end)) if start < watchpoint.end && watchpoint.start < end => Some(start.max(watchpoint.start)),
+            _ => None,
+        };
+        self.watchpoints.iter().find_map(|watchpoint| {
+            let write = overlap(watchpoint, access.written).filter(|_| watchpoint.writes);
+            let read = overlap(watchpoint, access.read).filter(|_| watchpoint.reads);
+            match (write, read) {
+                (Some(addr), _) => Some(Stop::Watchpoint { pc, addr, access: Access::Write }),
+                (None, Some(addr)) => Some(Stop::Watchpoint { pc, addr, access: Access::Read }),
+                (None, None) => None,
+            }
+        })
+    }
+}
+
+enum Advance {
+    Stopped(Stop),
+    Ran(StepOutcome),
+}
+
+/// Error in a breakpoint condition, with the 1-based column it was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConditionError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConditionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl error::Error for ConditionError {}
+
+/// Breakpoint condition, such as `V3 == 0x10 && I > 0x300` or `hits >= 5`.
+///
+/// Operands are numbers (decimal, or hexadecimal with `0x`), the registers
+/// `V0`-`VF`, `I`, `pc`, `sp`, `dt` and `st`, `hits` (how many times the
+/// breakpoint was reached, this time included) and `[ADDR]`, the byte of
+/// memory at `ADDR`. They are compared with `==`, `!=`, `<`, `<=`, `>` and
+/// `>=`, and comparisons combined with `&&`, `||` and parentheses. Names are
+/// case-insensitive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Number(u32),
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    DelayTimer,
+    SoundTimer,
+    Hits,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Operand(Operand),
+    Memory(Box<Expr>),
+    Compare(Box<Expr>, &'static str, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+// Longest first, so `<=` is not read as `<`.
+const PUNCTUATION: [&str; 12] = ["==", "!=", "<=", ">=", "&&", "||", "<", ">", "(", ")", "[", "]"];
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Condition, ConditionError> {
+        let mut parser = Parser { tokens: tokenize(source)?, position: 0, end: source.len() + 1 };
+        let expr = parser.or()?;
+        if let Some((column, token)) = parser.tokens.get(parser.position) {
+            return Err(ConditionError { column: *column, message: format!("unexpected `{}`", token) });
+        }
+        Ok(Condition { source: source.trim().to_string(), expr })
+    }
+
+    /// Whether the condition holds for `chip`, the breakpoint having been reached `hits` times.
+    pub fn holds(&self, chip: &Chip, hits: u32) -> bool {
+        evaluate(&self.expr, chip, hits) != 0
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn evaluate(expr: &Expr, chip: &Chip, hits: u32) -> u32 {
+    match expr {
+        Expr::Operand(operand) => match *operand {
+            Operand::Number(value) => value,
+            Operand::V(x) => chip.registers()[x] as u32,
+            Operand::I => chip.i(),
+            Operand::Pc => chip.pc() as u32,
+            Operand::Sp => chip.sp() as u32,
+            Operand::DelayTimer => chip.delay_timer(),
+            Operand::SoundTimer => chip.sound_timer(),
+            Operand::Hits => hits,
+        },
+        Expr::Memory(addr) => {
+            let addr = evaluate(addr, chip, hits) as usize;
+            chip.memory().get(addr).copied().unwrap_or(0) as u32
+        },
+        Expr::Compare(left, operator, right) => {
+            let (left, right) = (evaluate(left, chip, hits), evaluate(right, chip, hits));
+            let holds = match *operator {
+                "==" => left == right,
+                "!=" => left != right,
+                "<" => left < right,
+                "<=" => left <= right,
+                ">" => left > right,
+                _ => left >= right,
+            };
+            holds as u32
+        },
+        Expr::And(left, right) => (evaluate(left, chip, hits) != 0 && evaluate(right, chip, hits) != 0) as u32,
+        Expr::Or(left, right) => (evaluate(left, chip, hits) != 0 || evaluate(right, chip, hits) != 0) as u32,
+    }
+}
+
+/// Splits a condition into (1-based column, token) pairs.
+fn tokenize(source: &str) -> Result<Vec<(usize, String)>, ConditionError> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+        rest = &rest[start..];
+        let column = source.len() - rest.len() + 1;
+        let length = if let Some(punctuation) = PUNCTUATION.iter().find(|punctuation| rest.starts_with(*punctuation)) {
+            punctuation.len()
+        } else {
+            match rest.find(|c: char| !c.is_ascii_alphanumeric()) {
+                Some(0) => return Err(ConditionError {
+                    column,
+                    message: format!("unexpected `{}`", rest.chars().next().unwrap()),
+                }),
+                Some(end) => end,
+                None => rest.len(),
+            }
+        };
+        tokens.push((column, rest[..length].to_string()));
+        rest = &rest[length..];
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, String)>,
+    position: usize,
+    // Column reported for errors at the end of the condition.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|(_, token)| token.as_str())
+    }
+
+    fn error(&self, message: String) -> ConditionError {
+        let column = self.tokens.get(self.position).map_or(self.end, |(column, _)| *column);
+        ConditionError { column, message }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), ConditionError> {
+        if self.peek() != Some(expected) {
+            return Err(self.error(format!("expected `{}`", expected)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<Expr, ConditionError> {
+        let mut expr = self.and()?;
+        while self.peek() == Some("||") {
+            self.position += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ConditionError> {
+        let mut expr = self.comparison()?;
+        while self.peek() == Some("&&") {
+            self.position += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.comparison()?));
+        }
+        Ok(expr)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ConditionError> {
+        let left = self.operand()?;
+        let operator = match self.peek() {
+            Some(operator) => ["==", "!=", "<=", ">=", "<", ">"].iter().find(|candidate| **candidate == operator),
+            None => None,
+        };
+        match operator {
+            Some(operator) => {
+                self.position += 1;
+                Ok(Expr::Compare(Box::new(left), operator, Box::new(self.operand()?)))
+            },
+            None => Ok(left),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Expr, ConditionError> {
+        let token = match self.peek() {
+            Some(token) => token.to_ascii_lowercase(),
+            None => return Err(self.error("expected a value".to_string())),
+        };
+        let operand = match token.as_str() {
+            "(" | "[" => {
+                self.position += 1;
+                let inner = self.or()?;
+                return if token == "(" {
+                    self.expect(")").map(|()| inner)
+                } else {
+                    self.expect("]").map(|()| Expr::Memory(Box::new(inner)))
+                };
+            },
+            "i" => Operand::I,
+            "pc" => Operand::Pc,
+            "sp" => Operand::Sp,
+            "dt" => Operand::DelayTimer,
+            "st" => Operand::SoundTimer,
+            "hits" => Operand::Hits,
+            _ if token.len() == 2 && token.starts_with('v') => match u8::from_str_radix(&token[1..], 16) {
+                Ok(x) => Operand::V(x as usize),
+                Err(_) => return Err(self.error(format!("unknown register `{}`", token))),
+            },
+            _ => {
+                let parsed = match token.strip_prefix("0x") {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => token.parse(),
+                };
+                match parsed {
+                    Ok(value) => Operand::Number(value),
+                    Err(_) => return Err(self.error(format!("expected a value, found `{}`", token))),
+                }
+            },
+        };
+        self.position += 1;
+        Ok(Expr::Operand(operand))
+    }
+}
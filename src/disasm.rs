@@ -0,0 +1,256 @@
+//! Disassembler turning a ROM back into source for `asm`.
+//!
+//! Code is found by following the control flow from the entry point, so data
+//! mixed with instructions is not mistaken for code. Bytes drawn as sprites
+//! (an `LD I` followed by a `DRW` on the same path) are printed one row per
+//! line with a preview of their pixels; any other byte is printed as raw data.
+
+use std::collections::BTreeMap;
+
+use crate::chip::APPLICATION_MEMORY_LOCATION;
+use crate::opcodes::Instruction;
+
+// Operands and comments are aligned on this column.
+const COMMENT_COLUMN: usize = 32;
+// Raw data bytes printed per `db` line.
+const DATA_BYTES_PER_LINE: usize = 4;
+
+/// Why an address got a label; the strongest reason names it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Data,
+    Sprite,
+    Label,
+    Sub,
+}
+
+impl LabelKind {
+    fn prefix(self) -> &'static str {
+        match self {
+            LabelKind::Data => "data",
+            LabelKind::Sprite => "sprite",
+            LabelKind::Label => "label",
+            LabelKind::Sub => "sub",
+        }
+    }
+}
+
+struct Analysis<'a> {
+    rom: &'a [u8],
+    origin: usize,
+    // Decoded instruction starting at each offset of the ROM.
+    instructions: Vec<Option<Instruction>>,
+    // Whether each byte belongs to an instruction.
+    code: Vec<bool>,
+    // Pixel width of the sprite each byte belongs to, 0 if none.
+    sprite_width: Vec<u8>,
+    labels: BTreeMap<usize, LabelKind>,
+}
+
+/// Disassembles a ROM loaded at 0x200.
+pub fn disassemble(rom: &[u8]) -> String {
+    let mut analysis = Analysis {
+        rom,
+        origin: APPLICATION_MEMORY_LOCATION,
+        instructions: vec![None; rom.len()],
+        code: vec![false; rom.len()],
+        sprite_width: vec![0; rom.len()],
+        labels: BTreeMap::new(),
+    };
+    let sprites = analysis.trace_code();
+    analysis.mark_sprites(&sprites);
+    analysis.drop_unplaceable_labels();
+    analysis.listing()
+}
+
+impl<'a> Analysis<'a> {
+    fn offset(&self, addr: usize) -> Option<usize> {
+        addr.checked_sub(self.origin).filter(|offset| *offset < self.rom.len())
+    }
+
+    fn add_label(&mut self, addr: usize, kind: LabelKind) {
+        let label = self.labels.entry(addr).or_insert(kind);
+        *label = (*label).max(kind);
+    }
+
+    fn decode(&self, offset: usize) -> Option<Instruction> {
+        Instruction::decode_at(self.rom, offset).ok()
+            .filter(|instruction| offset + instruction.size() <= self.rom.len())
+    }
+
+    /// Follows every path from the entry point, and returns the sprites
+    /// drawn along the way as (address, DXYN height).
+    fn trace_code(&mut self) -> Vec<(usize, u8)> {
+        let mut sprites = Vec::new();
+        // (address, value of I known on the path leading there)
+        let mut pending = vec![(self.origin, None)];
+
+        while let Some((mut addr, mut i)) = pending.pop() {
+            while let Some(offset) = self.offset(addr) {
+                if self.instructions[offset].is_some() {
+                    break;
+                }
+                let instruction = match self.decode(offset) {
+                    Some(instruction) => instruction,
+                    None => break,
+                };
+                self.instructions[offset] = Some(instruction);
+                for byte in &mut self.code[offset..offset + instruction.size()] {
+                    *byte = true;
+                }
+                let next = addr + instruction.size();
+
+                match instruction {
+                    Instruction::Jump { nnn } => {
+                        self.add_label(nnn as usize, LabelKind::Label);
+                        pending.push((nnn as usize, i));
+                        break;
+                    },
+                    Instruction::JumpV0 { nnn } => {
+                        // The jump table is indexed at runtime, only its start is known.
+                        self.add_label(nnn as usize, LabelKind::Label);
+                        break;
+                    },
+                    Instruction::Ret | Instruction::Exit => break,
+                    Instruction::Call { nnn } => {
+                        self.add_label(nnn as usize, LabelKind::Sub);
+                        pending.push((nnn as usize, None));
+                        // The subroutine may change I.
+                        i = None;
+                    },
+                    Instruction::SkipEqImm { .. } | Instruction::SkipNeImm { .. }
+                    | Instruction::SkipEqReg { .. } | Instruction::SkipNeReg { .. }
+                    | Instruction::SkipKey { .. } | Instruction::SkipNotKey { .. } => {
+                        let skipped = self.offset(next).and_then(|offset| self.decode(offset));
+                        pending.push((next + skipped.map_or(2, |skipped| skipped.size()), i));
+                    },
+                    Instruction::LoadI { nnn } => {
+                        self.add_label(nnn as usize, LabelKind::Data);
+                        i = Some(nnn as usize);
+                    },
+                    Instruction::LoadILong { nnnn } => {
+                        self.add_label(nnnn as usize, LabelKind::Data);
+                        i = Some(nnnn as usize);
+                    },
+                    Instruction::AddI { .. } | Instruction::LoadFont { .. } | Instruction::LoadBigFont { .. }
+                    | Instruction::StoreRegs { .. } | Instruction::LoadRegs { .. } => i = None,
+                    Instruction::Draw { n, .. } => {
+                        if let Some(sprite) = i {
+                            sprites.push((sprite, n));
+                        }
+                    },
+                    _ => {},
+                }
+                addr = next;
+            }
+        }
+        sprites
+    }
+
+    fn mark_sprites(&mut self, sprites: &[(usize, u8)]) {
+        for &(addr, n) in sprites {
+            // DXY0 draws a 16x16 sprite, two bytes per row.
+            let (width, length) = if n == 0 { (16, 32) } else { (8, n as usize) };
+            if let Some(start) = self.offset(addr) {
+                if self.code[start] {
+                    continue;
+                }
+                self.add_label(addr, LabelKind::Sprite);
+                let end = (start + length).min(self.rom.len());
+                for offset in start..end {
+                    if !self.code[offset] && self.sprite_width[offset] == 0 {
+                        self.sprite_width[offset] = width;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Labels are only kept where the listing can define them: inside the
+    /// ROM, and not in the middle of an instruction.
+    fn drop_unplaceable_labels(&mut self) {
+        let placeable: Vec<usize> = self.labels.keys().copied()
+            .filter(|addr| match self.offset(*addr) {
+                Some(offset) => !self.code[offset] || self.instructions[offset].is_some(),
+                None => false,
+            })
+            .collect();
+        self.labels.retain(|addr, _| placeable.contains(addr));
+    }
+
+    fn label_name(&self, addr: usize) -> Option<String> {
+        self.labels.get(&addr).map(|kind| format!("{}_{:03X}", kind.prefix(), addr))
+    }
+
+    /// The instruction's mnemonic, with its address operand replaced by a label.
+    fn mnemonic(&self, instruction: &Instruction) -> String {
+        let target = match *instruction {
+            Instruction::Jump { nnn } | Instruction::Call { nnn }
+            | Instruction::LoadI { nnn } | Instruction::JumpV0 { nnn } => nnn as usize,
+            Instruction::LoadILong { nnnn } => nnnn as usize,
+            _ => return instruction.to_string(),
+        };
+        let text = instruction.to_string();
+        match (self.label_name(target), text.rfind(' ')) {
+            (Some(label), Some(operand)) => format!("{} {}", &text[..operand], label),
+            _ => text,
+        }
+    }
+
+    fn listing(&self) -> String {
+        let mut out = String::new();
+
+        let mut offset = 0;
+        while offset < self.rom.len() {
+            let addr = self.origin + offset;
+            if let Some(label) = self.label_name(addr) {
+                if offset != 0 {
+                    out.push('\n');
+                }
+                out += &format!("{}:\n", label);
+            }
+
+            if let Some(instruction) = self.instructions[offset] {
+                let bytes: Vec<_> = self.rom[offset..offset + instruction.size()].chunks(2)
+                    .map(|word| word.iter().map(|byte| format!("{:02X}", byte)).collect::<String>())
+                    .collect();
+                out += &line(self.mnemonic(&instruction), format!("0x{:03X}  {}", addr, bytes.join(" ")));
+                offset += instruction.size();
+            } else if self.sprite_width[offset] != 0 {
+                let row_length = (self.sprite_width[offset] / 8) as usize;
+                let row = self.data_run(offset, row_length);
+                let preview: String = row.iter()
+                    .flat_map(|byte| (0..8).rev().map(move |bit| if byte >> bit & 1 != 0 { '#' } else { '.' }))
+                    .collect();
+                out += &line(byte_directive(row), format!("0x{:03X}  {}", addr, preview));
+                offset += row.len();
+            } else {
+                let run = self.data_run(offset, DATA_BYTES_PER_LINE);
+                out += &line(byte_directive(run), format!("0x{:03X}", addr));
+                offset += run.len();
+            }
+        }
+        out
+    }
+
+    /// Up to `max` data bytes from `offset` of the same kind, stopping at
+    /// labels and code.
+    fn data_run(&self, offset: usize, max: usize) -> &[u8] {
+        let kind = self.sprite_width[offset];
+        let mut end = offset + 1;
+        while end < self.rom.len() && end - offset < max && !self.code[end]
+            && self.sprite_width[end] == kind && !self.labels.contains_key(&(self.origin + end)) {
+            end += 1;
+        }
+        &self.rom[offset..end]
+    }
+}
+
+fn line(code: String, comment: String) -> String {
+    format!("    {:<width$}; {}\n", code, comment, width = COMMENT_COLUMN - 4)
+}
+
+fn byte_directive(bytes: &[u8]) -> String {
+    let bytes: Vec<_> = bytes.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+    format!("db {}", bytes.join(", "))
+}
@@ -0,0 +1,39 @@
+use std::error;
+use std::fmt;
+
+/// Errors raised while emulating a single instruction.
+///
+/// Every variant carries the address of the faulting instruction so frontends
+/// can report where the program went wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChipError {
+    /// The opcode does not match any known instruction.
+    UnknownOpcode { pc: usize, opcode: u16 },
+    /// The opcode is valid CHIP-8 but cannot be emulated (0NNN machine code calls).
+    UnsupportedOpcode { pc: usize, opcode: u16 },
+    /// A subroutine call was made with a full stack.
+    StackOverflow { pc: usize },
+    /// A return was made with an empty stack.
+    StackUnderflow { pc: usize },
+    /// The instruction accessed memory outside of the address space.
+    MemoryOutOfBounds { pc: usize, addr: usize },
+}
+
+impl fmt::Display for ChipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChipError::UnknownOpcode { pc, opcode } =>
+                write!(f, "[{:04X}] unknown opcode {:04X}", pc, opcode),
+            ChipError::UnsupportedOpcode { pc, opcode } =>
+                write!(f, "[{:04X}] unsupported opcode {:04X} (RCA 1802 calls are not supported)", pc, opcode),
+            ChipError::StackOverflow { pc } =>
+                write!(f, "[{:04X}] stack overflow", pc),
+            ChipError::StackUnderflow { pc } =>
+                write!(f, "[{:04X}] stack underflow: return without a matching call", pc),
+            ChipError::MemoryOutOfBounds { pc, addr } =>
+                write!(f, "[{:04X}] memory access out of bounds at {:04X}", pc, addr),
+        }
+    }
+}
+
+impl error::Error for ChipError {}
@@ -0,0 +1,271 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant, SystemTime};
+
+use chip8::chip::{Chip, FRAMES_PER_SECOND};
+use chip8::debugger::Resume;
+use chip8::keymap::Keymap;
+use chip8::rewind::RewindBuffer;
+use chip8::scheduler::{FrameScheduler, Pacing};
+
+use crate::{assemble_source, finish_trace, Debugging, MovieMode, Options, ERROR_EMULATION_FAILED, ERROR_INPUT_LOADING_FAILED};
+use crate::buzzer::{self, Buzzer};
+use crate::runtime_manager::{Action, RuntimeManager};
+
+const REAL_WINDOW_WIDTH: u32 = 640;
+const REAL_WINDOW_HEIGHT: u32 = 320;
+
+const FAST_FORWARD_SPEED: f64 = 4.0;
+const SLOW_MOTION_SPEED: f64 = 0.25;
+// Minimum delay between two "can't keep up" warnings.
+const LAG_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+// Frames between two checks of the watched source for changes.
+const WATCH_INTERVAL_FRAMES: u32 = 15;
+
+fn state_path(game: &str, slot: u8) -> String {
+    format!("{}.state{}", game, slot)
+}
+
+/// The keymap config applied to every game.
+fn global_keymap_path() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config.join("chip8").join("keymap.cfg"))
+}
+
+/// The `--keymap` preset, adjusted by the global config and then by the
+/// game's own `GAME.keymap`, when they exist.
+fn load_keymap(options: &Options) -> Keymap {
+    let mut keymap = Keymap::preset(&options.keymap).expect("--keymap was validated");
+    let game_config = PathBuf::from(format!("{}.keymap", options.game));
+    for path in global_keymap_path().into_iter().chain(Some(game_config)) {
+        let applied = match fs::read_to_string(&path) {
+            Ok(config) => keymap.apply(&config).map_err(|e| e.to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => Err(e.to_string()),
+        };
+        match applied {
+            Ok(()) => eprintln!("Loaded keymap {}", path.display()),
+            Err(e) => {
+                eprintln!("Could not load keymap {}: {}", path.display(), e);
+                process::exit(ERROR_INPUT_LOADING_FAILED);
+            },
+        }
+    }
+    keymap
+}
+
+fn save_state(chip: &Chip, game: &str, slot: u8) -> Result<(), io::Error> {
+    fs::write(state_path(game, slot), chip.save_state())
+}
+
+fn load_state(chip: &mut Chip, game: &str, slot: u8) -> Result<(), io::Error> {
+    let state = fs::read(state_path(game, slot))?;
+    chip.load_state(&state).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Writes out the movie being recorded, if any.
+fn finish_movie(movie: &MovieMode) {
+    if let MovieMode::Record { path, movie } = movie {
+        match fs::write(path, movie.to_bytes()) {
+            Ok(()) => println!("Recorded {} frames to {}", movie.frames.len(), path),
+            Err(e) => eprintln!("Could not write movie {}: {}", path, e),
+        }
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Re-assembles the watched source and restarts it, keeping the old program
+/// running if it does not assemble.
+fn reload(chip: &mut Chip, source: &str) {
+    let assembly = match assemble_source(Path::new(source)) {
+        Ok(assembly) => assembly,
+        Err(e) => return eprintln!("{}", e),
+    };
+    match chip.load_rom(&assembly.rom) {
+        Ok(()) => {
+            chip.reset();
+            println!("Reloaded {} ({} bytes)", source, assembly.rom.len());
+        },
+        Err(e) => eprintln!("Could not reload {}: {}", source, e),
+    }
+}
+
+pub fn run(mut chip: Chip, options: &Options, mut movie: MovieMode, mut debugging: Debugging) {
+    let game = options.game.as_str();
+    let cycles_per_frame = options.cycles_per_frame();
+    let speed = options.speed;
+
+    // Initialize various runtime elements
+    let mut runtime_manager = RuntimeManager::new(load_keymap(options));
+    let v = sfml::system::Vector2u::new(REAL_WINDOW_WIDTH, REAL_WINDOW_HEIGHT);
+    runtime_manager.window.set_size(v);
+    runtime_manager.draw_graphics(chip.graphics(), chip.width(), chip.height());
+    let sound_buffer = buzzer::load(&options.tone);
+    if sound_buffer.is_none() {
+        eprintln!("Could not create the sound buffer, running without sound");
+    }
+    let mut buzzer = sound_buffer.as_ref().map(Buzzer::new);
+
+    let mut scheduler = FrameScheduler::new(FRAMES_PER_SECOND);
+    scheduler.set_speed(speed);
+    let (mut fast_forward, mut slow_motion) = (false, false);
+    let mut frames_behind = 0;
+    let mut last_lag_report: Option<Instant> = None;
+    let mut rewind = RewindBuffer::new(options.rewind_seconds.saturating_mul(FRAMES_PER_SECOND) as usize,
+                                       options.rewind_megabytes.saturating_mul(1024 * 1024));
+    let mut rewinding = false;
+    let mut paused = false;
+    let mut replay_frame = 0;
+    let mut desynchronised = false;
+    let mut source_modified = modified(game);
+    let mut frame: u32 = 0;
+
+    loop {
+        frame = frame.wrapping_add(1);
+        if options.watch && frame.is_multiple_of(WATCH_INTERVAL_FRAMES) && modified(game) != source_modified {
+            source_modified = modified(game);
+            reload(&mut chip, game);
+            rewind.clear();
+            chip.draw_flag = 1;
+        }
+
+        for action in runtime_manager.handle_events(&mut chip) {
+            match action {
+                Action::FastForward(held) => fast_forward = held,
+                Action::SlowMotion(held) => slow_motion = held,
+                Action::Rewind(held) => rewinding = held,
+                Action::Pause => {
+                    paused = !paused;
+                    println!("{}", if paused { "Paused" } else { "Resumed" });
+                },
+                Action::Reset if !matches!(movie, MovieMode::Off) =>
+                    eprintln!("Resetting is disabled while recording or replaying a movie"),
+                Action::Reset => {
+                    chip.reset();
+                    rewind.clear();
+                    chip.draw_flag = 1;
+                    println!("Reset {}", game);
+                },
+                Action::Mute => if let Some(buzzer) = &mut buzzer {
+                    println!("Sound {}", if buzzer.toggle_mute() { "muted" } else { "on" });
+                },
+                Action::Break => if !debugging.request_break() {
+                    eprintln!("Start with --debug or --gdb to break into the debugger");
+                },
+                Action::SaveState(slot) => match save_state(&chip, game, slot) {
+                    Ok(()) => println!("Saved state to {}", state_path(game, slot)),
+                    Err(e) => eprintln!("Could not save state to {}: {}", state_path(game, slot), e),
+                },
+                Action::LoadState(_) if !matches!(movie, MovieMode::Off) =>
+                    eprintln!("Loading states is disabled while recording or replaying a movie"),
+                Action::LoadState(slot) => match load_state(&mut chip, game, slot) {
+                    Ok(()) => {
+                        println!("Loaded state from {}", state_path(game, slot));
+                        rewind.clear();
+                        runtime_manager.draw_graphics(chip.graphics(), chip.width(), chip.height());
+                    },
+                    Err(e) => eprintln!("Could not load state from {}: {}", state_path(game, slot), e),
+                },
+            }
+        }
+        scheduler.set_speed(match (fast_forward, slow_motion) {
+            (true, _) => speed * FAST_FORWARD_SPEED,
+            (false, true) => speed * SLOW_MOTION_SPEED,
+            (false, false) => speed,
+        });
+
+        let replaying = matches!(&movie, MovieMode::Replay(recorded) if replay_frame < recorded.frames.len());
+        if paused {
+            // Nothing runs; events are still handled above so the game can be resumed.
+        } else if rewinding && !replaying {
+            // Step one frame back per tick; once the history runs out the game stays paused.
+            if let Some(state) = rewind.pop() {
+                if let Err(e) = chip.load_state(&state) {
+                    eprintln!("Could not rewind: {}", e);
+                    rewind.clear();
+                }
+                chip.draw_flag = 1;
+                // Rewound frames are re-recorded from here on.
+                if let MovieMode::Record { movie, .. } = &mut movie {
+                    movie.frames.pop();
+                }
+            }
+        } else {
+            let keys = match &movie {
+                MovieMode::Replay(recorded) if replaying => recorded.frames[replay_frame].keys,
+                _ => runtime_manager.keypad(),
+            };
+            chip.set_keys(keys);
+
+            let result = debugging.run_frame(&mut chip, cycles_per_frame, &mut |chip| {
+                runtime_manager.draw_graphics(chip.graphics(), chip.width(), chip.height());
+            });
+            match result {
+                Ok(Resume::Continue) => {},
+                Ok(Resume::Quit) => {
+                    finish_movie(&movie);
+                    finish_trace(&mut chip, options);
+                    process::exit(0);
+                },
+                Err(e) => {
+                    eprintln!("Emulation stopped: {}", e);
+                    finish_movie(&movie);
+                    finish_trace(&mut chip, options);
+                    runtime_manager.show_error(&e);
+                    process::exit(ERROR_EMULATION_FAILED);
+                },
+            }
+            rewind.push(chip.save_state());
+
+            match &mut movie {
+                MovieMode::Record { movie, .. } => movie.record_frame(keys, &chip),
+                MovieMode::Replay(recorded) if replaying => {
+                    if !desynchronised && !recorded.check_frame(replay_frame, &chip) {
+                        eprintln!("Replay desynchronised at frame {}", replay_frame);
+                        desynchronised = true;
+                    }
+                    replay_frame += 1;
+                    if replay_frame == recorded.frames.len() {
+                        println!("Replay finished after {} frames{}, handing control back to the keyboard",
+                                 replay_frame, if desynchronised { " (desynchronised)" } else { "" });
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        if let Some(buzzer) = &mut buzzer {
+            // Rewound states are shown silently.
+            buzzer.update(chip.is_sounding() && !rewinding && !paused);
+        }
+
+        if chip.exit_flag == 1 {
+            finish_movie(&movie);
+            finish_trace(&mut chip, options);
+            process::exit(0);
+        }
+
+        if chip.draw_flag != 0 || chip.clear_flag != 0 {
+            runtime_manager.draw_graphics(chip.graphics(), chip.width(), chip.height());
+            chip.draw_flag = 0;
+            chip.clear_flag = 0;
+        }
+
+        if let Pacing::Behind { frames_behind: behind } = scheduler.wait_for_next_frame() {
+            frames_behind += behind;
+            if last_lag_report.is_none_or(|at| at.elapsed() >= LAG_REPORT_INTERVAL) {
+                eprintln!("warning: host is too slow for {} instructions per frame at {}x speed, {} frames behind",
+                          cycles_per_frame, scheduler.speed(), frames_behind);
+                last_lag_report = Some(Instant::now());
+                frames_behind = 0;
+            }
+        }
+    }
+}
@@ -0,0 +1,476 @@
+//! GDB Remote Serial Protocol stub, so GDB (or any RSP client) can debug a
+//! running `Chip` over TCP.
+//!
+//! The target describes its registers in `target.xml`: `v0`-`vf`, then `i`,
+//! `pc`, `sp`, `dt` and `st`. In `g`/`G`/`p`/`P` packets `i` and `pc` are 16
+//! bits, little-endian, the others 8 bits. Memory is the 64 KiB address
+//! space. Software breakpoints (`Z0`/`Z1`) and watchpoints (`Z2` writes, `Z3`
+//! reads, `Z4` both) are supported, as are `s`, `c`, the `0x03` interrupt,
+//! `D` and `k`.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::chip::{Chip, MEMORY_SIZE};
+use crate::debugger::{Access, Debugger, Resume, Stop, Watchpoint};
+use crate::error::ChipError;
+
+const INTERRUPT: u8 = 0x03;
+// Largest packet accepted, advertised in `qSupported`.
+const PACKET_SIZE: usize = 0x1000;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.cpu">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+// Register numbers after V0-VF, and the size in bytes of every register.
+const REGISTER_I: usize = 16;
+const REGISTER_PC: usize = 17;
+const REGISTER_SP: usize = 18;
+const REGISTER_DT: usize = 19;
+const REGISTER_ST: usize = 20;
+const NUMBER_OF_REGISTERS: usize = REGISTER_ST + 1;
+
+fn register_size(register: usize) -> usize {
+    match register {
+        REGISTER_I | REGISTER_PC => 2,
+        _ => 1,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Halted,
+    Running,
+    // The client went away: the program runs on without breakpoints.
+    Detached,
+}
+
+/// A connection to one GDB client.
+pub struct GdbStub {
+    stream: TcpStream,
+    debugger: Debugger,
+    state: State,
+    // Bytes received but not handled yet.
+    input: Vec<u8>,
+    // Last packet sent, resent if the client does not acknowledge it.
+    last_packet: String,
+    // Reply to `?`.
+    last_stop: String,
+    break_requested: bool,
+}
+
+impl GdbStub {
+    /// Waits for a client to connect on `listener`. The program starts
+    /// halted, before its first instruction.
+    pub fn accept(listener: &TcpListener, debugger: Debugger) -> io::Result<GdbStub> {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        Ok(GdbStub {
+            stream,
+            debugger,
+            state: State::Halted,
+            input: Vec::new(),
+            last_packet: String::new(),
+            last_stop: format!("S{:02x}", SIGTRAP),
+            break_requested: false,
+        })
+    }
+
+    /// Halts the program before its next instruction, as the `0x03` interrupt does.
+    pub fn request_break(&mut self) {
+        self.break_requested = true;
+    }
+
+    /// Runs one frame under the client's control: while the program is
+    /// halted this serves the client's requests until it resumes it.
+    ///
+    /// Emulation errors are reported to the client as signals and halt the
+    /// program; they are only returned once the client is gone. Losing the
+    /// connection detaches the client, like `D`.
+    pub fn run_frame(&mut self, chip: &mut Chip) -> Result<Resume, ChipError> {
+        loop {
+            if self.state == State::Running && (self.break_requested || self.interrupted()) {
+                self.halt(format!("T{:02x}", SIGINT));
+            }
+            self.break_requested = false;
+            if self.state == State::Halted && self.serve(chip) == Resume::Quit {
+                return Ok(Resume::Quit);
+            }
+
+            match self.debugger.run_frame(chip) {
+                Ok(Some(stop)) => self.halt(stop_reply(stop)),
+                Ok(None) => {
+                    if chip.exit_flag != 0 && self.state != State::Detached {
+                        self.send(&format!("W{:02x}", 0));
+                        self.detach();
+                    }
+                    return Ok(Resume::Continue);
+                },
+                Err(e) if self.state == State::Detached => return Err(e),
+                Err(e) => self.halt(signal_reply(&e)),
+            }
+        }
+    }
+
+    fn halt(&mut self, reply: String) {
+        self.send(&reply);
+        self.last_stop = reply;
+        if self.state != State::Detached {
+            self.state = State::Halted;
+        }
+    }
+
+    fn detach(&mut self) {
+        self.state = State::Detached;
+        self.debugger.clear_breakpoints();
+        self.debugger.clear_watchpoints();
+    }
+
+    /// Handles requests until the client resumes or kills the program.
+    fn serve(&mut self, chip: &mut Chip) -> Resume {
+        while self.state == State::Halted {
+            let packet = match self.receive() {
+                Some(packet) => packet,
+                None => {
+                    self.detach();
+                    break;
+                },
+            };
+            match packet.as_bytes().first() {
+                Some(b'c') => {
+                    if let Some(addr) = parse_hex(&packet[1..]) {
+                        chip.set_pc(addr);
+                    }
+                    self.debugger.resume(chip);
+                    self.state = State::Running;
+                },
+                Some(b's') => {
+                    if let Some(addr) = parse_hex(&packet[1..]) {
+                        chip.set_pc(addr);
+                    }
+                    let reply = match self.debugger.step_many(chip, 1) {
+                        Ok(_) if chip.exit_flag != 0 => format!("W{:02x}", 0),
+                        Ok(Some(stop @ Stop::Watchpoint { .. })) => stop_reply(stop),
+                        Ok(_) => format!("T{:02x}", SIGTRAP),
+                        Err(e) => signal_reply(&e),
+                    };
+                    self.halt(reply);
+                },
+                Some(b'D') => {
+                    self.send("OK");
+                    self.detach();
+                },
+                Some(b'k') => return Resume::Quit,
+                _ => {
+                    let reply = self.handle(chip, &packet);
+                    self.send(&reply);
+                },
+            }
+        }
+        Resume::Continue
+    }
+
+    /// Reply to a request that leaves the program halted.
+    fn handle(&mut self, chip: &mut Chip, packet: &str) -> String {
+        if !packet.is_char_boundary(1) {
+            return String::new();
+        }
+        let error = "E01".to_string();
+        let ok = "OK".to_string();
+        let (command, args) = packet.split_at(1);
+        match command {
+            "?" => self.last_stop.clone(),
+            "g" => (0..NUMBER_OF_REGISTERS).map(|register| read_register(chip, register)).collect(),
+            "G" => {
+                let mut rest = args;
+                for register in 0..NUMBER_OF_REGISTERS {
+                    let size = register_size(register) * 2;
+                    if rest.len() < size || !write_register(chip, register, &rest[..size]) {
+                        return error;
+                    }
+                    rest = &rest[size..];
+                }
+                ok
+            },
+            "p" => match parse_hex(args) {
+                Some(register) if register < NUMBER_OF_REGISTERS => read_register(chip, register),
+                _ => error,
+            },
+            "P" => match args.split_once('=') {
+                Some((register, value)) => match parse_hex(register) {
+                    Some(register) if register < NUMBER_OF_REGISTERS && write_register(chip, register, value) => ok,
+                    _ => error,
+                },
+                None => error,
+            },
+            "m" => match parse_pair(args) {
+                Some((addr, length)) if addr < chip.memory().len() => {
+                    let end = addr.saturating_add(length).min(chip.memory().len());
+                    chip.memory()[addr..end].iter().map(|byte| format!("{:02x}", byte)).collect()
+                },
+                _ => error,
+            },
+            "M" => match args.split_once(':') {
+                Some((range, data)) => match (parse_pair(range), decode_hex(data)) {
+                    (Some((addr, length)), Some(bytes)) if bytes.len() == length => {
+                        match chip.set_memory(addr, &bytes) {
+                            Ok(()) => ok,
+                            Err(_) => error,
+                        }
+                    },
+                    _ => error,
+                },
+                None => error,
+            },
+            "Z" | "z" => self.set_stop_point(command == "Z", args).map_or(error, |()| ok),
+            "H" | "T" => ok,
+            "q" => query(args),
+            _ => String::new(),
+        }
+    }
+
+    /// Handles `Z`/`z` packets: `TYPE,ADDR,KIND`.
+    fn set_stop_point(&mut self, insert: bool, args: &str) -> Option<()> {
+        let mut fields = args.split(',');
+        let kind = fields.next()?;
+        let addr = parse_hex(fields.next()?)?;
+        let length = parse_hex(fields.next()?)?.max(1);
+        let (reads, writes) = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.debugger.add_breakpoint(addr);
+                } else {
+                    self.debugger.remove_breakpoint(addr);
+                }
+                return Some(());
+            },
+            "2" => (false, true),
+            "3" => (true, false),
+            "4" => (true, true),
+            _ => return None,
+        };
+        if addr >= MEMORY_SIZE {
+            return None;
+        }
+        if insert {
+            let end = addr.saturating_add(length).min(MEMORY_SIZE);
+            self.debugger.add_watchpoint(Watchpoint { start: addr, end, reads, writes });
+        } else {
+            self.debugger.remove_watchpoint(addr);
+        }
+        Some(())
+    }
+
+    /// Whether the client sent an interrupt, without waiting for one.
+    fn interrupted(&mut self) -> bool {
+        if self.stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let alive = self.fill();
+        self.stream.set_nonblocking(false).ok();
+        if !alive {
+            self.detach();
+            return false;
+        }
+        match self.input.iter().position(|byte| *byte == INTERRUPT) {
+            Some(position) => {
+                self.input.remove(position);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Reads what is available into `input`, returning false once the connection is closed.
+    fn fill(&mut self) -> bool {
+        let mut buffer = [0; 1024];
+        match self.stream.read(&mut buffer) {
+            Ok(0) => false,
+            Ok(length) => {
+                self.input.extend_from_slice(&buffer[..length]);
+                true
+            },
+            Err(e) => e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::Interrupted,
+        }
+    }
+
+    /// Waits for the next packet and acknowledges it; None once the connection is closed.
+    fn receive(&mut self) -> Option<String> {
+        loop {
+            // Acknowledgements of our packets, and interrupts of an already halted program.
+            while let Some(&byte) = self.input.first() {
+                match byte {
+                    b'$' => break,
+                    b'-' => {
+                        let packet = self.last_packet.clone();
+                        self.write(&packet);
+                    },
+                    _ => {},
+                }
+                self.input.remove(0);
+            }
+
+            if let Some(end) = self.input.iter().position(|byte| *byte == b'#') {
+                if self.input.len() >= end + 3 {
+                    let packet: Vec<u8> = self.input.drain(..end + 3).collect();
+                    let data = &packet[1..end];
+                    let checksum = std::str::from_utf8(&packet[end + 1..]).ok()
+                        .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+                    let valid = checksum == Some(data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)));
+                    let acknowledged = self.stream.write_all(if valid { b"+" } else { b"-" }).is_ok();
+                    if valid && acknowledged {
+                        return Some(String::from_utf8_lossy(data).into_owned());
+                    }
+                    if !acknowledged {
+                        return None;
+                    }
+                    continue;
+                }
+            }
+            if self.input.len() > PACKET_SIZE * 2 + 4 {
+                // Not a packet we can ever handle; drop it.
+                self.input.clear();
+            }
+            if !self.fill() {
+                return None;
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        let packet = format!("${}#{:02x}", data, checksum);
+        self.write(&packet);
+        self.last_packet = packet;
+    }
+
+    fn write(&mut self, packet: &str) {
+        if self.state != State::Detached && self.stream.write_all(packet.as_bytes()).is_err() {
+            self.detach();
+        }
+    }
+}
+
+fn stop_reply(stop: Stop) -> String {
+    match stop {
+        Stop::Breakpoint(_) => format!("T{:02x}swbreak:;", SIGTRAP),
+        Stop::StepOver(_) => format!("T{:02x}", SIGTRAP),
+        Stop::Watchpoint { addr, access, .. } => {
+            let kind = match access {
+                Access::Read => "rwatch",
+                Access::Write => "watch",
+            };
+            format!("T{:02x}{}:{:x};", SIGTRAP, kind, addr)
+        },
+    }
+}
+
+fn signal_reply(error: &ChipError) -> String {
+    let signal = match error {
+        ChipError::UnknownOpcode { .. } | ChipError::UnsupportedOpcode { .. } => SIGILL,
+        _ => SIGSEGV,
+    };
+    format!("T{:02x}", signal)
+}
+
+/// Reply to the `q` queries GDB sends while connecting.
+fn query(query: &str) -> String {
+    let (name, args) = query.split_once(':').unwrap_or((query, ""));
+    match name {
+        "Supported" => format!("PacketSize={:x};qXfer:features:read+;swbreak+", PACKET_SIZE),
+        "Attached" => "1".to_string(),
+        "C" => "QC1".to_string(),
+        "fThreadInfo" => "m1".to_string(),
+        "sThreadInfo" => "l".to_string(),
+        "Xfer" => match args.strip_prefix("features:read:target.xml:").and_then(parse_pair) {
+            Some((offset, length)) => {
+                let xml = TARGET_XML.as_bytes();
+                let start = offset.min(xml.len());
+                let end = start.saturating_add(length).min(xml.len());
+                let more = if end < xml.len() { "m" } else { "l" };
+                format!("{}{}", more, String::from_utf8_lossy(&xml[start..end]))
+            },
+            None => "E00".to_string(),
+        },
+        _ => String::new(),
+    }
+}
+
+fn read_register(chip: &Chip, register: usize) -> String {
+    let value = match register {
+        0..=15 => chip.registers()[register] as usize,
+        REGISTER_I => chip.i() as usize,
+        REGISTER_PC => chip.pc(),
+        REGISTER_SP => chip.sp(),
+        REGISTER_DT => chip.delay_timer() as usize,
+        _ => chip.sound_timer() as usize,
+    };
+    (0..register_size(register)).map(|byte| format!("{:02x}", value >> (byte * 8) & 0xFF)).collect()
+}
+
+/// Sets a register from its little-endian hex encoding, returning false if it is invalid.
+fn write_register(chip: &mut Chip, register: usize, hex: &str) -> bool {
+    let value = match decode_hex(hex) {
+        Some(bytes) if bytes.len() == register_size(register) =>
+            bytes.iter().rev().fold(0usize, |value, byte| value << 8 | *byte as usize),
+        _ => return false,
+    };
+    match register {
+        0..=15 => chip.set_register(register, value as u8),
+        REGISTER_I => chip.set_i(value as u32),
+        REGISTER_PC => chip.set_pc(value),
+        REGISTER_SP if value <= chip.stack().len() => chip.set_sp(value),
+        REGISTER_SP => return false,
+        REGISTER_DT => chip.set_delay_timer(value as u32),
+        _ => chip.set_sound_timer(value as u32),
+    }
+    true
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+/// Parses `ADDR,LENGTH`.
+fn parse_pair(text: &str) -> Option<(usize, usize)> {
+    let (first, second) = text.split_once(',')?;
+    Some((parse_hex(first)?, parse_hex(second)?))
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
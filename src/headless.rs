@@ -0,0 +1,136 @@
+//! Running a `Chip` without any frontend, for automated testing.
+
+use std::error;
+use std::fmt;
+
+use crate::chip::Chip;
+use crate::error::ChipError;
+
+/// Error in an input script, with the 1-based line it was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for ScriptError {}
+
+/// Keypad input scripted frame by frame.
+///
+/// Each line holds a frame number followed by the CHIP-8 keys (hex digits
+/// separated by spaces) held from that frame on, or `-` for none:
+///
+/// ```text
+/// # Start the game, then hold 4 and 6 together.
+/// 10  5
+/// 12  -
+/// 90  4 6
+/// ```
+///
+/// Frame numbers must increase. Blank lines and `#` comments are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputScript {
+    // (first frame, keypad mask), sorted by frame.
+    changes: Vec<(u32, u16)>,
+}
+
+impl InputScript {
+    pub fn parse(source: &str) -> Result<InputScript, ScriptError> {
+        let mut changes: Vec<(u32, u16)> = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let error = |message: String| ScriptError { line: index + 1, message };
+            let mut fields = line.split('#').next().unwrap().split_whitespace();
+            let frame = match fields.next() {
+                Some(frame) => frame.parse::<u32>().map_err(|_| error(format!("invalid frame number `{}`", frame)))?,
+                None => continue,
+            };
+            if changes.last().is_some_and(|(last, _)| *last >= frame) {
+                return Err(error(format!("frame {} does not come after the previous line", frame)));
+            }
+
+            let mut keys = 0;
+            for field in fields {
+                if field == "-" {
+                    continue;
+                }
+                match u8::from_str_radix(field, 16) {
+                    Ok(key) if key < 16 && field.len() == 1 => keys |= 1 << key,
+                    _ => return Err(error(format!("invalid key `{}`, expected 0-F or -", field))),
+                }
+            }
+            changes.push((frame, keys));
+        }
+        Ok(InputScript { changes })
+    }
+
+    /// Keypad mask held during `frame`.
+    pub fn keys_at(&self, frame: u32) -> u16 {
+        self.changes.iter()
+            .take_while(|(first, _)| *first <= frame)
+            .last()
+            .map_or(0, |(_, keys)| *keys)
+    }
+
+    /// The last frame where the keys change, if they ever do.
+    pub fn last_change(&self) -> Option<u32> {
+        self.changes.last().map(|(frame, _)| *frame)
+    }
+}
+
+/// Runs up to `frames` frames with scripted input, stopping early if the ROM
+/// exits, and returns the number of frames actually run. `on_frame` sees the
+/// chip after every frame, e.g. to capture its audio.
+pub fn run(chip: &mut Chip, frames: u32, cycles_per_frame: usize, input: &InputScript,
+           on_frame: &mut dyn FnMut(&Chip)) -> Result<u32, ChipError> {
+    for frame in 0..frames {
+        chip.set_keys(input.keys_at(frame));
+        chip.run_frame(cycles_per_frame)?;
+        on_frame(chip);
+        if chip.exit_flag != 0 {
+            return Ok(frame + 1);
+        }
+    }
+    Ok(frames)
+}
+
+/// JSON object describing the registers and screen mode of `chip`.
+pub fn registers_json(chip: &Chip) -> String {
+    let list = |values: &mut dyn Iterator<Item = String>| values.collect::<Vec<_>>().join(", ");
+    format!(concat!(
+        "{{\n",
+        "  \"pc\": {},\n",
+        "  \"opcode\": {},\n",
+        "  \"i\": {},\n",
+        "  \"v\": [{}],\n",
+        "  \"sp\": {},\n",
+        "  \"stack\": [{}],\n",
+        "  \"delay_timer\": {},\n",
+        "  \"sound_timer\": {},\n",
+        "  \"keys\": [{}],\n",
+        "  \"hires\": {},\n",
+        "  \"width\": {},\n",
+        "  \"height\": {},\n",
+        "  \"planes\": {},\n",
+        "  \"rom_hash\": \"{:016x}\"\n",
+        "}}\n"),
+        chip.pc(),
+        chip.opcode(),
+        chip.i(),
+        list(&mut chip.registers().iter().map(|v| v.to_string())),
+        chip.sp(),
+        list(&mut chip.stack()[..chip.sp()].iter().map(|address| address.to_string())),
+        chip.delay_timer(),
+        chip.sound_timer(),
+        list(&mut chip.keys().iter().map(|held| held.to_string())),
+        chip.is_hires(),
+        chip.width(),
+        chip.height(),
+        chip.planes(),
+        chip.rom_hash())
+}
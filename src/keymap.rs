@@ -0,0 +1,260 @@
+//! Host keyboard bindings: which keys press the 16 CHIP-8 keys, and which
+//! ones control the emulator.
+//!
+//! Keymaps start from a preset and are adjusted by config files, one
+//! binding per line:
+//!
+//! ```text
+//! # Play with the arrow keys, and keep WASD for the second player.
+//! preset azerty
+//! 5 = Up Z
+//! 8 = Down S
+//! 7 = Left Q
+//! 9 = Right D
+//! pause = P
+//! ```
+//!
+//! The left side is a CHIP-8 key (a hex digit) or a hotkey (`pause`,
+//! `reset`, `save`, `quit`, `fast-forward`, `slow-motion`, `rewind`, `break`,
+//! `mute`), the right side the host keys that trigger it, by their SFML name
+//! (`A`, `Num1`, `Numpad1`, `F1`, `Space`, `SemiColon`...). A line replaces
+//! the keys previously bound to its left side, and a host key triggers only
+//! one thing: binding it again takes it from its earlier binding. `save`
+//! lists the keys of quick-save slots 1, 2 and so on; with Shift held, they
+//! load the slot instead. `preset NAME` starts over from a preset.
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+
+/// Key names, as in SFML's `Key` enum.
+pub const KEY_NAMES: [&str; 101] = [
+    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M",
+    "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X", "Y", "Z",
+    "Num0", "Num1", "Num2", "Num3", "Num4", "Num5", "Num6", "Num7", "Num8", "Num9",
+    "Escape", "LControl", "LShift", "LAlt", "LSystem", "RControl", "RShift", "RAlt", "RSystem", "Menu",
+    "LBracket", "RBracket", "SemiColon", "Comma", "Period", "Quote", "Slash", "BackSlash",
+    "Tilde", "Equal", "Dash", "Space", "Return", "BackSpace", "Tab",
+    "PageUp", "PageDown", "End", "Home", "Insert", "Delete",
+    "Add", "Subtract", "Multiply", "Divide", "Left", "Right", "Up", "Down",
+    "Numpad0", "Numpad1", "Numpad2", "Numpad3", "Numpad4",
+    "Numpad5", "Numpad6", "Numpad7", "Numpad8", "Numpad9",
+    "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12", "F13", "F14", "F15",
+    "Pause",
+];
+
+pub const PRESET_NAMES: [&str; 3] = ["qwerty", "azerty", "dvorak"];
+
+const HOTKEYS: &str = "
+pause = Space
+reset = F9
+save = F1 F2 F3 F4 F5 F6 F7 F8
+quit = Escape
+fast-forward = Tab
+slow-motion = Tilde
+rewind = BackSpace
+break = F12
+mute = M
+";
+
+// The CHIP-8 keys 0-F, laid out over four rows of four host keys.
+const QWERTY: &str = "
+0 = Num1
+1 = Num2
+2 = Num3
+3 = Num4
+4 = Q
+5 = W
+6 = E
+7 = R
+8 = A
+9 = S
+A = D
+B = F
+C = Z
+D = X
+E = C
+F = V
+";
+
+const AZERTY: &str = "
+0 = Num1
+1 = Num2
+2 = Num3
+3 = Num4
+4 = A
+5 = Z
+6 = E
+7 = R
+8 = Q
+9 = S
+A = D
+B = F
+C = W
+D = X
+E = C
+F = V
+";
+
+const DVORAK: &str = "
+0 = Num1
+1 = Num2
+2 = Num3
+3 = Num4
+4 = Quote
+5 = Comma
+6 = Period
+7 = P
+8 = A
+9 = O
+A = E
+B = U
+C = SemiColon
+D = Q
+E = J
+F = K
+";
+
+/// Emulator controls a host key can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    Pause,
+    Reset,
+    /// Quick-save to the numbered slot, or load it with Shift held.
+    Save(u8),
+    Quit,
+    FastForward,
+    SlowMotion,
+    Rewind,
+    Break,
+    Mute,
+}
+
+const HOTKEY_NAMES: [(&str, Hotkey); 9] = [
+    ("pause", Hotkey::Pause),
+    ("reset", Hotkey::Reset),
+    ("save", Hotkey::Save(1)),
+    ("quit", Hotkey::Quit),
+    ("fast-forward", Hotkey::FastForward),
+    ("slow-motion", Hotkey::SlowMotion),
+    ("rewind", Hotkey::Rewind),
+    ("break", Hotkey::Break),
+    ("mute", Hotkey::Mute),
+];
+
+/// What a host key does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    /// Holds the CHIP-8 key 0-F.
+    Keypad(u8),
+    Hotkey(Hotkey),
+}
+
+impl Binding {
+    /// Whether binding `self` to a key replaces `other`, which is the case
+    /// for the same CHIP-8 key or hotkey, and for every save slot.
+    fn replaces(&self, other: &Binding) -> bool {
+        match (self, other) {
+            (Binding::Hotkey(Hotkey::Save(_)), Binding::Hotkey(Hotkey::Save(_))) => true,
+            _ => self == other,
+        }
+    }
+}
+
+/// Error in a keymap config, with the 1-based line it was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeymapError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for KeymapError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    // Host key name to what it does.
+    bindings: BTreeMap<String, Binding>,
+}
+
+impl Keymap {
+    pub fn preset(name: &str) -> Option<Keymap> {
+        let keypad = match name {
+            "qwerty" => QWERTY,
+            "azerty" => AZERTY,
+            "dvorak" => DVORAK,
+            _ => return None,
+        };
+        let mut keymap = Keymap { bindings: BTreeMap::new() };
+        keymap.apply(HOTKEYS).expect("the hotkeys are valid");
+        keymap.apply(keypad).expect("the presets are valid");
+        Some(keymap)
+    }
+
+    /// Applies the lines of a config on top of the current bindings. On
+    /// error, the lines before the faulty one are kept.
+    pub fn apply(&mut self, config: &str) -> Result<(), KeymapError> {
+        for (index, line) in config.lines().enumerate() {
+            let error = |message: String| KeymapError { line: index + 1, message };
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix("preset ") {
+                *self = Keymap::preset(name.trim()).ok_or_else(|| error(format!(
+                    "unknown preset `{}`, expected one of {}", name.trim(), PRESET_NAMES.join(", "))))?;
+                continue;
+            }
+
+            let (target, keys) = line.split_once('=')
+                .ok_or_else(|| error(format!("expected `KEY = HOST_KEYS...` or `preset NAME`, found `{}`", line)))?;
+            let target = target.trim();
+            let binding = match HOTKEY_NAMES.iter().find(|(name, _)| *name == target) {
+                Some((_, hotkey)) => Binding::Hotkey(*hotkey),
+                None => match u8::from_str_radix(target, 16) {
+                    Ok(key) if target.len() == 1 => Binding::Keypad(key),
+                    _ => return Err(error(format!("unknown CHIP-8 key or hotkey `{}`", target))),
+                },
+            };
+            let keys: Vec<&str> = keys.split_whitespace().collect();
+            if let Some(unknown) = keys.iter().find(|key| !KEY_NAMES.contains(key)) {
+                return Err(error(format!("unknown host key `{}`", unknown)));
+            }
+
+            self.bindings.retain(|_, bound| !binding.replaces(bound));
+            for (slot, key) in keys.into_iter().enumerate() {
+                let binding = match binding {
+                    Binding::Hotkey(Hotkey::Save(_)) => Binding::Hotkey(Hotkey::Save(slot as u8 + 1)),
+                    binding => binding,
+                };
+                self.bindings.insert(key.to_string(), binding);
+            }
+        }
+        Ok(())
+    }
+
+    /// What the host key named `key` does, if anything.
+    pub fn binding(&self, key: &str) -> Option<Binding> {
+        self.bindings.get(key).copied()
+    }
+
+    /// The host keys bound to `binding`, in alphabetical order.
+    pub fn keys(&self, binding: Binding) -> Vec<&str> {
+        self.bindings.iter()
+            .filter(|(_, bound)| **bound == binding)
+            .map(|(key, _)| key.as_str())
+            .collect()
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::preset("qwerty").unwrap()
+    }
+}
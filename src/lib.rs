@@ -0,0 +1,32 @@
+//! CHIP-8 interpreter core.
+//!
+//! The emulator itself has no dependency on any windowing or audio library:
+//! frontends drive a `Chip` by calling `run_frame` 60 times per second (or
+//! `emulate_cycle` and `tick_timers` separately) and reading back its
+//! framebuffer through `Chip::graphics`, and its buzzer through
+//! `Chip::sound_timer` and `audio`.
+
+pub mod asm;
+pub mod audio;
+pub mod chip;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod gdb;
+pub mod headless;
+pub mod keymap;
+pub mod movie;
+pub mod octo;
+pub mod opcodes;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod savestate;
+pub mod scheduler;
+pub mod screenshot;
+pub mod trace;
+
+pub use chip::{Chip, StepOutcome};
+pub use error::ChipError;
+pub use opcodes::Instruction;
+pub use quirks::Quirks;
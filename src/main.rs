@@ -1,81 +1,661 @@
+use std::cell::RefCell;
 use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::TcpListener;
+use std::path::Path;
 use std::process;
-use std::time;
-use std::thread;
+use std::rc::Rc;
+use std::slice;
+use std::str::FromStr;
 
+use chip8::asm::{self, AsmError, Assembly};
+use chip8::audio::{self, AudioCapture, Tone};
+use chip8::chip::{self, FRAMES_PER_SECOND};
+use chip8::debugger::{Debugger, Resume};
+use chip8::disasm;
+use chip8::error::ChipError;
+use chip8::gdb::GdbStub;
+use chip8::headless::{self, InputScript};
+use chip8::keymap::{self, Keymap};
+use chip8::movie::Movie;
+use chip8::octo;
+use chip8::quirks::{self, Quirks};
+use chip8::rng::XorShiftRng;
+use chip8::screenshot;
+use chip8::trace::{self, Filter, Step, TraceWriter};
+
+use crate::debug_console::Console;
+
+#[cfg(feature = "sfml")]
+mod buzzer;
+mod debug_console;
+#[cfg(feature = "sfml")]
+mod frontend;
+#[cfg(feature = "sfml")]
 mod runtime_manager;
-mod chip;
-mod opcodes;
 
 const ERROR_INVALID_ARGUMENTS: i32 = 0x0001;
 const ERROR_GAME_LOADING_FAILED: i32 = 0x0002;
+#[cfg(not(feature = "sfml"))]
+const ERROR_NO_FRONTEND: i32 = 0x0003;
+const ERROR_EMULATION_FAILED: i32 = 0x0004;
+const ERROR_MOVIE_LOADING_FAILED: i32 = 0x0005;
+const ERROR_INPUT_LOADING_FAILED: i32 = 0x0006;
+const ERROR_REPLAY_DESYNCHRONISED: i32 = 0x0007;
+const ERROR_OUTPUT_FAILED: i32 = 0x0008;
+const ERROR_ASSEMBLY_FAILED: i32 = 0x0009;
+const ERROR_GDB_FAILED: i32 = 0x000A;
+const ERROR_TRACE_DIVERGED: i32 = 0x000B;
+
+const DEFAULT_INSTRUCTIONS_PER_SECOND: u32 = 600;
+const DEFAULT_REWIND_SECONDS: u32 = 10;
+const DEFAULT_REWIND_MEGABYTES: usize = 64;
+const DEFAULT_HEADLESS_FRAMES: u32 = 600;
+const DEFAULT_TRACE_DIFF_SEED: u64 = 0;
+const WAV_SAMPLE_RATE: u32 = 44100;
+// Matching instructions shown before a divergence, and instructions after it.
+const TRACE_DIFF_CONTEXT_BEFORE: usize = 3;
+const TRACE_DIFF_CONTEXT_AFTER: usize = 2;
+
+// The window-only settings are unused when the SFML frontend is compiled out.
+#[cfg_attr(not(feature = "sfml"), allow(dead_code))]
+struct Options {
+    game: String,
+    quirks: Quirks,
+    instructions_per_second: u32,
+    speed: f64,
+    seed: Option<u64>,
+    rewind_seconds: u32,
+    rewind_megabytes: usize,
+    record: Option<String>,
+    replay: Option<String>,
+    headless: bool,
+    // None for the default, which trace-diff does without.
+    frames: Option<u32>,
+    input: Option<String>,
+    screenshot: Option<String>,
+    text: Option<String>,
+    dump: Option<String>,
+    wav: Option<String>,
+    watch: bool,
+    debug: bool,
+    gdb: Option<u16>,
+    log: Option<Filter>,
+    trace: Option<String>,
+    tone: Tone,
+    keymap: String,
+}
+
+/// The debugger in control of the emulation, if any.
+enum Debugging {
+    Off,
+    Console(Console),
+    Gdb(GdbStub),
+}
+
+impl Debugging {
+    /// Runs one frame, under the debugger if there is one. `redraw` shows the
+    /// screen after the debugger changed it.
+    fn run_frame(&mut self, chip: &mut chip::Chip, cycles_per_frame: usize, redraw: &mut dyn FnMut(&chip::Chip))
+                 -> Result<Resume, ChipError> {
+        match self {
+            Debugging::Off => chip.run_frame(cycles_per_frame).map(|()| Resume::Continue),
+            Debugging::Console(console) => console.run_frame(chip, redraw),
+            Debugging::Gdb(stub) => stub.run_frame(chip),
+        }
+    }
+
+    /// Stops in the debugger before the next instruction, returning false if there is none.
+    #[cfg_attr(not(feature = "sfml"), allow(dead_code))]
+    fn request_break(&mut self) -> bool {
+        match self {
+            Debugging::Off => return false,
+            Debugging::Console(console) => console.request_break(),
+            Debugging::Gdb(stub) => stub.request_break(),
+        }
+        true
+    }
+}
 
-const FRAME_PER_SECONDS: f32 = 60.0;
-const MILLISECONDS_PER_FRAME: f32 = 1000.0 / FRAME_PER_SECONDS;
+/// Whether the session is being recorded to, or played back from, a movie.
+#[cfg_attr(not(feature = "sfml"), allow(dead_code))]
+enum MovieMode {
+    Off,
+    Record { path: String, movie: Movie },
+    Replay(Movie),
+}
 
-const REAL_WINDOW_WIDTH: u32 = 640;
-const REAL_WINDOW_HEIGHT: u32 = 320;
+#[cfg_attr(not(feature = "sfml"), allow(dead_code))]
+impl Options {
+    fn cycles_per_frame(&self) -> usize {
+        // In u64, as rounding would overflow near u32::MAX instructions per second.
+        let ips = self.instructions_per_second as u64;
+        let fps = FRAMES_PER_SECOND as u64;
+        ((ips + fps / 2) / fps).max(1) as usize
+    }
 
-fn display_usage_and_exit() {
+    fn headless_frames(&self) -> u32 {
+        self.frames.unwrap_or(DEFAULT_HEADLESS_FRAMES)
+    }
+}
+
+fn display_usage_and_exit() -> ! {
     println!("Usage:");
-    println!("./chip8 PATH_TO_GAME");
+    println!("./chip8 [run] [OPTIONS] PATH_TO_GAME");
+    println!("./chip8 disasm PATH_TO_GAME");
+    println!("./chip8 asm SOURCE [-o ROM] [--symbols FILE]");
+    println!("./chip8 watch SOURCE.8o [OPTIONS]");
+    println!("./chip8 trace-diff [OPTIONS] PATH_TO_GAME REFERENCE_TRACE");
+    println!();
+    println!("Options:");
+    println!("  --quirks PRESET    Interpretation of ambiguous opcodes: {}", quirks::PRESET_NAMES.join(", "));
+    println!("  --ips N            CPU speed in instructions per second (default: {})", DEFAULT_INSTRUCTIONS_PER_SECOND);
+    println!("  --ipf N            CPU speed in instructions per 60 Hz frame (overrides --ips)");
+    println!("  --speed X          Emulation speed multiplier, e.g. 2 or 0.5 (default: 1)");
+    println!("  --seed N           Seed of the random number generator (default: random)");
+    println!("  --rewind-seconds N How much gameplay can be rewound, 0 to disable (default: {})", DEFAULT_REWIND_SECONDS);
+    println!("  --rewind-memory MB Memory cap of the rewind history (default: {})", DEFAULT_REWIND_MEGABYTES);
+    println!("  --record FILE      Record the session's input to a movie file");
+    println!("  --replay FILE      Play a recorded movie back (overrides --quirks, --ips and --seed)");
+    println!("  --tone HZ          Pitch of the buzzer, 20-20000 Hz (default: {})", audio::DEFAULT_FREQUENCY);
+    println!("  --volume PERCENT   Volume of the buzzer (default: {})", audio::DEFAULT_VOLUME * 100.0);
+    println!("  --waveform NAME    Shape of the buzzer tone: {} (default: square)", audio::WAVEFORM_NAMES.join(", "));
+    println!("  --keymap PRESET    Keyboard layout: {} (default: {}), see the readme for config files",
+             keymap::PRESET_NAMES.join(", "), keymap::PRESET_NAMES[0]);
+    println!("  --debug            Start paused in the debugger prompt on the terminal (type `help` there)");
+    println!("  --gdb PORT         Wait for GDB to connect on localhost:PORT, and let it control the game");
+    println!("  --log FILTER       Log to stderr, e.g. `debug` or `cpu=trace,input=debug` (targets: cpu, timers, input, render)");
+    println!("  --trace FILE       Write every executed instruction and the registers around it to FILE");
+    println!();
+    println!("Headless options:");
+    println!("  --headless         Run without a window, then write the outputs below");
+    println!("  --frames N         Number of 60 Hz frames to run (default: {}, or the whole --replay movie)", DEFAULT_HEADLESS_FRAMES);
+    println!("  --input FILE       Keypad input script: lines of `FRAME KEYS...`, e.g. `120 4 6` or `130 -`");
+    println!("  --screenshot FILE  Write the final screen as a .png or .pbm image");
+    println!("  --text FILE        Write the final screen as text, - for stdout");
+    println!("  --dump FILE        Write the final registers as JSON, - for stdout");
+    println!("  --wav FILE         Write the sound of the whole run as a 16-bit .wav file, - for stdout");
+    println!();
+    println!("PATH_TO_GAME may also be an Octo .8o source, which is assembled first.");
+    println!();
+    println!("Hotkeys: hold Tab to fast-forward, hold ~ for slow motion, hold Backspace to rewind,");
+    println!("         F1-F8 to save the state to a slot, Shift+F1-F8 to load it back,");
+    println!("         F12 to break into the debugger with --debug or --gdb, M to mute the sound,");
+    println!("         Space to pause, F9 to reset, Escape to quit.");
     process::exit(ERROR_INVALID_ARGUMENTS);
 }
 
-fn main() {
-    let args: Vec<_> = env::args().collect();
-    if args.len() != 2 {
-        display_usage_and_exit();
-    }
+fn display_usage_and_exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    display_usage_and_exit();
+}
 
-    // Initialize various runtime elements
-    let mut runtime_manager = runtime_manager::RuntimeManager::new();
-    let v = sfml::system::Vector2u::new(REAL_WINDOW_WIDTH, REAL_WINDOW_HEIGHT);
-    runtime_manager.window.set_size(v);
-    let mut chip = chip::Chip::new();
-    let result = chip.load_game(&args[1]);
-    match result {
-        Err(e) => { println!("{:?}", e); process::exit(ERROR_GAME_LOADING_FAILED); },
-        Ok(result) => result
+/// Parses the value following `option`, rejecting it unless `valid` accepts it.
+fn parse_value<T: FromStr>(args: &mut slice::Iter<String>, option: &str, valid: fn(&T) -> bool) -> T {
+    let value = args.next().unwrap_or_else(|| display_usage_and_exit_with(&format!("Missing value for {}", option)));
+    match value.parse() {
+        Ok(parsed) if valid(&parsed) => parsed,
+        _ => display_usage_and_exit_with(&format!("Invalid value for {}: {}", option, value)),
     }
+}
 
-    let duration_per_frame: time::Duration = time::Duration::
-                from_millis(MILLISECONDS_PER_FRAME.trunc() as u64);
-    runtime_manager.draw_graphics(&chip.graphics);
+fn parse_options(args: &[String]) -> Options {
+    let mut game = None;
+    let mut options = Options {
+        game: String::new(),
+        quirks: Quirks::default(),
+        instructions_per_second: DEFAULT_INSTRUCTIONS_PER_SECOND,
+        speed: 1.0,
+        seed: None,
+        rewind_seconds: DEFAULT_REWIND_SECONDS,
+        rewind_megabytes: DEFAULT_REWIND_MEGABYTES,
+        record: None,
+        replay: None,
+        headless: false,
+        frames: None,
+        input: None,
+        screenshot: None,
+        text: None,
+        dump: None,
+        wav: None,
+        watch: false,
+        debug: false,
+        gdb: None,
+        log: None,
+        trace: None,
+        tone: Tone::default(),
+        keymap: keymap::PRESET_NAMES[0].to_string(),
+    };
 
-    loop {
-        let timer_start = time::SystemTime::now();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--quirks" => {
+                let name: String = parse_value(&mut args, arg, |_| true);
+                options.quirks = Quirks::preset(&name).unwrap_or_else(
+                    || display_usage_and_exit_with(&format!("Unknown quirks preset: {}", name)));
+            },
+            "--ips" => options.instructions_per_second = parse_value(&mut args, arg, |ips| *ips > 0),
+            "--ipf" => options.instructions_per_second = parse_value::<u32>(&mut args, arg, |ipf| {
+                (1..=u32::MAX / FRAMES_PER_SECOND).contains(ipf)
+            }) * FRAMES_PER_SECOND,
+            "--speed" => options.speed = parse_value(&mut args, arg, |speed| *speed > 0.0),
+            "--seed" => options.seed = Some(parse_value(&mut args, arg, |_| true)),
+            "--rewind-seconds" => options.rewind_seconds = parse_value(&mut args, arg, |_| true),
+            "--rewind-memory" => options.rewind_megabytes = parse_value(&mut args, arg, |_| true),
+            "--record" => options.record = Some(parse_value(&mut args, arg, |_| true)),
+            "--replay" => options.replay = Some(parse_value(&mut args, arg, |_| true)),
+            "--headless" => options.headless = true,
+            "--frames" => options.frames = Some(parse_value(&mut args, arg, |_| true)),
+            "--input" => options.input = Some(parse_value(&mut args, arg, |_| true)),
+            "--screenshot" => options.screenshot = Some(parse_value(&mut args, arg, |path: &String| {
+                path.ends_with(".png") || path.ends_with(".pbm")
+            })),
+            "--text" => options.text = Some(parse_value(&mut args, arg, |_| true)),
+            "--dump" => options.dump = Some(parse_value(&mut args, arg, |_| true)),
+            "--wav" => options.wav = Some(parse_value(&mut args, arg, |_| true)),
+            "--debug" => options.debug = true,
+            "--gdb" => options.gdb = Some(parse_value(&mut args, arg, |port| *port > 0)),
+            "--tone" => options.tone.frequency = parse_value(&mut args, arg, |hz| (20..=20000).contains(hz)),
+            "--volume" => options.tone.volume = parse_value::<f64>(&mut args, arg, |percent| (0.0..=100.0).contains(percent)) / 100.0,
+            "--waveform" => options.tone.waveform = parse_value(&mut args, arg, |_| true),
+            "--keymap" => options.keymap = parse_value(&mut args, arg, |name: &String| Keymap::preset(name).is_some()),
+            "--log" => options.log = Some(parse_value(&mut args, arg, |_| true)),
+            "--trace" => options.trace = Some(parse_value(&mut args, arg, |_| true)),
+            _ if arg.starts_with("--") => display_usage_and_exit_with(&format!("Unknown option: {}", arg)),
+            _ if game.is_none() => game = Some(arg.clone()),
+            _ => display_usage_and_exit(),
+        }
+    }
 
-        loop {
-            chip.emulate_cycle();
-            runtime_manager.handle_events(&mut chip);
+    options.game = game.unwrap_or_else(|| display_usage_and_exit());
+    if options.record.is_some() && options.replay.is_some() {
+        display_usage_and_exit_with("--record and --replay cannot be used together");
+    }
+    if options.debug && options.gdb.is_some() {
+        display_usage_and_exit_with("--debug and --gdb cannot be used together");
+    }
+    if (options.debug || options.gdb.is_some()) && (options.record.is_some() || options.replay.is_some()) {
+        // Stepping in the debugger moves the frame boundaries the movie relies on.
+        display_usage_and_exit_with("--debug and --gdb cannot be combined with --record or --replay");
+    }
+    if options.headless && options.record.is_some() {
+        display_usage_and_exit_with("--record is not available in headless mode");
+    }
+    if options.replay.is_some() && (options.frames.is_some() || options.input.is_some()) {
+        display_usage_and_exit_with("--replay plays the whole movie, it cannot be combined with --frames or --input");
+    }
+    if !options.headless && (options.frames.is_some() || options.input.is_some() || options.screenshot.is_some()
+                             || options.text.is_some() || options.dump.is_some() || options.wav.is_some()) {
+        display_usage_and_exit_with("--frames, --input, --screenshot, --text, --dump and --wav need --headless");
+    }
+    options
+}
 
-            if chip.clear_flag != 0 {
-                runtime_manager.clear_screen();
+fn main() {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let mut options = match args.first().map(String::as_str) {
+        Some("run") => parse_options(&args[1..]),
+        Some("disasm") => return disasm_command(&args[1..]),
+        Some("asm") => return asm_command(&args[1..]),
+        Some("trace-diff") => return trace_diff_command(&args[1..]),
+        Some("watch") => {
+            let mut options = parse_options(&args[1..]);
+            if options.headless || options.record.is_some() || options.replay.is_some() {
+                display_usage_and_exit_with("watch cannot be combined with --headless, --record or --replay");
             }
+            options.watch = true;
+            options
+        },
+        _ => parse_options(&args),
+    };
 
-            if chip.draw_flag != 0 {
-                runtime_manager.draw_graphics(&chip.graphics);
-            }
+    if let Some(filter) = &options.log {
+        filter.install();
+    }
 
-            if chip.exit_flag == 1 {
-                process::exit(0);
-            }
+    let replay = options.replay.clone().map(|path| {
+        let movie = fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| Movie::from_bytes(&bytes).map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| {
+                eprintln!("Could not load movie {}: {}", path, e);
+                process::exit(ERROR_MOVIE_LOADING_FAILED);
+            });
+        // The movie only reproduces the session under the settings it was recorded with.
+        options.quirks = movie.quirks;
+        options.seed = Some(movie.seed);
+        options.instructions_per_second = movie.cycles_per_frame * FRAMES_PER_SECOND;
+        eprintln!("Replaying {}: {} frames", path, movie.frames.len());
+        movie
+    });
+
+    let mut chip = chip::Chip::new(options.quirks);
+    let seed = options.seed.unwrap_or_else(rand::random);
+    eprintln!("Random seed: {} (pass --seed {} to replay this run)", seed, seed);
+    chip.set_rng(Box::new(XorShiftRng::new(seed)));
+    let (labels, breakpoints) = load_game(&mut chip, &options.game);
 
-            if chip.draw_flag != 0 || chip.clear_flag != 0 {
-                let timer_end = time::SystemTime::now();
-                let loop_time = timer_end.duration_since(timer_start).unwrap();
-                if loop_time < duration_per_frame {
-                    println!("[0000] SLEEPING   : {:?}", duration_per_frame - loop_time);
-                    thread::sleep(duration_per_frame - loop_time);
-                    chip.draw_flag = 0;
-                    chip.clear_flag = 0;
-                }
-                else {
-                    println!("Loop was too slow: {:?}", loop_time - duration_per_frame);
-                }
-                break;
+    if let Some(path) = &options.trace {
+        match File::create(path) {
+            Ok(file) => chip.set_tracer(Box::new(TraceWriter::new(BufWriter::new(file)))),
+            Err(e) => {
+                eprintln!("Could not write {}: {}", path, e);
+                process::exit(ERROR_OUTPUT_FAILED);
+            },
+        }
+    }
+
+    let movie = match (replay, &options.record) {
+        (Some(movie), _) => {
+            if movie.rom_hash != chip.rom_hash() {
+                eprintln!("Movie {} was recorded with a different ROM", options.replay.as_ref().unwrap());
+                process::exit(ERROR_MOVIE_LOADING_FAILED);
             }
+            MovieMode::Replay(movie)
+        },
+        (None, Some(path)) => MovieMode::Record {
+            path: path.clone(),
+            movie: Movie::new(chip.rom_hash(), seed, options.quirks, options.cycles_per_frame() as u32),
+        },
+        (None, None) => MovieMode::Off,
+    };
+
+    let debugger = Debugger::new(options.cycles_per_frame());
+    let debugging = match options.gdb {
+        Some(port) => Debugging::Gdb(wait_for_gdb(port, debugger)),
+        None if options.debug => Debugging::Console(Console::new(debugger, labels, &breakpoints)),
+        None => Debugging::Off,
+    };
+
+    if options.headless {
+        run_headless(chip, &options, movie, debugging);
+    } else {
+        run_window(chip, &options, movie, debugging);
+    }
+}
+
+/// Names with their address, from an assembled source.
+type Symbols = Vec<(String, u16)>;
+
+/// Loads a ROM, or assembles an Octo source, returning the labels and
+/// `:breakpoint`s of the source for the debugger.
+fn load_game(chip: &mut chip::Chip, game: &str) -> (Symbols, Symbols) {
+    eprintln!("Loading game: {}", game);
+    let symbols = if is_octo_source(game) {
+        let assembly = assemble_source(Path::new(game)).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(ERROR_ASSEMBLY_FAILED);
+        });
+        if let Err(e) = chip.load_rom(&assembly.rom) {
+            eprintln!("{:?}", e);
+            process::exit(ERROR_GAME_LOADING_FAILED);
+        }
+        (assembly.labels, assembly.breakpoints)
+    } else {
+        match chip.load_game(game) {
+            Err(e) => { eprintln!("{:?}", e); process::exit(ERROR_GAME_LOADING_FAILED); },
+            Ok(result) => result
+        }
+        (Vec::new(), Vec::new())
+    };
+    eprintln!("Game {} properly loaded.", game);
+    symbols
+}
+
+fn disasm_command(args: &[String]) {
+    let game = match args {
+        [game] => game,
+        _ => display_usage_and_exit(),
+    };
+    let rom = fs::read(game).unwrap_or_else(|e| {
+        eprintln!("Could not read {}: {}", game, e);
+        process::exit(ERROR_GAME_LOADING_FAILED);
+    });
+    print!("; {}: {} bytes\n\n{}", game, rom.len(), disasm::disassemble(&rom));
+}
+
+fn is_octo_source(path: &str) -> bool {
+    path.ends_with(".8o")
+}
+
+/// Assembles an Octo `.8o` source, or a source in the disassembler's syntax.
+fn assemble_source(path: &Path) -> Result<Assembly, AsmError> {
+    if is_octo_source(&path.to_string_lossy()) {
+        octo::assemble_file(path)
+    } else {
+        asm::assemble_file(path)
+    }
+}
+
+fn asm_command(args: &[String]) {
+    let (mut source, mut output, mut symbols) = (None, None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(parse_value::<String>(&mut args, arg, |_| true)),
+            "--symbols" => symbols = Some(parse_value::<String>(&mut args, arg, |_| true)),
+            _ if arg.starts_with('-') => display_usage_and_exit_with(&format!("Unknown option: {}", arg)),
+            _ if source.is_none() => source = Some(arg.clone()),
+            _ => display_usage_and_exit(),
+        }
+    }
+    let source = source.unwrap_or_else(|| display_usage_and_exit());
+    let output = output.unwrap_or_else(|| Path::new(&source).with_extension("ch8").display().to_string());
+
+    let assembly = assemble_source(Path::new(&source)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(ERROR_ASSEMBLY_FAILED);
+    });
+    write_output(&output, &assembly.rom);
+    if let Some(path) = symbols {
+        write_output(&path, assembly.symbol_map().as_bytes());
+    }
+    eprintln!("Assembled {} bytes to {}", assembly.rom.len(), output);
+}
+
+/// Writes `contents` to `path`, or to stdout if `path` is `-`.
+fn write_output(path: &str, contents: &[u8]) {
+    let written = if path == "-" {
+        io::stdout().write_all(contents)
+    } else {
+        fs::write(path, contents)
+    };
+    if let Err(e) = written {
+        eprintln!("Could not write {}: {}", path, e);
+        process::exit(ERROR_OUTPUT_FAILED);
+    }
+}
+
+/// Flushes the `--trace` file, if any.
+fn finish_trace(chip: &mut chip::Chip, options: &Options) {
+    if let (Some(mut tracer), Some(path)) = (chip.take_tracer(), &options.trace) {
+        if let Err(e) = tracer.finish() {
+            eprintln!("Could not write {}: {}", path, e);
+            process::exit(ERROR_OUTPUT_FAILED);
+        }
+    }
+}
+
+fn wait_for_gdb(port: u16, debugger: Debugger) -> GdbStub {
+    // Only local clients: the protocol can read and write anything in the emulator.
+    let stub = TcpListener::bind(("127.0.0.1", port)).and_then(|listener| {
+        eprintln!("Waiting for GDB on localhost:{} (target remote localhost:{})", port, port);
+        GdbStub::accept(&listener, debugger)
+    });
+    stub.unwrap_or_else(|e| {
+        eprintln!("Could not accept a GDB connection on port {}: {}", port, e);
+        process::exit(ERROR_GDB_FAILED);
+    })
+}
+
+/// `headless::run` under a debugger, stopping early if the user quits.
+fn run_debugged(chip: &mut chip::Chip, options: &Options, input: &InputScript, debugging: &mut Debugging,
+                on_frame: &mut dyn FnMut(&chip::Chip)) -> Result<u32, ChipError> {
+    let frames = options.headless_frames();
+    for frame in 0..frames {
+        chip.set_keys(input.keys_at(frame));
+        let resume = debugging.run_frame(chip, options.cycles_per_frame(), &mut |_| {})?;
+        on_frame(chip);
+        if resume == Resume::Quit || chip.exit_flag != 0 {
+            return Ok(frame + 1);
         }
     }
+    Ok(frames)
+}
+
+fn load_input(options: &Options) -> InputScript {
+    match &options.input {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| InputScript::parse(&source).map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| {
+                eprintln!("Could not load input script {}: {}", path, e);
+                process::exit(ERROR_INPUT_LOADING_FAILED);
+            }),
+        None => InputScript::default(),
+    }
+}
+
+fn run_headless(mut chip: chip::Chip, options: &Options, movie: MovieMode, mut debugging: Debugging) {
+    let input = load_input(options);
+
+    let mut audio = options.wav.as_ref().map(|_| AudioCapture::new(options.tone, WAV_SAMPLE_RATE));
+    let mut capture = |chip: &chip::Chip| if let Some(audio) = &mut audio {
+        audio.capture_frame(chip);
+    };
+
+    let mut divergence = None;
+    let result = match (&movie, &debugging) {
+        (MovieMode::Replay(movie), _) => movie.replay(&mut chip, &mut capture).map(|diverged| divergence = diverged),
+        (_, Debugging::Off) => headless::run(&mut chip, options.headless_frames(), options.cycles_per_frame(), &input, &mut capture)
+            .map(|frames| eprintln!("Ran {} frames", frames)),
+        _ => run_debugged(&mut chip, options, &input, &mut debugging, &mut capture)
+            .map(|frames| eprintln!("Ran {} frames", frames)),
+    };
+
+    // Outputs are written even when emulation failed, they show where it stopped.
+    finish_trace(&mut chip, options);
+    let (graphics, width, height) = (chip.graphics(), chip.width(), chip.height());
+    if let Some(path) = &options.screenshot {
+        let image = if path.ends_with(".pbm") {
+            screenshot::to_pbm(graphics, width, height)
+        } else {
+            screenshot::to_png(graphics, width, height)
+        };
+        write_output(path, &image);
+    }
+    if let Some(path) = &options.text {
+        write_output(path, screenshot::to_text(graphics, width, height).as_bytes());
+    }
+    if let Some(path) = &options.dump {
+        write_output(path, headless::registers_json(&chip).as_bytes());
+    }
+    if let (Some(path), Some(audio)) = (&options.wav, &audio) {
+        write_output(path, &audio.to_wav());
+    }
+
+    if let Err(e) = result {
+        eprintln!("Emulation stopped: {}", e);
+        process::exit(ERROR_EMULATION_FAILED);
+    }
+    if let Some(frame) = divergence {
+        eprintln!("Replay desynchronised at frame {}", frame);
+        process::exit(ERROR_REPLAY_DESYNCHRONISED);
+    }
+}
+
+/// Runs the game headlessly and compares every instruction with a reference
+/// trace, reporting the first one that differs.
+fn trace_diff_command(args: &[String]) {
+    let (reference_path, args) = args.split_last().unwrap_or_else(|| display_usage_and_exit());
+    let mut args = args.to_vec();
+    args.push("--headless".to_string());
+    let options = parse_options(&args);
+    if options.debug || options.gdb.is_some() || options.replay.is_some() || options.trace.is_some() {
+        display_usage_and_exit_with("trace-diff cannot be combined with --debug, --gdb, --replay or --trace");
+    }
+
+    let reference = fs::read_to_string(reference_path)
+        .map_err(|e| e.to_string())
+        .and_then(|source| trace::parse(&source).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            eprintln!("Could not load trace {}: {}", reference_path, e);
+            process::exit(ERROR_INPUT_LOADING_FAILED);
+        });
+    let input = load_input(&options);
+    if let Some(filter) = &options.log {
+        filter.install();
+    }
+
+    let mut chip = chip::Chip::new(options.quirks);
+    chip.set_rng(Box::new(XorShiftRng::new(options.seed.unwrap_or(DEFAULT_TRACE_DIFF_SEED))));
+    load_game(&mut chip, &options.game);
+    let steps = Rc::new(RefCell::new(Vec::new()));
+    chip.set_tracer(Box::new(steps.clone()));
+
+    // Run just long enough to cover the reference, unless --frames says otherwise.
+    let mut frame = 0;
+    let mut frame_limit_hit = false;
+    while steps.borrow().len() < reference.len() && chip.exit_flag == 0 {
+        if options.frames.is_some_and(|frames| frame >= frames) {
+            frame_limit_hit = true;
+            break;
+        }
+        let executed = steps.borrow().len();
+        chip.set_keys(input.keys_at(frame));
+        if let Err(e) = chip.run_frame(options.cycles_per_frame()) {
+            eprintln!("Emulation stopped: {}", e);
+            break;
+        }
+        frame += 1;
+        // Nothing ran, and no key will change to end a key wait.
+        if steps.borrow().len() == executed && input.last_change().is_none_or(|last| last < frame) {
+            eprintln!("The program waits for a key after frame {}", frame);
+            break;
+        }
+    }
+
+    let steps = steps.borrow();
+    let expected: Vec<Step> = reference.iter().map(|(_, step)| *step).collect();
+    let mut divergence = match trace::first_divergence(&expected, &steps) {
+        Some(divergence) => divergence,
+        None => return println!("All {} instructions of {} match", expected.len(), reference_path),
+    };
+    if frame_limit_hit && divergence.index == steps.len() {
+        divergence.differences = vec![format!("the --frames limit of {} frames was hit after {} instructions",
+                                              frame, steps.len())];
+    }
+
+    let index = divergence.index;
+    println!("Diverged from {} at instruction {} (line {}):", reference_path, index + 1, reference[index].0);
+    for difference in &divergence.differences {
+        println!("  {}", difference);
+    }
+    println!();
+    for (line, step) in &reference[index.saturating_sub(TRACE_DIFF_CONTEXT_BEFORE)..index] {
+        println!("  line {:<6} {}", line, step);
+    }
+    let end = index + 1 + TRACE_DIFF_CONTEXT_AFTER;
+    for at in index..end.min(reference.len().max(steps.len())) {
+        if let Some((line, step)) = reference.get(at) {
+            println!("- line {:<6} {}", line, step);
+        }
+        if let Some(step) = steps.get(at) {
+            println!("+ {:<11} {}", "", step);
+        }
+    }
+    process::exit(ERROR_TRACE_DIVERGED);
+}
+
+#[cfg(feature = "sfml")]
+fn run_window(chip: chip::Chip, options: &Options, movie: MovieMode, debugging: Debugging) {
+    frontend::run(chip, options, movie, debugging);
+}
+
+#[cfg(not(feature = "sfml"))]
+fn run_window(_chip: chip::Chip, _options: &Options, _movie: MovieMode, _debugging: Debugging) {
+    eprintln!("This build of chip8 has no window frontend (built without the `sfml` feature).");
+    process::exit(ERROR_NO_FRONTEND);
 }
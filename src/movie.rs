@@ -0,0 +1,158 @@
+use std::error;
+use std::fmt;
+
+use crate::chip::{Chip, FRAMES_PER_SECOND};
+use crate::error::ChipError;
+use crate::quirks::Quirks;
+use crate::savestate::{self, SaveStateError, StateReader, StateWriter};
+
+/// First bytes of every movie file.
+pub const MAGIC: &[u8; 4] = b"C8MV";
+/// Bumped whenever the movie layout changes.
+pub const VERSION: u16 = 1;
+
+/// Errors raised by `Movie::from_bytes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieError {
+    /// The data does not start with the movie magic bytes.
+    NotAMovie,
+    /// The movie was written by an incompatible version of the emulator.
+    UnsupportedVersion { found: u16, expected: u16 },
+    /// The data ends in the middle of a field.
+    Truncated,
+    /// The CPU speed is zero, or too fast to count in instructions per second.
+    InvalidSpeed { cycles_per_frame: u32 },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MovieError::NotAMovie =>
+                write!(f, "not a CHIP-8 movie"),
+            MovieError::UnsupportedVersion { found, expected } =>
+                write!(f, "movie version {} is not supported (expected version {})", found, expected),
+            MovieError::Truncated =>
+                write!(f, "movie is truncated"),
+            MovieError::InvalidSpeed { cycles_per_frame } =>
+                write!(f, "movie runs {} instructions per frame, expected 1 to {}",
+                       cycles_per_frame, u32::MAX / FRAMES_PER_SECOND),
+        }
+    }
+}
+
+impl error::Error for MovieError {}
+
+impl From<SaveStateError> for MovieError {
+    fn from(_: SaveStateError) -> MovieError {
+        // StateReader only ever fails by running out of data.
+        MovieError::Truncated
+    }
+}
+
+/// What happened during one 60 Hz frame of a recorded session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovieFrame {
+    /// Keypad state applied before the frame ran, bit N = key N held.
+    pub keys: u16,
+    /// `state_hash` of the chip once the frame had run.
+    pub state_hash: u64,
+}
+
+/// A recorded play session: everything needed to reproduce it from power-on.
+///
+/// Keys are applied once per frame through `Chip::set_keys`, so replaying the
+/// frames against a chip built from the same ROM, seed, quirks and CPU speed
+/// reproduces the session exactly. The per-frame state hashes pinpoint the
+/// first frame where a replay diverges.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub cycles_per_frame: u32,
+    pub frames: Vec<MovieFrame>,
+}
+
+/// Fingerprint of the complete state of `chip`.
+pub fn state_hash(chip: &Chip) -> u64 {
+    savestate::fnv1a(&chip.save_state())
+}
+
+impl Movie {
+    pub fn new(rom_hash: u64, seed: u64, quirks: Quirks, cycles_per_frame: u32) -> Movie {
+        Movie {
+            rom_hash,
+            seed,
+            quirks,
+            cycles_per_frame,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Appends a frame that ran with `keys` held and left the chip as `chip`.
+    pub fn record_frame(&mut self, keys: u16, chip: &Chip) {
+        self.frames.push(MovieFrame { keys, state_hash: state_hash(chip) });
+    }
+
+    /// Whether `chip` matches the recorded state after frame `frame`.
+    pub fn check_frame(&self, frame: usize, chip: &Chip) -> bool {
+        self.frames.get(frame).is_some_and(|recorded| recorded.state_hash == state_hash(chip))
+    }
+
+    /// Runs every recorded frame on `chip`, which must have been set up with
+    /// this movie's ROM, seed and quirks, and returns the first frame whose
+    /// state differs from the recording, if any. `on_frame` sees the chip
+    /// after every frame.
+    pub fn replay(&self, chip: &mut Chip, on_frame: &mut dyn FnMut(&Chip)) -> Result<Option<usize>, ChipError> {
+        let mut divergence = None;
+        for (index, frame) in self.frames.iter().enumerate() {
+            chip.set_keys(frame.keys);
+            chip.run_frame(self.cycles_per_frame as usize)?;
+            on_frame(chip);
+            if divergence.is_none() && !self.check_frame(index, chip) {
+                divergence = Some(index);
+            }
+            if chip.exit_flag != 0 {
+                break;
+            }
+        }
+        Ok(divergence)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.put_bytes(MAGIC);
+        writer.put_u16(VERSION);
+        writer.put_u64(self.rom_hash);
+        writer.put_u64(self.seed);
+        writer.put_u8(self.quirks.to_bits());
+        writer.put_u32(self.cycles_per_frame);
+        writer.put_u32(self.frames.len() as u32);
+        for frame in &self.frames {
+            writer.put_u16(frame.keys);
+            writer.put_u64(frame.state_hash);
+        }
+        writer.into_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Movie, MovieError> {
+        let mut reader = StateReader::new(bytes);
+        if reader.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(MovieError::NotAMovie);
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion { found: version, expected: VERSION });
+        }
+
+        let mut movie = Movie::new(reader.u64()?, reader.u64()?, Quirks::from_bits(reader.u8()?), reader.u32()?);
+        if movie.cycles_per_frame == 0 || movie.cycles_per_frame > u32::MAX / FRAMES_PER_SECOND {
+            return Err(MovieError::InvalidSpeed { cycles_per_frame: movie.cycles_per_frame });
+        }
+        let frame_count = reader.u32()?;
+        for _ in 0..frame_count {
+            movie.frames.push(MovieFrame { keys: reader.u16()?, state_hash: reader.u64()? });
+        }
+        Ok(movie)
+    }
+}
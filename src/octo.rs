@@ -0,0 +1,830 @@
+//! Assembler for the Octo language (`.8o` files).
+//!
+//! Supports the statements Octo programs use day to day:
+//!
+//! * `: name` labels, with execution starting at `: main`; a bare label name
+//!   calls it as a subroutine and `;` or `return` returns.
+//! * `:=` and the other assignment operators on `v0`..`vf`, `i`, `delay`,
+//!   `buzzer` and `pitch`, e.g. `v0 += 1`, `i := sprite-data`, `v1 := random 0xFF`.
+//! * `loop` ... `again`, with `while condition` to leave the loop.
+//! * `if condition then statement` and `if condition begin` ... `else` ... `end`,
+//!   with the conditions `==`, `!=`, `<`, `>`, `<=`, `>=`, `key` and `-key`.
+//!   The comparisons go through `vf`, as in Octo.
+//! * `:alias`, `:const`, `:calc name { expression }`, `:macro name params { body }`,
+//!   `:byte`, `:org`, `:unpack` and `:breakpoint`.
+//!
+//! `:calc` expressions are evaluated right to left without precedence, like
+//! Octo does; use parentheses to group.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::asm::{register, AsmError, Assembly, Location, Token, TokenKind};
+use crate::chip::{APPLICATION_MEMORY_LOCATION, MEMORY_SIZE};
+use crate::opcodes::Instruction;
+
+// Guards against macros expanding forever.
+const MAX_MACRO_EXPANSIONS: usize = 10_000;
+
+/// Assembles the Octo file at `path`.
+pub fn assemble_file(path: &Path) -> Result<Assembly, AsmError> {
+    let source = fs::read_to_string(path).map_err(|e| AsmError {
+        file: path.display().to_string(),
+        line: 0,
+        column: 0,
+        message: e.to_string(),
+    })?;
+    assemble(&source, path)
+}
+
+/// Assembles Octo `source`, `path` naming it in errors.
+pub fn assemble(source: &str, path: &Path) -> Result<Assembly, AsmError> {
+    let tokens = tokenize(source, path)?;
+    // Like Octo, start with a jump to `main`, unless `main` comes first anyway.
+    let compiled = Compiler::new(tokens.clone(), true).compile()?;
+    if compiled.labels.get("main") == Some(&((APPLICATION_MEMORY_LOCATION + 2) as u16)) {
+        return Compiler::new(tokens, false).compile()?.assembly();
+    }
+    compiled.assembly()
+}
+
+/// Splits Octo source on whitespace; `#` starts a comment.
+fn tokenize(source: &str, path: &Path) -> Result<Vec<Token>, AsmError> {
+    let file: Rc<str> = path.display().to_string().into();
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let mut rest = line;
+        while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+            let column = line.len() - rest.len() + start + 1;
+            let text = &rest[start..];
+            if text.starts_with('#') {
+                break;
+            }
+            let end = text.find(char::is_whitespace).unwrap_or(text.len());
+            let word = &text[..end];
+            let location = Location { file: file.clone(), line: index + 1, column };
+            let kind = match parse_number(word) {
+                Some(value) => TokenKind::Number(value),
+                None if word.starts_with(|c: char| c.is_ascii_digit()) =>
+                    return Err(location.error(format!("invalid number `{}`", word))),
+                None => TokenKind::Ident(word.to_string()),
+            };
+            tokens.push(Token { kind, location });
+            rest = &text[end..];
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_number(word: &str) -> Option<i64> {
+    let (negative, digits) = match word.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, word),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// The 12-bit address of a jump `at` compiles to `target`.
+fn jump_target(target: usize, at: &Token) -> Result<u16, AsmError> {
+    if target > 0xFFF {
+        return Err(at.location.error(format!("{} jumps out of reach to {:#X}", at, target)));
+    }
+    Ok(target as u16)
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// A reference to a label defined later, patched once the program is done.
+struct Fixup {
+    // Address of the instruction holding the reference.
+    addr: usize,
+    // `i := long`, whose address is a whole word after the opcode.
+    long: bool,
+    name: Token,
+}
+
+/// Operand of a comparison.
+#[derive(Clone, Copy)]
+enum Operand {
+    Register(u8),
+    Immediate(u8),
+}
+
+struct Compiler {
+    tokens: Vec<Token>,
+    position: usize,
+    expansions: usize,
+    // Bytes from 0x200 on.
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    // Open `loop`s: the token, start address and `while` jumps to patch at `again`.
+    loops: Vec<(Token, usize, Vec<usize>)>,
+    // Open `if ... begin`s: the token and the jump to patch at `else` or `end`.
+    branches: Vec<(Token, usize)>,
+    breakpoints: Vec<(String, u16)>,
+    jump_to_main: bool,
+}
+
+impl Compiler {
+    fn new(tokens: Vec<Token>, jump_to_main: bool) -> Compiler {
+        Compiler {
+            tokens,
+            position: 0,
+            expansions: 0,
+            rom: Vec::new(),
+            here: APPLICATION_MEMORY_LOCATION + if jump_to_main { 2 } else { 0 },
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            loops: Vec::new(),
+            branches: Vec::new(),
+            breakpoints: Vec::new(),
+            jump_to_main,
+        }
+    }
+
+    fn compile(mut self) -> Result<Compiler, AsmError> {
+        while self.position < self.tokens.len() {
+            self.statement()?;
+        }
+
+        if let Some((token, _, _)) = self.loops.last() {
+            return Err(token.location.error("`loop` without `again`"));
+        }
+        if let Some((token, _)) = self.branches.last() {
+            return Err(token.location.error("`begin` without `end`"));
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let name = fixup.name.ident().unwrap();
+            let target = *self.labels.get(name)
+                .ok_or_else(|| fixup.name.location.error(format!("unknown label `{}`", name)))?;
+            let offset = fixup.addr - APPLICATION_MEMORY_LOCATION;
+            if fixup.long {
+                self.rom[offset + 2..offset + 4].copy_from_slice(&target.to_be_bytes());
+            } else {
+                if target > 0xFFF {
+                    return Err(fixup.name.location.error(format!("label `{}` is out of reach at {:#X}", name, target)));
+                }
+                self.rom[offset] = (self.rom[offset] & 0xF0) | (target >> 8) as u8;
+                self.rom[offset + 1] = target as u8;
+            }
+        }
+        if self.jump_to_main {
+            let start = Location {
+                file: self.tokens.first().map_or_else(|| "".into(), |token| token.location.file.clone()),
+                line: 1,
+                column: 1,
+            };
+            let main = *self.labels.get("main").ok_or_else(|| start.error("missing `: main` label"))?;
+            if main > 0xFFF {
+                return Err(start.error(format!("label `main` is out of reach at {:#X}", main)));
+            }
+            self.put(APPLICATION_MEMORY_LOCATION, &Instruction::Jump { nnn: main }.encode_bytes());
+        }
+        Ok(self)
+    }
+
+    fn assembly(self) -> Result<Assembly, AsmError> {
+        let mut labels: Vec<_> = self.labels.into_iter().collect();
+        labels.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+        Ok(Assembly { rom: self.rom, labels, breakpoints: self.breakpoints })
+    }
+
+    fn next(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.get(self.position) {
+            Some(token) => {
+                self.position += 1;
+                Ok(token.clone())
+            },
+            None => {
+                let at = self.tokens.last().map(|token| token.location.clone()).unwrap();
+                Err(at.error("unexpected end of file"))
+            },
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).and_then(Token::ident)
+    }
+
+    fn expect(&mut self, word: &str) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        if token.ident() != Some(word) {
+            return Err(token.location.error(format!("expected `{}`, found {}", word, token)));
+        }
+        Ok(token)
+    }
+
+    fn name(&mut self) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        match token.ident() {
+            Some(_) => Ok(token),
+            None => Err(token.location.error(format!("expected a name, found {}", token))),
+        }
+    }
+
+    fn put(&mut self, addr: usize, bytes: &[u8]) {
+        let offset = addr - APPLICATION_MEMORY_LOCATION;
+        if self.rom.len() < offset + bytes.len() {
+            self.rom.resize(offset + bytes.len(), 0);
+        }
+        self.rom[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn emit_bytes(&mut self, bytes: &[u8], at: &Token) -> Result<(), AsmError> {
+        if self.here + bytes.len() > MEMORY_SIZE {
+            return Err(at.location.error("program does not fit in memory"));
+        }
+        self.put(self.here, bytes);
+        self.here += bytes.len();
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction, at: &Token) -> Result<(), AsmError> {
+        self.emit_bytes(&instruction.encode_bytes(), at)
+    }
+
+    fn define_label(&mut self, name: &Token) -> Result<(), AsmError> {
+        let key = name.ident().unwrap().to_string();
+        if self.labels.insert(key.clone(), self.here as u16).is_some() {
+            return Err(name.location.error(format!("label `{}` is already defined", key)));
+        }
+        Ok(())
+    }
+
+    fn register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        token.ident()
+            .and_then(|name| self.aliases.get(name).copied().or_else(|| register(name)))
+            .ok_or_else(|| token.location.error(format!("expected a register, found {}", token)))
+    }
+
+    /// A number or constant.
+    fn value(&mut self) -> Result<(i64, Token), AsmError> {
+        let token = self.next()?;
+        let value = match &token.kind {
+            TokenKind::Number(value) => Some(*value),
+            TokenKind::Ident(name) => self.constants.get(name).copied()
+                .or_else(|| self.labels.get(name).map(|addr| *addr as i64)),
+            _ => None,
+        };
+        match value {
+            Some(value) => Ok((value, token)),
+            None => Err(token.location.error(format!("expected a number or constant, found {}", token))),
+        }
+    }
+
+    fn value_in(&mut self, min: i64, max: i64, what: &str) -> Result<i64, AsmError> {
+        let (value, token) = self.value()?;
+        if value < min || value > max {
+            return Err(token.location.error(format!("{} {} out of range ({}..={:#X})", what, value, min, max)));
+        }
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        Ok(self.value_in(-128, 0xFF, "byte")? as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AsmError> {
+        Ok(self.value_in(0, 0xF, "nibble")? as u8)
+    }
+
+    /// An address for the instruction about to be emitted, which may name a
+    /// label defined later.
+    fn address(&mut self, long: bool) -> Result<u16, AsmError> {
+        let token = self.next()?;
+        let known = match &token.kind {
+            TokenKind::Number(value) => Some(*value),
+            TokenKind::Ident(name) => self.constants.get(name).copied()
+                .or_else(|| self.labels.get(name).map(|addr| *addr as i64)),
+            _ => None,
+        };
+        let max = if long { 0xFFFF } else { 0xFFF };
+        match known {
+            Some(value) if (0..=max).contains(&value) => Ok(value as u16),
+            Some(value) => Err(token.location.error(format!("address {:#X} out of range", value))),
+            None => {
+                self.fixups.push(Fixup { addr: self.here, long, name: token });
+                Ok(0)
+            },
+        }
+    }
+
+    /// Emits a jump whose target is patched later, and returns its address.
+    fn emit_placeholder_jump(&mut self, at: &Token) -> Result<usize, AsmError> {
+        let addr = self.here;
+        self.emit(Instruction::Jump { nnn: 0 }, at)?;
+        Ok(addr)
+    }
+
+    /// Points the jump at `jump` to `target`, which `at` needs to reach.
+    fn patch_jump(&mut self, jump: usize, target: usize, at: &Token) -> Result<(), AsmError> {
+        let nnn = jump_target(target, at)?;
+        self.put(jump, &Instruction::Jump { nnn }.encode_bytes());
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+        let word = match &token.kind {
+            TokenKind::Number(_) => {
+                self.position -= 1;
+                let byte = self.byte()?;
+                return self.emit_bytes(&[byte], &token);
+            },
+            TokenKind::Ident(word) => word.clone(),
+            _ => return Err(token.location.error(format!("unexpected {}", token))),
+        };
+
+        match word.as_str() {
+            ":" => {
+                let name = self.name()?;
+                self.define_label(&name)?;
+            },
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register()?;
+                self.aliases.insert(name.ident().unwrap().to_string(), register);
+            },
+            ":const" => {
+                let name = self.name()?;
+                let (value, _) = self.value()?;
+                self.constants.insert(name.ident().unwrap().to_string(), value);
+            },
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.braced_calc()?;
+                self.constants.insert(name.ident().unwrap().to_string(), value);
+            },
+            ":byte" => {
+                let byte = if self.peek() == Some("{") {
+                    self.braced_calc()? as u8
+                } else {
+                    self.byte()?
+                };
+                self.emit_bytes(&[byte], &token)?;
+            },
+            ":org" => {
+                let addr = self.value_in(APPLICATION_MEMORY_LOCATION as i64, MEMORY_SIZE as i64 - 1, "address")?;
+                self.here = addr as usize;
+            },
+            ":breakpoint" => {
+                let name = self.name()?;
+                self.breakpoints.push((name.ident().unwrap().to_string(), self.here as u16));
+            },
+            ":unpack" => {
+                let nibble = self.nibble()?;
+                let (addr, _) = self.value()?;
+                let addr = addr as u16;
+                self.emit(Instruction::LoadImm { x: 0, nn: (nibble << 4) | (addr >> 8) as u8 & 0xF }, &token)?;
+                self.emit(Instruction::LoadImm { x: 1, nn: addr as u8 }, &token)?;
+            },
+            ":macro" => self.define_macro()?,
+            _ if word.starts_with(':') && word.len() > 1 =>
+                return Err(token.location.error(format!("unsupported directive `{}`", word))),
+
+            "clear" => self.emit(Instruction::Cls, &token)?,
+            "return" | ";" => self.emit(Instruction::Ret, &token)?,
+            "hires" => self.emit(Instruction::HighRes, &token)?,
+            "lores" => self.emit(Instruction::LowRes, &token)?,
+            "exit" => self.emit(Instruction::Exit, &token)?,
+            "scroll-left" => self.emit(Instruction::ScrollLeft, &token)?,
+            "scroll-right" => self.emit(Instruction::ScrollRight, &token)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollDown { n }, &token)?;
+            },
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollUp { n }, &token)?;
+            },
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::Plane { n }, &token)?;
+            },
+            "audio" => self.emit(Instruction::Audio, &token)?,
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(Instruction::StoreBcd { x }, &token)?;
+            },
+            "save" | "load" => {
+                let x = self.register()?;
+                let instruction = if self.peek() == Some("-") {
+                    self.position += 1;
+                    let y = self.register()?;
+                    if word == "save" { Instruction::StoreRange { x, y } } else { Instruction::LoadRange { x, y } }
+                } else if word == "save" {
+                    Instruction::StoreRegs { x }
+                } else {
+                    Instruction::LoadRegs { x }
+                };
+                self.emit(instruction, &token)?;
+            },
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::StoreFlags { x }, &token)?;
+            },
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::LoadFlags { x }, &token)?;
+            },
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(Instruction::Draw { x, y, n }, &token)?;
+            },
+            "jump" => {
+                let nnn = self.address(false)?;
+                self.emit(Instruction::Jump { nnn }, &token)?;
+            },
+            "jump0" => {
+                let nnn = self.address(false)?;
+                self.emit(Instruction::JumpV0 { nnn }, &token)?;
+            },
+            "native" => {
+                let nnn = self.address(false)?;
+                self.emit(Instruction::Sys { nnn }, &token)?;
+            },
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let instruction = match word.as_str() {
+                    "delay" => Instruction::SetDelay { x },
+                    "buzzer" => Instruction::SetSound { x },
+                    _ => Instruction::Pitch { x },
+                };
+                self.emit(instruction, &token)?;
+            },
+            "i" => self.index_assignment(&token)?,
+
+            "loop" => self.loops.push((token, self.here, Vec::new())),
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err(token.location.error("`while` outside of a loop"));
+                }
+                self.conditional_skip(&token, true)?;
+                let exit = self.emit_placeholder_jump(&token)?;
+                self.loops.last_mut().unwrap().2.push(exit);
+            },
+            "again" => {
+                let (_, start, exits) = self.loops.pop()
+                    .ok_or_else(|| token.location.error("`again` without `loop`"))?;
+                let nnn = jump_target(start, &token)?;
+                self.emit(Instruction::Jump { nnn }, &token)?;
+                for exit in exits {
+                    self.patch_jump(exit, self.here, &token)?;
+                }
+            },
+            "if" => {
+                // Checks the condition ahead of the skip it compiles to.
+                let condition_start = self.position;
+                self.skip_condition()?;
+                let keyword = self.next()?;
+                let end_of_condition = self.position;
+                self.position = condition_start;
+                match keyword.ident() {
+                    Some("then") => {
+                        self.conditional_skip(&token, false)?;
+                        self.position = end_of_condition;
+                    },
+                    Some("begin") => {
+                        self.conditional_skip(&token, true)?;
+                        self.position = end_of_condition;
+                        let jump = self.emit_placeholder_jump(&token)?;
+                        self.branches.push((token, jump));
+                    },
+                    _ => return Err(keyword.location.error(format!("expected `then` or `begin`, found {}", keyword))),
+                }
+            },
+            "else" => {
+                let (begin, jump) = self.branches.pop()
+                    .ok_or_else(|| token.location.error("`else` without `if ... begin`"))?;
+                let end = self.emit_placeholder_jump(&token)?;
+                self.patch_jump(jump, self.here, &token)?;
+                self.branches.push((begin, end));
+            },
+            "end" => {
+                let (_, jump) = self.branches.pop()
+                    .ok_or_else(|| token.location.error("`end` without `if ... begin`"))?;
+                self.patch_jump(jump, self.here, &token)?;
+            },
+
+            _ if self.macros.contains_key(&word) => self.expand_macro(&token, &word)?,
+            _ if self.aliases.contains_key(&word) || register(&word).is_some() => {
+                self.position -= 1;
+                self.register_assignment(&token)?;
+            },
+            _ => {
+                // A bare name calls the subroutine it labels.
+                self.position -= 1;
+                let nnn = self.address(false)?;
+                self.emit(Instruction::Call { nnn }, &token)?;
+            },
+        }
+        Ok(())
+    }
+
+    fn index_assignment(&mut self, token: &Token) -> Result<(), AsmError> {
+        let operator = self.next()?;
+        match operator.ident() {
+            Some(":=") => match self.peek() {
+                Some("long") => {
+                    self.position += 1;
+                    let nnnn = self.address(true)?;
+                    self.emit(Instruction::LoadILong { nnnn }, token)
+                },
+                Some("hex") => {
+                    self.position += 1;
+                    let x = self.register()?;
+                    self.emit(Instruction::LoadFont { x }, token)
+                },
+                Some("bighex") => {
+                    self.position += 1;
+                    let x = self.register()?;
+                    self.emit(Instruction::LoadBigFont { x }, token)
+                },
+                _ => {
+                    let nnn = self.address(false)?;
+                    self.emit(Instruction::LoadI { nnn }, token)
+                },
+            },
+            Some("+=") => {
+                let x = self.register()?;
+                self.emit(Instruction::AddI { x }, token)
+            },
+            _ => Err(operator.location.error(format!("expected `:=` or `+=` after `i`, found {}", operator))),
+        }
+    }
+
+    fn register_assignment(&mut self, token: &Token) -> Result<(), AsmError> {
+        let x = self.register()?;
+        let operator = self.next()?;
+        let source_is_register = self.peek()
+            .is_some_and(|name| self.aliases.contains_key(name) || register(name).is_some());
+
+        let instruction = match (operator.ident().unwrap_or(""), source_is_register) {
+            (":=", true) => Instruction::LoadReg { x, y: self.register()? },
+            (":=", false) => match self.peek() {
+                Some("random") => {
+                    self.position += 1;
+                    Instruction::Rand { x, nn: self.byte()? }
+                },
+                Some("delay") => {
+                    self.position += 1;
+                    Instruction::LoadDelay { x }
+                },
+                Some("key") => {
+                    self.position += 1;
+                    Instruction::WaitKey { x }
+                },
+                _ => Instruction::LoadImm { x, nn: self.byte()? },
+            },
+            ("+=", true) => Instruction::AddReg { x, y: self.register()? },
+            ("+=", false) => Instruction::AddImm { x, nn: self.byte()? },
+            ("-=", true) => Instruction::Sub { x, y: self.register()? },
+            ("-=", false) => Instruction::AddImm { x, nn: self.byte()?.wrapping_neg() },
+            ("=-", true) => Instruction::SubN { x, y: self.register()? },
+            ("|=", true) => Instruction::Or { x, y: self.register()? },
+            ("&=", true) => Instruction::And { x, y: self.register()? },
+            ("^=", true) => Instruction::Xor { x, y: self.register()? },
+            (">>=", true) => Instruction::ShiftRight { x, y: self.register()? },
+            ("<<=", true) => Instruction::ShiftLeft { x, y: self.register()? },
+            _ => return Err(operator.location.error(format!("unsupported register operation {}", operator))),
+        };
+        self.emit(instruction, token)
+    }
+
+    /// Moves past a condition without compiling it.
+    fn skip_condition(&mut self) -> Result<(), AsmError> {
+        self.register()?;
+        let operator = self.next()?;
+        match operator.ident() {
+            Some("key") | Some("-key") => Ok(()),
+            Some("==") | Some("!=") | Some("<") | Some(">") | Some("<=") | Some(">=") => self.next().map(|_| ()),
+            _ => Err(operator.location.error(format!("expected a comparison, found {}", operator))),
+        }
+    }
+
+    fn comparison_operand(&mut self) -> Result<Operand, AsmError> {
+        let is_register = self.peek().is_some_and(|name| self.aliases.contains_key(name) || register(name).is_some());
+        if is_register {
+            Ok(Operand::Register(self.register()?))
+        } else {
+            Ok(Operand::Immediate(self.byte()?))
+        }
+    }
+
+    /// Compiles a condition into instructions that skip the next one when
+    /// the condition is `skip_when`.
+    fn conditional_skip(&mut self, at: &Token, skip_when: bool) -> Result<(), AsmError> {
+        let x = self.register()?;
+        let operator = self.next()?;
+        let (equal_skip, unequal_skip) = (
+            |x, operand| match operand {
+                Operand::Register(y) => Instruction::SkipEqReg { x, y },
+                Operand::Immediate(nn) => Instruction::SkipEqImm { x, nn },
+            },
+            |x, operand| match operand {
+                Operand::Register(y) => Instruction::SkipNeReg { x, y },
+                Operand::Immediate(nn) => Instruction::SkipNeImm { x, nn },
+            },
+        );
+
+        let instruction = match operator.ident().unwrap_or("") {
+            "key" if skip_when => Instruction::SkipKey { x },
+            "key" => Instruction::SkipNotKey { x },
+            "-key" if skip_when => Instruction::SkipNotKey { x },
+            "-key" => Instruction::SkipKey { x },
+            "==" | "!=" => {
+                let operand = self.comparison_operand()?;
+                if (operator.ident() == Some("==")) == skip_when {
+                    equal_skip(x, operand)
+                } else {
+                    unequal_skip(x, operand)
+                }
+            },
+            comparison @ ("<" | ">" | "<=" | ">=") => {
+                let operand = self.comparison_operand()?;
+                // VF becomes 1 when the first operand is greater than or equal to the second.
+                let (swapped, flag_when_true) = match comparison {
+                    "<" => (false, 0),
+                    ">=" => (false, 1),
+                    ">" => (true, 0),
+                    _ => (true, 1),
+                };
+                let (first, second) = if swapped { (operand, Operand::Register(x)) } else { (Operand::Register(x), operand) };
+                self.emit_greater_or_equal_flag(first, second, at)?;
+                if skip_when {
+                    Instruction::SkipEqImm { x: 0xF, nn: flag_when_true }
+                } else {
+                    Instruction::SkipNeImm { x: 0xF, nn: flag_when_true }
+                }
+            },
+            _ => return Err(operator.location.error(format!("expected a comparison, found {}", operator))),
+        };
+        self.emit(instruction, at)
+    }
+
+    /// Sets VF to 1 when `first >= second`, and to 0 otherwise.
+    fn emit_greater_or_equal_flag(&mut self, first: Operand, second: Operand, at: &Token) -> Result<(), AsmError> {
+        match (first, second) {
+            (Operand::Register(a), Operand::Register(b)) => {
+                self.emit(Instruction::LoadReg { x: 0xF, y: a }, at)?;
+                self.emit(Instruction::Sub { x: 0xF, y: b }, at)
+            },
+            (Operand::Register(a), Operand::Immediate(b)) => {
+                self.emit(Instruction::LoadImm { x: 0xF, nn: b }, at)?;
+                self.emit(Instruction::SubN { x: 0xF, y: a }, at)
+            },
+            (Operand::Immediate(a), Operand::Register(b)) => {
+                self.emit(Instruction::LoadImm { x: 0xF, nn: a }, at)?;
+                self.emit(Instruction::Sub { x: 0xF, y: b }, at)
+            },
+            (Operand::Immediate(_), Operand::Immediate(_)) => Err(at.location.error("cannot compare two constants")),
+        }
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.name()?;
+        let mut params = Vec::new();
+        loop {
+            let param = self.name()?;
+            match param.ident() {
+                Some("{") => break,
+                Some(param) => params.push(param.to_string()),
+                None => unreachable!(),
+            }
+        }
+        let body = self.braced_tokens(&name)?;
+        self.macros.insert(name.ident().unwrap().to_string(), Macro { params, body });
+        Ok(())
+    }
+
+    /// The tokens up to the `}` closing an already read `{`.
+    fn braced_tokens(&mut self, at: &Token) -> Result<Vec<Token>, AsmError> {
+        let mut depth = 1;
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next().map_err(|_| at.location.error("missing `}`"))?;
+            match token.ident() {
+                Some("{") => depth += 1,
+                Some("}") => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(tokens);
+                    }
+                },
+                _ => {},
+            }
+            tokens.push(token);
+        }
+    }
+
+    fn expand_macro(&mut self, token: &Token, name: &str) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_MACRO_EXPANSIONS {
+            return Err(token.location.error("too many macro expansions, is a macro invoking itself?"));
+        }
+        let (params, body) = {
+            let invoked = &self.macros[name];
+            (invoked.params.clone(), invoked.body.clone())
+        };
+        let mut args = Vec::new();
+        for _ in &params {
+            args.push(self.next()?);
+        }
+        let expanded: Vec<Token> = body.into_iter()
+            .map(|token| match token.ident().and_then(|ident| params.iter().position(|param| param == ident)) {
+                Some(index) => args[index].clone(),
+                None => token,
+            })
+            .collect();
+        self.tokens.splice(self.position..self.position, expanded);
+        Ok(())
+    }
+
+    fn braced_calc(&mut self) -> Result<i64, AsmError> {
+        let open = self.expect("{")?;
+        let tokens = self.braced_tokens(&open)?;
+        self.calc(&tokens, &open)
+    }
+
+    /// Evaluates `term (operator expression)?`, right to left.
+    fn calc(&self, tokens: &[Token], at: &Token) -> Result<i64, AsmError> {
+        let (value, rest) = self.calc_term(tokens, at)?;
+        let (operator, rest) = match rest.split_first() {
+            None => return Ok(value),
+            Some(split) => split,
+        };
+        let right = self.calc(rest, operator)?;
+        let result = match operator.ident().unwrap_or("") {
+            "+" => value.wrapping_add(right),
+            "-" => value.wrapping_sub(right),
+            "*" => value.wrapping_mul(right),
+            "/" | "%" if right == 0 => return Err(operator.location.error("division by zero")),
+            "/" => value / right,
+            "%" => value % right,
+            "&" => value & right,
+            "|" => value | right,
+            "^" => value ^ right,
+            "<<" => value.wrapping_shl(right as u32),
+            ">>" => value.wrapping_shr(right as u32),
+            _ => return Err(operator.location.error(format!("unknown operator {}", operator))),
+        };
+        Ok(result)
+    }
+
+    fn calc_term<'t>(&self, tokens: &'t [Token], at: &Token) -> Result<(i64, &'t [Token]), AsmError> {
+        let (token, rest) = tokens.split_first().ok_or_else(|| at.location.error("expected a value"))?;
+        match &token.kind {
+            TokenKind::Number(value) => Ok((*value, rest)),
+            TokenKind::Ident(name) => match name.as_str() {
+                "-" | "~" | "!" => {
+                    let (value, rest) = self.calc_term(rest, token)?;
+                    Ok((match name.as_str() { "-" => -value, "~" => !value, _ => (value == 0) as i64 }, rest))
+                },
+                "(" => {
+                    let mut depth = 0;
+                    let close = tokens.iter().position(|token| {
+                        match token.ident() {
+                            Some("(") => depth += 1,
+                            Some(")") => depth -= 1,
+                            _ => {},
+                        }
+                        depth == 0
+                    }).ok_or_else(|| token.location.error("unclosed `(`"))?;
+                    Ok((self.calc(&tokens[1..close], token)?, &tokens[close + 1..]))
+                },
+                "HERE" => Ok((self.here as i64, rest)),
+                _ => match self.constants.get(name).copied().or_else(|| self.labels.get(name).map(|addr| *addr as i64)) {
+                    Some(value) => Ok((value, rest)),
+                    None => Err(token.location.error(format!("unknown constant `{}`", name))),
+                },
+            },
+            _ => Err(token.location.error(format!("unexpected {}", token))),
+        }
+    }
+}
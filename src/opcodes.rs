@@ -1,16 +1,28 @@
+use std::error;
+use std::fmt;
+
 // CHIP-8 opcodes table
 pub const FAMILY_MISCEALLENOUS: u16                       = 0x0000;
 pub const FAMILY_ARITHMETICS: u16                         = 0x8000;
 pub const FAMILY_INPUT_CONDITIONALS: u16                  = 0xE000;
 pub const FAMILY_TIMERS_INPUT_QUERY_ETC: u16              = 0xF000;
 
+pub const OPCODE_SCROLL_DOWN: u16                         = 0x00C0;
+pub const OPCODE_SCROLL_UP: u16                           = 0x00D0;
 pub const OPCODE_CLEAR_SCREEN: u16                        = 0x00E0;
 pub const OPCODE_RETURN_FROM_SUBROUTINE: u16              = 0x00EE;
+pub const OPCODE_SCROLL_RIGHT: u16                        = 0x00FB;
+pub const OPCODE_SCROLL_LEFT: u16                         = 0x00FC;
+pub const OPCODE_EXIT: u16                                = 0x00FD;
+pub const OPCODE_LOW_RESOLUTION: u16                      = 0x00FE;
+pub const OPCODE_HIGH_RESOLUTION: u16                     = 0x00FF;
 pub const OPCODE_JMP: u16                                 = 0x1000;
 pub const OPCODE_CALL_SUBROUTINE: u16                     = 0x2000;
 pub const OPCODE_SKIP_IF_EQ_NN: u16                       = 0x3000;
 pub const OPCODE_SKIP_IF_NEQ_NN: u16                      = 0x4000;
 pub const OPCODE_SKIP_IF_EQ_XY: u16                       = 0x5000;
+pub const OPCODE_STORE_VX_TO_VY_AT_I: u16                 = 0x5002;
+pub const OPCODE_RESTORE_VX_TO_VY_FROM_I: u16             = 0x5003;
 pub const OPCODE_SET_VX_TO_NN: u16                        = 0x6000;
 pub const OPCODE_ADD_NN_TO_VX: u16                        = 0x7000;
 pub const OPCODE_SET_VX_TO_VY: u16                        = 0x8000;
@@ -29,12 +41,397 @@ pub const OPCODE_SET_VX_TO_NN_AND_RAND: u16               = 0xC000;
 pub const OPCODE_DRAW_SPRITE: u16                         = 0xD000;
 pub const OPCODE_SKIP_IF_VX_IS_PRESSED: u16               = 0xE09E;
 pub const OPCODE_SKIP_IF_VX_IS_NOT_PRESSED: u16           = 0xE0A1;
+pub const OPCODE_SET_I_TO_NNNN: u16                       = 0xF000;
+pub const OPCODE_SELECT_PLANES: u16                       = 0xF001;
+pub const OPCODE_LOAD_AUDIO_PATTERN: u16                  = 0xF002;
 pub const OPCODE_SET_VX_TO_DELAY_TIMER: u16               = 0xF007;
 pub const OPCODE_WAIT_FOR_INPUT_AND_SET_TO_VX: u16        = 0xF00A;
 pub const OPCODE_SET_DELAY_TIMER_TO_VX: u16               = 0xF015;
 pub const OPCODE_SET_SOUND_TIMER_TO_VX: u16               = 0xF018;
 pub const OPCODE_ADD_VX_TO_I: u16                         = 0xF01E;
 pub const OPCODE_SET_I_TO_SPRITE_IN_VX: u16               = 0xF029;
+pub const OPCODE_SET_I_TO_BIG_SPRITE_IN_VX: u16           = 0xF030;
+pub const OPCODE_SET_PITCH_TO_VX: u16                     = 0xF03A;
 pub const OPCODE_STORE_VX_AS_DIGITS_AT_I: u16             = 0xF033;
 pub const OPCODE_STORE_REGISTERS_AT_I: u16                = 0xF055;
 pub const OPCODE_RESTORE_REGISTERS_FROM_I: u16            = 0xF065;
+pub const OPCODE_STORE_REGISTERS_IN_RPL: u16              = 0xF075;
+pub const OPCODE_RESTORE_REGISTERS_FROM_RPL: u16          = 0xF085;
+
+/// A decoded CHIP-8 instruction.
+///
+/// `x` and `y` are register indexes (0x0..=0xF), `n` is a 4-bit immediate,
+/// `nn` an 8-bit immediate and `nnn` a 12-bit address. SUPER-CHIP 1.1 and
+/// XO-CHIP instructions are always decoded; a plain CHIP-8 program never
+/// uses them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0x0NNN: Call RCA 1802 program at address NNN
+    Sys { nnn: u16 },
+    /// 0x00CN: Scroll the display down by N pixels (SUPER-CHIP)
+    ScrollDown { n: u8 },
+    /// 0x00DN: Scroll the selected planes up by N pixels (XO-CHIP)
+    ScrollUp { n: u8 },
+    /// 0x00E0: Clear the screen
+    Cls,
+    /// 0x00EE: Return from subroutine
+    Ret,
+    /// 0x00FB: Scroll the display right by 4 pixels (SUPER-CHIP)
+    ScrollRight,
+    /// 0x00FC: Scroll the display left by 4 pixels (SUPER-CHIP)
+    ScrollLeft,
+    /// 0x00FD: Exit the interpreter (SUPER-CHIP)
+    Exit,
+    /// 0x00FE: Switch to the 64x32 low resolution mode (SUPER-CHIP)
+    LowRes,
+    /// 0x00FF: Switch to the 128x64 high resolution mode (SUPER-CHIP)
+    HighRes,
+    /// 0x1NNN: Jump to NNN
+    Jump { nnn: u16 },
+    /// 0x2NNN: Call subroutine at NNN
+    Call { nnn: u16 },
+    /// 0x3XNN: Skip the next instruction if VX equals NN
+    SkipEqImm { x: u8, nn: u8 },
+    /// 0x4XNN: Skip the next instruction if VX doesn't equal NN
+    SkipNeImm { x: u8, nn: u8 },
+    /// 0x5XY0: Skip the next instruction if VX equals VY
+    SkipEqReg { x: u8, y: u8 },
+    /// 0x5XY2: Store VX to VY (including VY, in either order) in memory starting at I (XO-CHIP)
+    StoreRange { x: u8, y: u8 },
+    /// 0x5XY3: Fill VX to VY (including VY, in either order) from memory starting at I (XO-CHIP)
+    LoadRange { x: u8, y: u8 },
+    /// 0x6XNN: Set VX to NN
+    LoadImm { x: u8, nn: u8 },
+    /// 0x7XNN: Add NN to VX (carry flag is not changed)
+    AddImm { x: u8, nn: u8 },
+    /// 0x8XY0: Set VX to the value of VY
+    LoadReg { x: u8, y: u8 },
+    /// 0x8XY1: Set VX to VX | VY
+    Or { x: u8, y: u8 },
+    /// 0x8XY2: Set VX to VX & VY
+    And { x: u8, y: u8 },
+    /// 0x8XY3: Set VX to VX ^ VY
+    Xor { x: u8, y: u8 },
+    /// 0x8XY4: Add VY to VX (VF is set to 1 if there is a carry, else 0)
+    AddReg { x: u8, y: u8 },
+    /// 0x8XY5: Substract VY from VX (VF is set to 0 if there is a borrow, else 1)
+    Sub { x: u8, y: u8 },
+    /// 0x8XY6: Store the LSB of VX in VF and then shift VX right by 1
+    ShiftRight { x: u8, y: u8 },
+    /// 0x8XY7: Set VX to VY minus VX (VF is set to 0 if there is a borrow, else 1)
+    SubN { x: u8, y: u8 },
+    /// 0x8XYE: Store the MSB of VX in VF and then shift VX left by 1
+    ShiftLeft { x: u8, y: u8 },
+    /// 0x9XY0: Skip the next instruction if VX doesn't equal VY
+    SkipNeReg { x: u8, y: u8 },
+    /// 0xANNN: Set I to the address NNN
+    LoadI { nnn: u16 },
+    /// 0xBNNN: Jump to the address NNN + V0
+    JumpV0 { nnn: u16 },
+    /// 0xCXNN: Set VX to the result of NN & rand()[0..255]
+    Rand { x: u8, nn: u8 },
+    /// 0xDXYN: Read N bytes from memory, starting in I. Those bytes are then displayed
+    ///         as sprites on screen at coordinates (VX, VY). Sprites are XORed onto the
+    ///         existing screen. If this causes any pixels to be erased, VF is set to 1,
+    ///         otherwise it is set to 0.
+    ///         With N = 0, a 16x16 sprite (two bytes per row) is drawn instead (SUPER-CHIP).
+    Draw { x: u8, y: u8, n: u8 },
+    /// 0xEX9E: Skip the next instruction if the key stored in VX is pressed
+    SkipKey { x: u8 },
+    /// 0xEXA1: Skip the next instruction if the key stored in VX isn't pressed
+    SkipNotKey { x: u8 },
+    /// 0xF000 NNNN: Set I to the 16-bit address NNNN stored in the next word (XO-CHIP)
+    LoadILong { nnnn: u16 },
+    /// 0xFN01: Select the bitplanes N (0-3) drawn to by DXYN, 00E0 and scrolling (XO-CHIP)
+    Plane { n: u8 },
+    /// 0xF002: Load the 16-byte audio pattern buffer from memory at I (XO-CHIP)
+    Audio,
+    /// 0xFX07: Set VX to the value of the delay timer
+    LoadDelay { x: u8 },
+    /// 0xFX0A: A key press is awaited, and then stored in VX (blocking)
+    WaitKey { x: u8 },
+    /// 0xFX15: Set the delay timer to VX
+    SetDelay { x: u8 },
+    /// 0xFX18: Set the sound timer to VX
+    SetSound { x: u8 },
+    /// 0xFX1E: Add VX to I
+    AddI { x: u8 },
+    /// 0xFX29: Set I to the location of the sprite for the character in VX.
+    ///         Characters 0-F (in hexadecimal) are represented by a 4x5 font.
+    LoadFont { x: u8 },
+    /// 0xFX30: Set I to the location of the 8x10 sprite for the character in VX (SUPER-CHIP)
+    LoadBigFont { x: u8 },
+    /// 0xFX3A: Set the audio pattern playback pitch to VX (XO-CHIP)
+    Pitch { x: u8 },
+    /// 0xFX33: Store the binary-coded decimal representation of VX: the hundreds
+    ///         digit at I, the tens digit at I+1, and the ones digit at I+2.
+    StoreBcd { x: u8 },
+    /// 0xFX55: Store V0 to VX (including VX) in memory starting at address I.
+    StoreRegs { x: u8 },
+    /// 0xFX65: Fill V0 to VX (including VX) with values from memory starting at address I.
+    LoadRegs { x: u8 },
+    /// 0xFX75: Store V0 to VX (including VX) in the RPL user flags (SUPER-CHIP)
+    StoreFlags { x: u8 },
+    /// 0xFX85: Fill V0 to VX (including VX) from the RPL user flags (SUPER-CHIP)
+    LoadFlags { x: u8 },
+}
+
+/// The opcode does not correspond to any instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown opcode {:04X}", self.opcode)
+    }
+}
+
+impl error::Error for DecodeError {}
+
+impl Instruction {
+    /// Decodes a single opcode word.
+    ///
+    /// `LoadILong` is the only instruction longer than one word: its address
+    /// lives in the following word, which `decode` cannot see, so it is
+    /// returned with `nnnn` set to 0. Use `decode_at` to decode it fully.
+    pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+
+        let instruction = match opcode & 0xF000 {
+            // 0x0--- family: Misceallenous
+            FAMILY_MISCEALLENOUS => match opcode {
+                _ if opcode & 0xFFF0 == OPCODE_SCROLL_DOWN => Instruction::ScrollDown { n },
+                _ if opcode & 0xFFF0 == OPCODE_SCROLL_UP => Instruction::ScrollUp { n },
+                OPCODE_CLEAR_SCREEN => Instruction::Cls,
+                OPCODE_RETURN_FROM_SUBROUTINE => Instruction::Ret,
+                OPCODE_SCROLL_RIGHT => Instruction::ScrollRight,
+                OPCODE_SCROLL_LEFT => Instruction::ScrollLeft,
+                OPCODE_EXIT => Instruction::Exit,
+                OPCODE_LOW_RESOLUTION => Instruction::LowRes,
+                OPCODE_HIGH_RESOLUTION => Instruction::HighRes,
+                _ => Instruction::Sys { nnn },
+            },
+            OPCODE_JMP => Instruction::Jump { nnn },
+            OPCODE_CALL_SUBROUTINE => Instruction::Call { nnn },
+            OPCODE_SKIP_IF_EQ_NN => Instruction::SkipEqImm { x, nn },
+            OPCODE_SKIP_IF_NEQ_NN => Instruction::SkipNeImm { x, nn },
+            OPCODE_SKIP_IF_EQ_XY => match opcode & 0xF00F {
+                OPCODE_SKIP_IF_EQ_XY => Instruction::SkipEqReg { x, y },
+                OPCODE_STORE_VX_TO_VY_AT_I => Instruction::StoreRange { x, y },
+                OPCODE_RESTORE_VX_TO_VY_FROM_I => Instruction::LoadRange { x, y },
+                _ => return Err(DecodeError { opcode }),
+            },
+            OPCODE_SET_VX_TO_NN => Instruction::LoadImm { x, nn },
+            OPCODE_ADD_NN_TO_VX => Instruction::AddImm { x, nn },
+            // 0x8--- family: Arithmetics
+            FAMILY_ARITHMETICS => match opcode & 0xF00F {
+                OPCODE_SET_VX_TO_VY => Instruction::LoadReg { x, y },
+                OPCODE_SET_VX_TO_VX_OR_VY => Instruction::Or { x, y },
+                OPCODE_SET_VX_TO_VX_AND_VY => Instruction::And { x, y },
+                OPCODE_SET_VX_TO_VX_XOR_VY => Instruction::Xor { x, y },
+                OPCODE_SET_VX_TO_VX_PLUS_VY => Instruction::AddReg { x, y },
+                OPCODE_SUBSTRACT_VY_FROM_VX => Instruction::Sub { x, y },
+                OPCODE_STORE_LSB_OF_VX_IN_VF_AND_RSHIFT_VX => Instruction::ShiftRight { x, y },
+                OPCODE_SET_VX_TO_VY_MINUS_VX => Instruction::SubN { x, y },
+                OPCODE_STORE_MSB_OF_VX_IN_VF_AND_LSHIFT_VX => Instruction::ShiftLeft { x, y },
+                _ => return Err(DecodeError { opcode }),
+            },
+            OPCODE_SKIP_IF_NEQ_XY if n == 0 => Instruction::SkipNeReg { x, y },
+            OPCODE_SET_I_TO_NNN => Instruction::LoadI { nnn },
+            OPCODE_JUMP_TO_NNN_PLUS_V0 => Instruction::JumpV0 { nnn },
+            OPCODE_SET_VX_TO_NN_AND_RAND => Instruction::Rand { x, nn },
+            OPCODE_DRAW_SPRITE => Instruction::Draw { x, y, n },
+            // 0xE--- family: Input conditionals
+            FAMILY_INPUT_CONDITIONALS => match opcode & 0xF0FF {
+                OPCODE_SKIP_IF_VX_IS_PRESSED => Instruction::SkipKey { x },
+                OPCODE_SKIP_IF_VX_IS_NOT_PRESSED => Instruction::SkipNotKey { x },
+                _ => return Err(DecodeError { opcode }),
+            },
+            // 0xF--- family: Timers, input query, and others
+            FAMILY_TIMERS_INPUT_QUERY_ETC => match opcode & 0xF0FF {
+                OPCODE_SET_I_TO_NNNN if x == 0 => Instruction::LoadILong { nnnn: 0 },
+                OPCODE_SELECT_PLANES => Instruction::Plane { n: x },
+                OPCODE_LOAD_AUDIO_PATTERN if x == 0 => Instruction::Audio,
+                OPCODE_SET_VX_TO_DELAY_TIMER => Instruction::LoadDelay { x },
+                OPCODE_WAIT_FOR_INPUT_AND_SET_TO_VX => Instruction::WaitKey { x },
+                OPCODE_SET_DELAY_TIMER_TO_VX => Instruction::SetDelay { x },
+                OPCODE_SET_SOUND_TIMER_TO_VX => Instruction::SetSound { x },
+                OPCODE_ADD_VX_TO_I => Instruction::AddI { x },
+                OPCODE_SET_I_TO_SPRITE_IN_VX => Instruction::LoadFont { x },
+                OPCODE_SET_I_TO_BIG_SPRITE_IN_VX => Instruction::LoadBigFont { x },
+                OPCODE_SET_PITCH_TO_VX => Instruction::Pitch { x },
+                OPCODE_STORE_VX_AS_DIGITS_AT_I => Instruction::StoreBcd { x },
+                OPCODE_STORE_REGISTERS_AT_I => Instruction::StoreRegs { x },
+                OPCODE_RESTORE_REGISTERS_FROM_I => Instruction::LoadRegs { x },
+                OPCODE_STORE_REGISTERS_IN_RPL => Instruction::StoreFlags { x },
+                OPCODE_RESTORE_REGISTERS_FROM_RPL => Instruction::LoadFlags { x },
+                _ => return Err(DecodeError { opcode }),
+            },
+            _ => return Err(DecodeError { opcode }),
+        };
+
+        Ok(instruction)
+    }
+
+    /// Decodes the instruction stored at `addr`, including the trailing
+    /// address word of `LoadILong`.
+    pub fn decode_at(memory: &[u8], addr: usize) -> Result<Instruction, DecodeError> {
+        let word = |at: usize| -> u16 {
+            (*memory.get(at).unwrap_or(&0) as u16) << 8 | *memory.get(at + 1).unwrap_or(&0) as u16
+        };
+
+        match Instruction::decode(word(addr))? {
+            Instruction::LoadILong { .. } => Ok(Instruction::LoadILong { nnnn: word(addr + 2) }),
+            instruction => Ok(instruction),
+        }
+    }
+
+    /// Size of the encoded instruction in bytes.
+    pub fn size(&self) -> usize {
+        match *self {
+            Instruction::LoadILong { .. } => 4,
+            _ => 2,
+        }
+    }
+
+    /// Encodes the instruction back to its bytes, big-endian.
+    pub fn encode_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.encode().to_be_bytes().to_vec();
+        if let Instruction::LoadILong { nnnn } = *self {
+            bytes.extend_from_slice(&nnnn.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Encodes the first opcode word of the instruction (see `encode_bytes`).
+    pub fn encode(&self) -> u16 {
+        fn xnn(base: u16, x: u8, nn: u8) -> u16 {
+            base | (x as u16 & 0xF) << 8 | nn as u16
+        }
+        fn xy(base: u16, x: u8, y: u8) -> u16 {
+            base | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4
+        }
+        fn x_only(base: u16, x: u8) -> u16 {
+            base | (x as u16 & 0xF) << 8
+        }
+
+        match *self {
+            Instruction::Sys { nnn } => nnn & 0x0FFF,
+            Instruction::ScrollDown { n } => OPCODE_SCROLL_DOWN | n as u16 & 0xF,
+            Instruction::ScrollUp { n } => OPCODE_SCROLL_UP | n as u16 & 0xF,
+            Instruction::Cls => OPCODE_CLEAR_SCREEN,
+            Instruction::Ret => OPCODE_RETURN_FROM_SUBROUTINE,
+            Instruction::ScrollRight => OPCODE_SCROLL_RIGHT,
+            Instruction::ScrollLeft => OPCODE_SCROLL_LEFT,
+            Instruction::Exit => OPCODE_EXIT,
+            Instruction::LowRes => OPCODE_LOW_RESOLUTION,
+            Instruction::HighRes => OPCODE_HIGH_RESOLUTION,
+            Instruction::Jump { nnn } => OPCODE_JMP | nnn & 0x0FFF,
+            Instruction::Call { nnn } => OPCODE_CALL_SUBROUTINE | nnn & 0x0FFF,
+            Instruction::SkipEqImm { x, nn } => xnn(OPCODE_SKIP_IF_EQ_NN, x, nn),
+            Instruction::SkipNeImm { x, nn } => xnn(OPCODE_SKIP_IF_NEQ_NN, x, nn),
+            Instruction::SkipEqReg { x, y } => xy(OPCODE_SKIP_IF_EQ_XY, x, y),
+            Instruction::StoreRange { x, y } => xy(OPCODE_STORE_VX_TO_VY_AT_I, x, y),
+            Instruction::LoadRange { x, y } => xy(OPCODE_RESTORE_VX_TO_VY_FROM_I, x, y),
+            Instruction::LoadImm { x, nn } => xnn(OPCODE_SET_VX_TO_NN, x, nn),
+            Instruction::AddImm { x, nn } => xnn(OPCODE_ADD_NN_TO_VX, x, nn),
+            Instruction::LoadReg { x, y } => xy(OPCODE_SET_VX_TO_VY, x, y),
+            Instruction::Or { x, y } => xy(OPCODE_SET_VX_TO_VX_OR_VY, x, y),
+            Instruction::And { x, y } => xy(OPCODE_SET_VX_TO_VX_AND_VY, x, y),
+            Instruction::Xor { x, y } => xy(OPCODE_SET_VX_TO_VX_XOR_VY, x, y),
+            Instruction::AddReg { x, y } => xy(OPCODE_SET_VX_TO_VX_PLUS_VY, x, y),
+            Instruction::Sub { x, y } => xy(OPCODE_SUBSTRACT_VY_FROM_VX, x, y),
+            Instruction::ShiftRight { x, y } => xy(OPCODE_STORE_LSB_OF_VX_IN_VF_AND_RSHIFT_VX, x, y),
+            Instruction::SubN { x, y } => xy(OPCODE_SET_VX_TO_VY_MINUS_VX, x, y),
+            Instruction::ShiftLeft { x, y } => xy(OPCODE_STORE_MSB_OF_VX_IN_VF_AND_LSHIFT_VX, x, y),
+            Instruction::SkipNeReg { x, y } => xy(OPCODE_SKIP_IF_NEQ_XY, x, y),
+            Instruction::LoadI { nnn } => OPCODE_SET_I_TO_NNN | nnn & 0x0FFF,
+            Instruction::JumpV0 { nnn } => OPCODE_JUMP_TO_NNN_PLUS_V0 | nnn & 0x0FFF,
+            Instruction::Rand { x, nn } => xnn(OPCODE_SET_VX_TO_NN_AND_RAND, x, nn),
+            Instruction::Draw { x, y, n } => xy(OPCODE_DRAW_SPRITE, x, y) | n as u16 & 0xF,
+            Instruction::SkipKey { x } => x_only(OPCODE_SKIP_IF_VX_IS_PRESSED, x),
+            Instruction::SkipNotKey { x } => x_only(OPCODE_SKIP_IF_VX_IS_NOT_PRESSED, x),
+            Instruction::LoadILong { .. } => OPCODE_SET_I_TO_NNNN,
+            Instruction::Plane { n } => x_only(OPCODE_SELECT_PLANES, n),
+            Instruction::Audio => OPCODE_LOAD_AUDIO_PATTERN,
+            Instruction::LoadDelay { x } => x_only(OPCODE_SET_VX_TO_DELAY_TIMER, x),
+            Instruction::WaitKey { x } => x_only(OPCODE_WAIT_FOR_INPUT_AND_SET_TO_VX, x),
+            Instruction::SetDelay { x } => x_only(OPCODE_SET_DELAY_TIMER_TO_VX, x),
+            Instruction::SetSound { x } => x_only(OPCODE_SET_SOUND_TIMER_TO_VX, x),
+            Instruction::AddI { x } => x_only(OPCODE_ADD_VX_TO_I, x),
+            Instruction::LoadFont { x } => x_only(OPCODE_SET_I_TO_SPRITE_IN_VX, x),
+            Instruction::LoadBigFont { x } => x_only(OPCODE_SET_I_TO_BIG_SPRITE_IN_VX, x),
+            Instruction::Pitch { x } => x_only(OPCODE_SET_PITCH_TO_VX, x),
+            Instruction::StoreBcd { x } => x_only(OPCODE_STORE_VX_AS_DIGITS_AT_I, x),
+            Instruction::StoreRegs { x } => x_only(OPCODE_STORE_REGISTERS_AT_I, x),
+            Instruction::LoadRegs { x } => x_only(OPCODE_RESTORE_REGISTERS_FROM_I, x),
+            Instruction::StoreFlags { x } => x_only(OPCODE_STORE_REGISTERS_IN_RPL, x),
+            Instruction::LoadFlags { x } => x_only(OPCODE_RESTORE_REGISTERS_FROM_RPL, x),
+        }
+    }
+}
+
+/// Formats the instruction as an assembly mnemonic, e.g. `DRW V0, V1, 5`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Sys { nnn } => write!(f, "SYS 0x{:03X}", nnn),
+            Instruction::ScrollDown { n } => write!(f, "SCD {}", n),
+            Instruction::ScrollUp { n } => write!(f, "SCU {}", n),
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::Jump { nnn } => write!(f, "JP 0x{:03X}", nnn),
+            Instruction::Call { nnn } => write!(f, "CALL 0x{:03X}", nnn),
+            Instruction::SkipEqImm { x, nn } => write!(f, "SE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipNeImm { x, nn } => write!(f, "SNE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::StoreRange { x, y } => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            Instruction::LoadImm { x, nn } => write!(f, "LD V{:X}, 0x{:02X}", x, nn),
+            Instruction::AddImm { x, nn } => write!(f, "ADD V{:X}, 0x{:02X}", x, nn),
+            Instruction::LoadReg { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubN { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadI { nnn } => write!(f, "LD I, 0x{:03X}", nnn),
+            Instruction::JumpV0 { nnn } => write!(f, "JP V0, 0x{:03X}", nnn),
+            Instruction::Rand { x, nn } => write!(f, "RND V{:X}, 0x{:02X}", x, nn),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKey { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipNotKey { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadILong { nnnn } => write!(f, "LD I, LONG 0x{:04X}", nnnn),
+            Instruction::Plane { n } => write!(f, "PLANE {}", n),
+            Instruction::Audio => write!(f, "AUDIO"),
+            Instruction::LoadDelay { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddI { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::LoadFont { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::LoadBigFont { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::Pitch { x } => write!(f, "LD PITCH, V{:X}", x),
+            Instruction::StoreBcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::StoreRegs { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::LoadRegs { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::StoreFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
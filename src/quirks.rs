@@ -0,0 +1,93 @@
+/// Behaviour switches for the instructions CHIP-8 interpreters historically
+/// disagree on.
+///
+/// `Quirks::default()` is this emulator's original behaviour. The named
+/// presets match the platforms most ROMs were written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VY and store the result in VX, instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// FX55/FX65 leave I pointing just past the last register transferred.
+    pub load_store_increments_i: bool,
+    /// BNNN jumps to NNN + VX, where X is the high nibble of NNN, instead of NNN + V0.
+    pub jump_uses_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub logic_resets_vf: bool,
+    /// DXYN clips sprites at the screen edges instead of wrapping them around.
+    pub clip_sprites: bool,
+    /// DXYN waits for the next vertical blank, so at most one sprite is drawn per frame.
+    pub display_wait: bool,
+}
+
+/// Names accepted by `Quirks::preset`.
+pub const PRESET_NAMES: [&str; 4] = ["default", "vip", "schip", "xochip"];
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter.
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            logic_resets_vf: true,
+            clip_sprites: true,
+            display_wait: true,
+        }
+    }
+
+    /// SUPER-CHIP 1.1 on the HP48.
+    pub fn super_chip() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            clip_sprites: true,
+            display_wait: false,
+        }
+    }
+
+    /// XO-CHIP, as implemented by Octo.
+    pub fn xo_chip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+            display_wait: false,
+        }
+    }
+
+    /// Packs the switches into a byte, in field order from the lowest bit.
+    pub fn to_bits(&self) -> u8 {
+        [self.shift_uses_vy, self.load_store_increments_i, self.jump_uses_vx,
+         self.logic_resets_vf, self.clip_sprites, self.display_wait]
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (bit, on)| bits | ((*on as u8) << bit))
+    }
+
+    /// Unpacks switches packed by `to_bits`; unknown bits are ignored.
+    pub fn from_bits(bits: u8) -> Quirks {
+        Quirks {
+            shift_uses_vy: bits & 0b00_0001 != 0,
+            load_store_increments_i: bits & 0b00_0010 != 0,
+            jump_uses_vx: bits & 0b00_0100 != 0,
+            logic_resets_vf: bits & 0b00_1000 != 0,
+            clip_sprites: bits & 0b01_0000 != 0,
+            display_wait: bits & 0b10_0000 != 0,
+        }
+    }
+
+    /// Looks up a preset by one of the names in `PRESET_NAMES`.
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name {
+            "default" => Some(Quirks::default()),
+            "vip" => Some(Quirks::cosmac_vip()),
+            "schip" => Some(Quirks::super_chip()),
+            "xochip" => Some(Quirks::xo_chip()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+
+/// One step back in time: the difference between a state and the one
+/// recorded just before it.
+struct Delta {
+    /// Length of the older state (states grow when switching to hi-res).
+    older_length: usize,
+    /// Run-length encoded XOR of the two states, see `encode`.
+    data: Vec<u8>,
+}
+
+/// A bounded history of save states, for rewinding gameplay frame by frame.
+///
+/// Only the most recent state is kept in full. Every older state is stored as
+/// a compressed XOR delta against its successor, which is tiny since a frame
+/// only touches a handful of bytes. The history is capped both in frames and
+/// in bytes; the oldest frames are forgotten first.
+pub struct RewindBuffer {
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+    max_frames: usize,
+    max_bytes: usize,
+    used_bytes: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(max_frames: usize, max_bytes: usize) -> RewindBuffer {
+        RewindBuffer {
+            latest: None,
+            deltas: VecDeque::new(),
+            max_frames,
+            max_bytes,
+            used_bytes: 0,
+        }
+    }
+
+    /// Records the state of a new frame.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(latest) = self.latest.take() {
+            let delta = Delta { older_length: latest.len(), data: encode(&xor(&latest, &state)) };
+            self.used_bytes += delta.data.len();
+            self.deltas.push_back(delta);
+        }
+        self.latest = Some(state);
+
+        while self.deltas.len() > self.max_frames || self.used_bytes > self.max_bytes {
+            match self.deltas.pop_front() {
+                Some(delta) => self.used_bytes -= delta.data.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Steps one frame back and returns that frame's state, or `None` once
+    /// the history is exhausted.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let delta = self.deltas.pop_back()?;
+        self.used_bytes -= delta.data.len();
+        let latest = self.latest.take().unwrap_or_default();
+        let mut older = xor(&latest, &decode(&delta.data));
+        older.truncate(delta.older_length);
+        self.latest = Some(older.clone());
+        Some(older)
+    }
+
+    /// Number of frames that can be rewound.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Bytes used by the compressed history, not counting the latest state.
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.used_bytes = 0;
+    }
+}
+
+/// XORs two byte strings, the shorter one padded with zeroes.
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    (0..a.len().max(b.len()))
+        .map(|index| a.get(index).unwrap_or(&0) ^ b.get(index).unwrap_or(&0))
+        .collect()
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn get_varint(data: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some(byte) = data.get(*position) {
+        *position += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 { break; }
+        shift += 7;
+    }
+    value
+}
+
+/// Encodes `data` as a sequence of (zero run length, literal length, literal bytes).
+fn encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        let zeroes = data[position..].iter().take_while(|byte| **byte == 0).count();
+        position += zeroes;
+        let literals = data[position..].iter().take_while(|byte| **byte != 0).count();
+        put_varint(&mut out, zeroes);
+        put_varint(&mut out, literals);
+        out.extend_from_slice(&data[position..position + literals]);
+        position += literals;
+    }
+    out
+}
+
+fn decode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        let zeroes = get_varint(data, &mut position);
+        out.resize(out.len() + zeroes, 0);
+        let literals = get_varint(data, &mut position);
+        let end = (position + literals).min(data.len());
+        out.extend_from_slice(&data[position..end]);
+        position = end;
+    }
+    out
+}
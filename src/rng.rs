@@ -0,0 +1,113 @@
+use std::error;
+use std::fmt;
+
+/// Source of the random bytes used by CXNN.
+///
+/// Implementations must be able to export and restore their state so save
+/// states and movies replay the exact same random sequence.
+pub trait RandomSource {
+    fn next_byte(&mut self) -> u8;
+
+    /// Serializes the generator's current state.
+    fn save_state(&self) -> Vec<u8>;
+
+    /// Restores a state produced by `save_state`.
+    fn load_state(&mut self, state: &[u8]) -> Result<(), InvalidRngState>;
+}
+
+/// The data passed to `RandomSource::load_state` is not a valid state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidRngState;
+
+impl fmt::Display for InvalidRngState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid random number generator state")
+    }
+}
+
+impl error::Error for InvalidRngState {}
+
+fn read_u64(state: &[u8]) -> Result<u64, InvalidRngState> {
+    let mut bytes = [0; 8];
+    if state.len() != bytes.len() {
+        return Err(InvalidRngState);
+    }
+    bytes.copy_from_slice(state);
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// A small, seedable xorshift64* generator. The default source of `Chip`.
+#[derive(Debug, Clone)]
+pub struct XorShiftRng {
+    state: u64,
+}
+
+impl XorShiftRng {
+    pub fn new(seed: u64) -> XorShiftRng {
+        // xorshift never leaves the all-zero state, so remap that seed.
+        let state = if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed };
+        XorShiftRng { state }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+impl RandomSource for XorShiftRng {
+    fn next_byte(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.state.to_le_bytes().to_vec()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), InvalidRngState> {
+        match read_u64(state)? {
+            0 => Err(InvalidRngState),
+            value => { self.state = value; Ok(()) },
+        }
+    }
+}
+
+/// Replays a fixed sequence of bytes, starting over once it is exhausted.
+/// Meant for tests that need to control exactly what CXNN returns.
+#[derive(Debug, Clone)]
+pub struct ScriptedRng {
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl ScriptedRng {
+    pub fn new(bytes: Vec<u8>) -> ScriptedRng {
+        ScriptedRng { bytes, position: 0 }
+    }
+}
+
+impl RandomSource for ScriptedRng {
+    fn next_byte(&mut self) -> u8 {
+        if self.bytes.is_empty() {
+            return 0;
+        }
+        let byte = self.bytes[self.position % self.bytes.len()];
+        self.position = (self.position + 1) % self.bytes.len();
+        byte
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        (self.position as u64).to_le_bytes().to_vec()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), InvalidRngState> {
+        let position = read_u64(state)? as usize;
+        if position > 0 && position >= self.bytes.len() {
+            return Err(InvalidRngState);
+        }
+        self.position = position;
+        Ok(())
+    }
+}
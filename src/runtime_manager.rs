@@ -1,139 +1,155 @@
-extern crate sfml;
+use std::collections::BTreeMap;
 
 use sfml::graphics;
 use sfml::window;
 
-use sfml::graphics::{Color, RenderTarget};
+use sfml::graphics::{RenderTarget, Transformable};
 use sfml::window::{Event, Key};
 
+use chip8::chip::{self, Chip};
+use chip8::error::ChipError;
+use chip8::keymap::{Binding, Hotkey, Keymap};
+
 const PIXEL_DISPLAY_SIZE: u32 = 1;
-const _EVENT_TO_KEYPAD_INDEX: [Key; 16] = [
-    Key::Num1, Key::Num2, Key::Num3, Key::Num4,
-    Key::Q, Key::W, Key::E, Key::R,
-    Key::A, Key::S, Key::D, Key::F,
-    Key::Z, Key::X, Key::C, Key::V
+// Colour of each pixel value, i.e. of each combination of XO-CHIP planes: (r, g, b)
+const PALETTE: [[u8; 3]; 4] = [
+    [0, 0, 0],          // BLACK: no plane
+    [255, 255, 255],    // WHITE: first plane
+    [170, 170, 170],    // LIGHT GREY: second plane
+    [85, 85, 85],       // DARK GREY: both planes
 ];
 
+/// Emulator controls triggered by hotkeys, for the main loop to carry out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// Held: run faster than real time.
+    FastForward(bool),
+    /// Held: run slower than real time.
+    SlowMotion(bool),
+    /// Held: step backwards through the recent frames.
+    Rewind(bool),
+    /// Save the state to the numbered quick-save slot.
+    SaveState(u8),
+    /// Load the state from the numbered quick-save slot.
+    LoadState(u8),
+    /// Stop in the `--debug` prompt.
+    Break,
+    /// Silence the buzzer, or bring it back.
+    Mute,
+    /// Stop the emulation, or let it go on.
+    Pause,
+    /// Restart the ROM from power-on.
+    Reset,
+}
+
 pub struct RuntimeManager {
     pub window: graphics::RenderWindow,
+    keymap: Keymap,
+    // Host keys held down, with the CHIP-8 key each one holds.
+    held: BTreeMap<String, u8>,
 }
 
 impl RuntimeManager {
-    pub fn new() -> RuntimeManager {
+    pub fn new(keymap: Keymap) -> RuntimeManager {
         let context_settings = window::ContextSettings {
             antialiasing_level: 0,
             ..Default::default()
         };
         RuntimeManager {
             window: graphics::RenderWindow::new(
-                        ((super::chip::SCREEN_WIDTH as u32) * PIXEL_DISPLAY_SIZE,
-                         (super::chip::SCREEN_HEIGHT as u32) * PIXEL_DISPLAY_SIZE),
+                        ((chip::SCREEN_WIDTH as u32) * PIXEL_DISPLAY_SIZE,
+                         (chip::SCREEN_HEIGHT as u32) * PIXEL_DISPLAY_SIZE),
                         "CHIP8-EMULATOR",
                         window::Style::DEFAULT,
                         &context_settings,
-                        )
+                        ),
+            keymap,
+            held: BTreeMap::new(),
         }
     }
 
-    pub fn handle_events(&mut self, chip: &mut super::chip::Chip,)
+    /// CHIP-8 keys held on the host keyboard, bit N = key N, for `Chip::set_keys`.
+    /// A key stays held as long as any of the host keys bound to it is.
+    pub fn keypad(&self) -> u16 {
+        self.held.values().fold(0, |keypad, key| keypad | 1 << key)
+    }
+
+    pub fn handle_events(&mut self, chip: &mut Chip) -> Vec<Action>
     {
+        let mut actions = Vec::new();
         while let Some(event) = self.window.poll_event() {
-            match event {
-                Event::Closed | Event::KeyPressed { code: Key::Escape, ..  } => { chip.exit_flag = 1; },
-                // What the fuck is this SHIT?
-                Event::KeyPressed  { code: Key::Num1, .. } => { chip.set_key(0,   true); }
-                Event::KeyPressed  { code: Key::Num2, .. } => { chip.set_key(1,   true); }
-                Event::KeyPressed  { code: Key::Num3, .. } => { chip.set_key(2,   true); }
-                Event::KeyPressed  { code: Key::Num4, .. } => { chip.set_key(3,   true); }
-                Event::KeyPressed  { code: Key::Q,    .. } => { chip.set_key(4,   true); }
-                Event::KeyPressed  { code: Key::W,    .. } => { chip.set_key(5,   true); }
-                Event::KeyPressed  { code: Key::E,    .. } => { chip.set_key(6,   true); }
-                Event::KeyPressed  { code: Key::R,    .. } => { chip.set_key(7,   true); }
-                Event::KeyPressed  { code: Key::A,    .. } => { chip.set_key(8,   true); }
-                Event::KeyPressed  { code: Key::S,    .. } => { chip.set_key(9,   true); }
-                Event::KeyPressed  { code: Key::D,    .. } => { chip.set_key(10,  true); }
-                Event::KeyPressed  { code: Key::F,    .. } => { chip.set_key(11,  true); }
-                Event::KeyPressed  { code: Key::Z,    .. } => { chip.set_key(12,  true); }
-                Event::KeyPressed  { code: Key::X,    .. } => { chip.set_key(13,  true); }
-                Event::KeyPressed  { code: Key::C,    .. } => { chip.set_key(14,  true); }
-                Event::KeyPressed  { code: Key::V,    .. } => { chip.set_key(15,  true); }
-                Event::KeyReleased { code: Key::Num1, .. } => { chip.set_key(0,  false); }
-                Event::KeyReleased { code: Key::Num2, .. } => { chip.set_key(1,  false); }
-                Event::KeyReleased { code: Key::Num3, .. } => { chip.set_key(2,  false); }
-                Event::KeyReleased { code: Key::Num4, .. } => { chip.set_key(3,  false); }
-                Event::KeyReleased { code: Key::Q,    .. } => { chip.set_key(4,  false); }
-                Event::KeyReleased { code: Key::W,    .. } => { chip.set_key(5,  false); }
-                Event::KeyReleased { code: Key::E,    .. } => { chip.set_key(6,  false); }
-                Event::KeyReleased { code: Key::R,    .. } => { chip.set_key(7,  false); }
-                Event::KeyReleased { code: Key::A,    .. } => { chip.set_key(8,  false); }
-                Event::KeyReleased { code: Key::S,    .. } => { chip.set_key(9,  false); }
-                Event::KeyReleased { code: Key::D,    .. } => { chip.set_key(10, false); }
-                Event::KeyReleased { code: Key::F,    .. } => { chip.set_key(11, false); }
-                Event::KeyReleased { code: Key::Z,    .. } => { chip.set_key(12, false); }
-                Event::KeyReleased { code: Key::X,    .. } => { chip.set_key(13, false); }
-                Event::KeyReleased { code: Key::C,    .. } => { chip.set_key(14, false); }
-                Event::KeyReleased { code: Key::V,    .. } => { chip.set_key(15, false); }
-                _ => {
-                    //println!("Un-handled event: {:?}", event);
-                }
-            }
+            let (code, shift, pressed) = match event {
+                Event::Closed => { chip.exit_flag = 1; continue; },
+                Event::KeyPressed { code, shift, .. } => (code, shift, true),
+                Event::KeyReleased { code, shift, .. } => (code, shift, false),
+                _ => continue,
+            };
+            // The keymap names keys as SFML does.
+            let name = format!("{:?}", code);
+            let action = match self.keymap.binding(&name) {
+                None => continue,
+                Some(Binding::Keypad(key)) => {
+                    if pressed {
+                        self.held.insert(name, key);
+                    } else {
+                        self.held.remove(&name);
+                    }
+                    continue;
+                },
+                Some(Binding::Hotkey(Hotkey::FastForward)) => Action::FastForward(pressed),
+                Some(Binding::Hotkey(Hotkey::SlowMotion)) => Action::SlowMotion(pressed),
+                Some(Binding::Hotkey(Hotkey::Rewind)) => Action::Rewind(pressed),
+                Some(Binding::Hotkey(_)) if !pressed => continue,
+                Some(Binding::Hotkey(Hotkey::Quit)) => { chip.exit_flag = 1; continue; },
+                Some(Binding::Hotkey(Hotkey::Save(slot))) if shift => Action::LoadState(slot),
+                Some(Binding::Hotkey(Hotkey::Save(slot))) => Action::SaveState(slot),
+                Some(Binding::Hotkey(Hotkey::Break)) => Action::Break,
+                Some(Binding::Hotkey(Hotkey::Mute)) => Action::Mute,
+                Some(Binding::Hotkey(Hotkey::Pause)) => Action::Pause,
+                Some(Binding::Hotkey(Hotkey::Reset)) => Action::Reset,
+            };
+            actions.push(action);
         }
+        actions
     }
 
-    pub fn draw_graphics(&mut self,
-         screen_buffer: &[u8; super::chip::SCREEN_WIDTH * super::chip::SCREEN_HEIGHT])
+    /// Draws a `width` x `height` framebuffer stretched over the whole window,
+    /// so both the 64x32 and the 128x64 modes fill the same area.
+    pub fn draw_graphics(&mut self, screen_buffer: &[u8], width: usize, height: usize)
     {
-        const W: u32 = super::chip::SCREEN_WIDTH as u32;
-        const H: u32 = super::chip::SCREEN_HEIGHT as u32;
         // SFML sprites, 4 bytes per pixel: (r, g, b, a)
-        let mut pixels = vec![0; (W * H * 4) as usize];
-        //let mut j = 0;
-        //for i in 0..(W * H) as usize {
-        //    match screen_buffer[i] {
-        //        0 => { // BLACK
-        //            pixels[j]     = 0;
-        //            pixels[j + 1] = 0;
-        //            pixels[j + 2] = 0;
-        //        },
-        //        _ => { // WHITE
-        //            pixels[j]     = 255;
-        //            pixels[j + 1] = 255;
-        //            pixels[j + 2] = 255;
-        //        }
-        //    }
-        //    pixels[j + 3] = 255;
-        //    j += 4;
-        //}
-
-        for y in 0..H as usize {
-            for x in 0..W as usize {
-                let pos = ((y * super::chip::SCREEN_WIDTH) + x) * 4;
-                match screen_buffer[y * super::chip::SCREEN_WIDTH + x] {
-                    0 => { // BLACK
-                        pixels[pos]     = 0;
-                        pixels[pos + 1] = 0;
-                        pixels[pos + 2] = 0;
-                    },
-                    _ => { // WHITE
-                        pixels[pos]     = 255;
-                        pixels[pos + 1] = 255;
-                        pixels[pos + 2] = 255;
-                    }
-                }
+        let mut pixels = vec![0; width * height * 4];
+        for y in 0..height {
+            for x in 0..width {
+                let pos = ((y * width) + x) * 4;
+                let colour = PALETTE[(screen_buffer[y * width + x] & 0b11) as usize];
+                pixels[pos..pos + 3].copy_from_slice(&colour);
                 pixels[pos + 3] = 255;
             }
         }
 
-        let mut texture = graphics::Texture::new(W, H).unwrap();
-        texture.update_from_pixels(&pixels, W, H, 0, 0);
-        let sprite  = graphics::Sprite::with_texture(&texture);
+        let (w, h) = (width as u32, height as u32);
+        let mut texture = graphics::Texture::new(w, h).unwrap();
+        texture.update_from_pixels(&pixels, w, h, 0, 0);
+        let mut sprite = graphics::Sprite::with_texture(&texture);
+        sprite.set_scale((chip::SCREEN_WIDTH as f32 / width as f32,
+                          chip::SCREEN_HEIGHT as f32 / height as f32));
         self.window.draw(&sprite);
         self.window.display();
     }
 
-    pub fn clear_screen(&mut self)
+    /// Freezes the last frame and reports `error` in the window title until
+    /// the user closes the window.
+    pub fn show_error(&mut self, error: &ChipError)
     {
-        self.window.clear(&Color::BLACK);
-        self.window.display();
+        self.window.set_title(&format!("CHIP8-EMULATOR - {}", error));
+        while self.window.is_open() {
+            match self.window.wait_event() {
+                Some(Event::Closed) | Some(Event::KeyPressed { code: Key::Escape, .. }) | None => {
+                    self.window.close();
+                },
+                _ => {}
+            }
+        }
     }
 }
@@ -0,0 +1,138 @@
+use std::error;
+use std::fmt;
+
+use crate::rng::InvalidRngState;
+
+/// First bytes of every save state.
+pub const MAGIC: &[u8; 4] = b"C8ST";
+/// Bumped whenever the layout written by `Chip::save_state` changes.
+pub const VERSION: u16 = 1;
+
+/// Errors raised by `Chip::load_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveStateError {
+    /// The data does not start with the save state magic bytes.
+    NotASaveState,
+    /// The save state was written by an incompatible version of the emulator.
+    UnsupportedVersion { found: u16, expected: u16 },
+    /// The save state was taken while running a different ROM.
+    RomMismatch { expected: u64, found: u64 },
+    /// The data ends in the middle of the state.
+    Truncated,
+    /// A field holds a value the emulator can never be in.
+    Corrupted(&'static str),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SaveStateError::NotASaveState =>
+                write!(f, "not a CHIP-8 save state"),
+            SaveStateError::UnsupportedVersion { found, expected } =>
+                write!(f, "save state version {} is not supported (expected version {})", found, expected),
+            SaveStateError::RomMismatch { expected, found } =>
+                write!(f, "save state belongs to a different ROM (ROM hash {:016x}, loaded ROM is {:016x})", found, expected),
+            SaveStateError::Truncated =>
+                write!(f, "save state is truncated"),
+            SaveStateError::Corrupted(field) =>
+                write!(f, "save state is corrupted: invalid {}", field),
+        }
+    }
+}
+
+impl error::Error for SaveStateError {}
+
+impl From<InvalidRngState> for SaveStateError {
+    fn from(_: InvalidRngState) -> SaveStateError {
+        SaveStateError::Corrupted("random number generator state")
+    }
+}
+
+/// 64-bit FNV-1a hash, used to identify ROMs and to fingerprint states.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Little-endian serializer for save state fields.
+#[derive(Default)]
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter::default()
+    }
+
+    pub fn put_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn put_u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Reads back the fields written by a `StateWriter`.
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> StateReader<'a> {
+        StateReader { bytes }
+    }
+
+    pub fn bytes(&mut self, length: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.bytes.len() < length {
+            return Err(SaveStateError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(length);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, SaveStateError> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, SaveStateError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, SaveStateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
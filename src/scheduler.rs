@@ -0,0 +1,72 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How many frames the scheduler may fall behind before it stops trying to
+/// catch up and resynchronises on the current time.
+const MAX_FRAMES_BEHIND: u32 = 4;
+
+/// How a frame's deadline was met.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pacing {
+    /// The frame finished in time (or the scheduler could still catch up).
+    OnTime,
+    /// The host could not keep up; `frames_behind` frames worth of time were skipped.
+    Behind { frames_behind: u32 },
+}
+
+/// Paces emulated frames against the wall clock.
+///
+/// Deadlines are computed from the previous deadline rather than from the
+/// moment the frame ended, so sleeping inaccuracies do not accumulate.
+pub struct FrameScheduler {
+    frame_duration: Duration,
+    speed: f64,
+    deadline: Instant,
+}
+
+impl FrameScheduler {
+    pub fn new(frames_per_second: u32) -> FrameScheduler {
+        let frame_duration = Duration::from_secs(1) / frames_per_second;
+        FrameScheduler {
+            frame_duration,
+            speed: 1.0,
+            deadline: Instant::now() + frame_duration,
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Sets the emulation speed multiplier: 2.0 runs twice as many frames per
+    /// second (fast-forward), 0.5 half as many (slow motion).
+    pub fn set_speed(&mut self, speed: f64) {
+        if speed > 0.0 && (speed - self.speed).abs() > f64::EPSILON {
+            self.speed = speed;
+            self.deadline = Instant::now() + self.scaled_frame_duration();
+        }
+    }
+
+    fn scaled_frame_duration(&self) -> Duration {
+        self.frame_duration.div_f64(self.speed)
+    }
+
+    /// Sleeps until the current frame's deadline, then schedules the next one.
+    pub fn wait_for_next_frame(&mut self) -> Pacing {
+        let frame_duration = self.scaled_frame_duration();
+        let now = Instant::now();
+
+        if now < self.deadline {
+            thread::sleep(self.deadline - now);
+        } else {
+            let frames_behind = ((now - self.deadline).as_secs_f64() / frame_duration.as_secs_f64()) as u32;
+            if frames_behind >= MAX_FRAMES_BEHIND {
+                self.deadline = now + frame_duration;
+                return Pacing::Behind { frames_behind };
+            }
+        }
+
+        self.deadline += frame_duration;
+        Pacing::OnTime
+    }
+}
@@ -0,0 +1,97 @@
+//! Encoders turning a `Chip::graphics` framebuffer into image files, for
+//! frontends without a window.
+
+/// Grey level of each pixel value, i.e. of each combination of XO-CHIP
+/// planes, matching the window frontend's palette.
+pub const SHADES: [u8; 4] = [0, 255, 170, 85];
+/// Character of each pixel value in `to_text`.
+pub const TEXT_PIXELS: [char; 4] = ['.', '#', 'o', '@'];
+
+/// Binary PBM (P4). Lit pixels, whatever their planes, are black on white.
+pub fn to_pbm(screen_buffer: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut image = format!("P4\n{} {}\n", width, height).into_bytes();
+    for row in screen_buffer.chunks(width).take(height) {
+        for pixels in row.chunks(8) {
+            let bits = pixels.iter().enumerate()
+                .fold(0, |bits, (bit, pixel)| if *pixel != 0 { bits | (0x80 >> bit) } else { bits });
+            image.push(bits);
+        }
+    }
+    image
+}
+
+/// 8-bit greyscale PNG, using `SHADES`.
+pub fn to_png(screen_buffer: &[u8], width: usize, height: usize) -> Vec<u8> {
+    // Every scanline starts with its filter type, 0 (none).
+    let mut scanlines = Vec::with_capacity((width + 1) * height);
+    for row in screen_buffer.chunks(width).take(height) {
+        scanlines.push(0);
+        scanlines.extend(row.iter().map(|pixel| SHADES[(pixel & 0b11) as usize]));
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 0, 0, 0, 0]); // 8 bits, greyscale, deflate, no filter, no interlace
+
+    let mut image = b"\x89PNG\r\n\x1a\n".to_vec();
+    put_png_chunk(&mut image, b"IHDR", &header);
+    put_png_chunk(&mut image, b"IDAT", &zlib_stored(&scanlines));
+    put_png_chunk(&mut image, b"IEND", &[]);
+    image
+}
+
+/// One line of `TEXT_PIXELS` per row of the screen.
+pub fn to_text(screen_buffer: &[u8], width: usize, height: usize) -> String {
+    let mut text = String::with_capacity((width + 1) * height);
+    for row in screen_buffer.chunks(width).take(height) {
+        text.extend(row.iter().map(|pixel| TEXT_PIXELS[(pixel & 0b11) as usize]));
+        text.push('\n');
+    }
+    text
+}
+
+fn put_png_chunk(image: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    image.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = image.len();
+    image.extend_from_slice(kind);
+    image.extend_from_slice(data);
+    let crc = crc32(&image[start..]);
+    image.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wraps `data` in a zlib stream of uncompressed deflate blocks; screens are
+/// small enough that compressing them is not worth the code.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+        stream.push(last as u8);
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 }
+        })
+    })
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    (b << 16) | a
+}
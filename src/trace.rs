@@ -0,0 +1,379 @@
+//! Diagnostics: leveled log messages per subsystem, and a compact trace of
+//! every executed instruction.
+//!
+//! Log messages go to stderr and are filtered by target and level with a
+//! `Filter` such as `cpu=trace,input=debug`. Every target is off by default,
+//! and a disabled message costs a single atomic load: its arguments are not
+//! even formatted.
+//!
+//! The instruction trace is written by a `Tracer` installed with
+//! `Chip::set_tracer`, one line per instruction:
+//!
+//! ```text
+//! 0200 6a02 v=00000000000000000000000000000000 i=0000 sp=00 dt=00 st=00 -> v=00000000000000000000020000000000 i=0000 sp=00 dt=00 st=00
+//! ```
+//!
+//! That is the address and opcode of the instruction, then the registers
+//! before and after it: V0-VF as 32 hex digits, I, the stack pointer and
+//! both timers, all in lowercase hex. Instructions stalled on the vertical
+//! blank are not traced, and FX0A is traced once however long it waits.
+//!
+//! `parse` reads the format back, so that a trace written by another
+//! emulator can be compared against this one with `first_divergence`.
+
+use std::cell::RefCell;
+use std::error;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Severity of a log message, from the most to the least important.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        })
+    }
+}
+
+/// Subsystem a log message comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// Every executed instruction.
+    Cpu,
+    /// The delay and sound timers.
+    Timers,
+    /// Key presses and FX0A.
+    Input,
+    /// Sprites, scrolling and resolution changes.
+    Render,
+}
+
+pub const TARGETS: [Target; 4] = [Target::Cpu, Target::Timers, Target::Input, Target::Render];
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            Target::Cpu => "cpu",
+            Target::Timers => "timers",
+            Target::Input => "input",
+            Target::Render => "render",
+        })
+    }
+}
+
+// Most verbose level enabled per target, 0 when the target is off.
+static LEVELS: [AtomicU8; 4] = [AtomicU8::new(0), AtomicU8::new(0), AtomicU8::new(0), AtomicU8::new(0)];
+
+/// Most verbose level logged for each target, parsed from a comma-separated
+/// list of `LEVEL` (for every target) and `TARGET=LEVEL` items, applied in
+/// order. `off` disables a target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Filter {
+    levels: [Option<Level>; 4],
+}
+
+impl Filter {
+    pub fn level(&self, target: Target) -> Option<Level> {
+        self.levels[target as usize]
+    }
+
+    /// Makes this filter the one applied to log messages.
+    pub fn install(&self) {
+        for (level, installed) in self.levels.iter().zip(LEVELS.iter()) {
+            installed.store(level.map_or(0, |level| level as u8), Ordering::Relaxed);
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Filter, String> {
+        let level = |name: &str| match name {
+            "off" => Ok(None),
+            "error" => Ok(Some(Level::Error)),
+            "warn" => Ok(Some(Level::Warn)),
+            "info" => Ok(Some(Level::Info)),
+            "debug" => Ok(Some(Level::Debug)),
+            "trace" => Ok(Some(Level::Trace)),
+            _ => Err(format!("unknown level `{}`", name)),
+        };
+
+        let mut filter = Filter::default();
+        for item in spec.split(',').map(str::trim) {
+            match item.split_once('=') {
+                Some((target, name)) => {
+                    let target = TARGETS.iter().find(|known| known.to_string() == target.trim())
+                        .ok_or_else(|| format!("unknown target `{}`", target.trim()))?;
+                    filter.levels[*target as usize] = level(name.trim())?;
+                },
+                None => filter.levels = [level(item)?; 4],
+            }
+        }
+        Ok(filter)
+    }
+}
+
+/// Whether messages of `level` from `target` are logged.
+#[inline]
+pub fn enabled(target: Target, level: Level) -> bool {
+    level as u8 <= LEVELS[target as usize].load(Ordering::Relaxed)
+}
+
+/// Writes a log message to stderr. Use `event!`, which skips the formatting
+/// when the message is filtered out.
+pub fn log(target: Target, level: Level, message: fmt::Arguments) {
+    eprintln!("{:5} {}: {}", level, target, message);
+}
+
+/// Logs a message if its target and level are enabled:
+/// `event!(Input, Debug, "key {:X} pressed", key)`.
+macro_rules! event {
+    ($target:ident, $level:ident, $($message:tt)+) => {
+        if $crate::trace::enabled($crate::trace::Target::$target, $crate::trace::Level::$level) {
+            $crate::trace::log($crate::trace::Target::$target, $crate::trace::Level::$level,
+                               format_args!($($message)+));
+        }
+    };
+}
+
+pub(crate) use event;
+
+/// Registers recorded around a traced instruction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Registers {
+    pub v: [u8; 16],
+    pub i: u32,
+    pub sp: usize,
+    pub delay_timer: u32,
+    pub sound_timer: u32,
+}
+
+impl Registers {
+    /// Describes every register that differs from `expected`.
+    fn differences(&self, expected: &Registers, when: &str, differences: &mut Vec<String>) {
+        let mut differ = |name: String, expected: String, actual: String| if expected != actual {
+            differences.push(format!("{} {}: expected {}, got {}", name, when, expected, actual));
+        };
+        for (x, (expected, actual)) in expected.v.iter().zip(self.v.iter()).enumerate() {
+            differ(format!("V{:X}", x), format!("{:02x}", expected), format!("{:02x}", actual));
+        }
+        differ("I".to_string(), format!("{:04x}", expected.i), format!("{:04x}", self.i));
+        differ("sp".to_string(), format!("{:02x}", expected.sp), format!("{:02x}", self.sp));
+        differ("dt".to_string(), format!("{:02x}", expected.delay_timer), format!("{:02x}", self.delay_timer));
+        differ("st".to_string(), format!("{:02x}", expected.sound_timer), format!("{:02x}", self.sound_timer));
+    }
+}
+
+impl FromStr for Registers {
+    type Err = String;
+
+    /// Parses `v=... i=... sp=... dt=... st=...`, in any order.
+    fn from_str(source: &str) -> Result<Registers, String> {
+        let hex = |name: &str, value: &str| u32::from_str_radix(value, 16)
+            .map_err(|_| format!("invalid {} `{}`", name, value));
+        let (mut v, mut i, mut sp, mut dt, mut st) = (None, None, None, None, None);
+        for field in source.split_whitespace() {
+            let (name, value) = field.split_once('=').ok_or_else(|| format!("expected NAME=VALUE, found `{}`", field))?;
+            match name {
+                "v" if value.len() == 32 && value.is_ascii() => {
+                    let mut registers = [0; 16];
+                    for (x, register) in registers.iter_mut().enumerate() {
+                        *register = u8::from_str_radix(&value[x * 2..x * 2 + 2], 16)
+                            .map_err(|_| format!("invalid v `{}`", value))?;
+                    }
+                    v = Some(registers);
+                },
+                "v" => return Err(format!("v must be 32 hex digits, found `{}`", value)),
+                "i" => i = Some(hex(name, value)?),
+                "sp" => sp = Some(hex(name, value)? as usize),
+                "dt" => dt = Some(hex(name, value)?),
+                "st" => st = Some(hex(name, value)?),
+                _ => return Err(format!("unknown register `{}`", name)),
+            }
+        }
+        let missing = |name: &str| format!("missing {}", name);
+        Ok(Registers {
+            v: v.ok_or_else(|| missing("v"))?,
+            i: i.ok_or_else(|| missing("i"))?,
+            sp: sp.ok_or_else(|| missing("sp"))?,
+            delay_timer: dt.ok_or_else(|| missing("dt"))?,
+            sound_timer: st.ok_or_else(|| missing("st"))?,
+        })
+    }
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "v=")?;
+        for v in &self.v {
+            write!(f, "{:02x}", v)?;
+        }
+        write!(f, " i={:04x} sp={:02x} dt={:02x} st={:02x}", self.i, self.sp, self.delay_timer, self.sound_timer)
+    }
+}
+
+/// One executed instruction, displayed as a line of the trace format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    pub pc: usize,
+    pub opcode: u16,
+    pub before: Registers,
+    pub after: Registers,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04x} {:04x} {} -> {}", self.pc, self.opcode, self.before, self.after)
+    }
+}
+
+impl Step {
+    /// Describes how `self` differs from `expected`, empty if it does not.
+    pub fn differences(&self, expected: &Step) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.pc != expected.pc {
+            differences.push(format!("pc: expected {:04x}, got {:04x}", expected.pc, self.pc));
+        }
+        if self.opcode != expected.opcode {
+            differences.push(format!("opcode: expected {:04x}, got {:04x}", expected.opcode, self.opcode));
+        }
+        self.before.differences(&expected.before, "before", &mut differences);
+        self.after.differences(&expected.after, "after", &mut differences);
+        differences
+    }
+}
+
+impl FromStr for Step {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Step, String> {
+        let (instruction, after) = line.split_once("->").ok_or("missing `->` between the registers")?;
+        let mut fields = instruction.trim().splitn(3, char::is_whitespace);
+        let mut hex = |name: &str| {
+            let value = fields.next().unwrap_or_default();
+            u16::from_str_radix(value, 16).map_err(|_| format!("invalid {} `{}`", name, value))
+        };
+        let pc = hex("pc")? as usize;
+        let opcode = hex("opcode")?;
+        let before = fields.next().unwrap_or_default();
+        Ok(Step { pc, opcode, before: before.parse()?, after: after.parse()? })
+    }
+}
+
+/// Error in a trace file, with the 1-based line it was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for TraceError {}
+
+/// Parses a trace, returning each step with the 1-based line it is on.
+/// Blank lines and `#` comments are ignored.
+pub fn parse(source: &str) -> Result<Vec<(usize, Step)>, TraceError> {
+    let mut steps = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let step = line.parse().map_err(|message| TraceError { line: index + 1, message })?;
+        steps.push((index + 1, step));
+    }
+    Ok(steps)
+}
+
+/// Where a run first departed from a reference trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Index of the first step that differs.
+    pub index: usize,
+    /// What differs, or why the step is missing from the run.
+    pub differences: Vec<String>,
+}
+
+/// Compares a run with the reference trace it should reproduce. Steps the run
+/// has past the end of the reference are not compared.
+pub fn first_divergence(expected: &[Step], actual: &[Step]) -> Option<Divergence> {
+    for (index, expected) in expected.iter().enumerate() {
+        let differences = match actual.get(index) {
+            Some(actual) => actual.differences(expected),
+            None => vec![format!("the run stopped after {} instructions", actual.len())],
+        };
+        if !differences.is_empty() {
+            return Some(Divergence { index, differences });
+        }
+    }
+    None
+}
+
+/// Receives every instruction a `Chip` executes.
+pub trait Tracer {
+    fn step(&mut self, step: &Step);
+
+    /// Flushes the trace, reporting any error met while writing it.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Collects the steps, for the caller to keep a clone to read them from.
+impl Tracer for Rc<RefCell<Vec<Step>>> {
+    fn step(&mut self, step: &Step) {
+        self.borrow_mut().push(*step);
+    }
+}
+
+/// Writes the trace format to `W`, one line per instruction.
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(writer: W) -> TraceWriter<W> {
+        TraceWriter { writer, error: None }
+    }
+}
+
+impl<W: Write> Tracer for TraceWriter<W> {
+    fn step(&mut self, step: &Step) {
+        // Emulation goes on after a write error, which `finish` reports.
+        if self.error.is_none() {
+            if let Err(e) = writeln!(self.writer, "{}", step) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.writer.flush(),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Assembling the `disasm` syntax with `chip8::asm`.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip8::asm::{self, Assembly};
+use chip8::disasm;
+
+fn assemble(source: &str) -> Assembly {
+    asm::assemble(source, Path::new("test.asm")).unwrap()
+}
+
+fn error(source: &str) -> String {
+    asm::assemble(source, Path::new("test.asm")).unwrap_err().to_string()
+}
+
+#[test]
+fn disassembled_games_assemble_back_to_the_same_bytes() {
+    let games = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("games");
+    for entry in fs::read_dir(games).unwrap() {
+        let path = entry.unwrap().path();
+        let rom = fs::read(&path).unwrap();
+        let assembly = asm::assemble(&disasm::disassemble(&rom), &path).unwrap();
+        assert_eq!(assembly.rom, rom, "{}", path.display());
+    }
+}
+
+#[test]
+fn macros_expand_their_arguments() {
+    let assembly = assemble("
+        :const SPEED 2
+        :alias x V6
+        :macro move reg, amount
+            ADD reg, amount
+            ADD reg, amount + 1
+        :endm
+        move x, SPEED
+        move V1, (SPEED - 1)
+    ");
+    assert_eq!(assembly.rom, [0x76, 0x02, 0x76, 0x03, 0x71, 0x01, 0x71, 0x02]);
+    assert_eq!(error(":macro twice a\n:endm\n:macro twice b\n:endm"), "test.asm:3:8: macro `twice` is already defined");
+    assert_eq!(error(":macro open\nCLS"), "test.asm:1:1: missing :endm");
+}
+
+#[test]
+fn includes_are_relative_to_the_including_file() {
+    let dir = env::temp_dir().join(format!("chip8-asm-{}", std::process::id()));
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(dir.join("main.asm"), "JP start\n:include \"lib/sprites.asm\"\nstart:\n    LD I, ball\n").unwrap();
+    fs::write(dir.join("lib/sprites.asm"), ":include \"ball.asm\"\n").unwrap();
+    fs::write(dir.join("lib/ball.asm"), "ball:\n    sprite \"##......\", \".#......\"\n").unwrap();
+    let assembly = asm::assemble_file(&dir.join("main.asm"));
+    fs::write(dir.join("lib/ball.asm"), "ball:\n    sprite \"##!\"\n").unwrap();
+    let broken = asm::assemble_file(&dir.join("main.asm")).unwrap_err();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(assembly.unwrap().rom, [0x12, 0x04, 0xC0, 0x40, 0xA2, 0x02]);
+    assert_eq!((broken.file, broken.line, broken.column), (dir.join("lib/ball.asm").display().to_string(), 2, 12));
+}
+
+#[test]
+fn errors_give_the_file_line_and_column() {
+    assert_eq!(error("CLS\n    LD V0, 256"), "test.asm:2:12: byte 256 out of range (-128..=0xFF)");
+    assert_eq!(error("start:\n  JP nowhere"), "test.asm:2:6: unknown symbol `nowhere`");
+    assert_eq!(error("start:\nstart:"), "test.asm:2:1: `start` is already defined");
+    assert_eq!(error("  FLY V0"), "test.asm:1:3: unknown instruction `FLY`");
+    assert_eq!(error("LD V0, 9223372036854775807 + 9223372036854775807"), "test.asm:1:30: expression overflows");
+    assert_eq!(error("LD V0, -(-9223372036854775807 - 1)"), "test.asm:1:9: expression overflows");
+}
+
+#[test]
+fn symbol_maps_list_labels_by_address() {
+    let assembly = assemble("
+        :const SPEED 2
+        main:
+            CALL draw
+            JP main
+        draw:
+            RET
+        data:
+            db 1, 2, SPEED
+    ");
+    assert_eq!(assembly.labels, [("main".to_string(), 0x200), ("draw".to_string(), 0x204), ("data".to_string(), 0x206)]);
+    assert_eq!(assembly.symbol_map(), "0x0200 main\n0x0204 draw\n0x0206 data\n");
+    assert_eq!(assembly.rom, [0x22, 0x04, 0x12, 0x00, 0x00, 0xEE, 1, 2, 2]);
+}
@@ -0,0 +1,78 @@
+//! The buzzer tones and audio capture of `chip8::audio`.
+
+mod common;
+
+use chip8::audio::{AudioCapture, Tone, Waveform};
+
+use common::boot;
+
+#[test]
+fn tones_loop_over_whole_periods() {
+    let tone = Tone { frequency: 441, volume: 0.5, waveform: Waveform::Square };
+    let samples = tone.loop_samples(44100);
+    assert_eq!(samples.len(), 44100);
+
+    // 100 samples per period: half high, half low, and the loop starts a new period.
+    assert!(samples[..50].iter().all(|sample| *sample == 16384));
+    assert!(samples[50..100].iter().all(|sample| *sample == -16384));
+    assert_eq!(samples[..100], samples[44000..]);
+    let rising = samples.windows(2).filter(|pair| pair[0] < pair[1]).count();
+    assert_eq!(rising, 440);
+}
+
+#[test]
+fn waveforms_have_the_same_peak() {
+    for waveform in ["square", "triangle", "sawtooth", "sine"] {
+        let tone = Tone { frequency: 100, volume: 1.0, waveform: waveform.parse().unwrap() };
+        let samples = tone.loop_samples(8000);
+        // The sawtooth's last sample falls one step short of the peak.
+        assert!(*samples.iter().max().unwrap() >= i16::MAX - i16::MAX / 40, "{}", waveform);
+        assert!(*samples.iter().min().unwrap() <= -i16::MAX, "{}", waveform);
+        assert_eq!(tone.waveform.to_string(), waveform);
+    }
+    assert!("noise".parse::<Waveform>().is_err());
+}
+
+fn capture(source: &str, frames: usize, sample_rate: u32) -> AudioCapture {
+    let mut chip = boot(source);
+    let tone = Tone { frequency: 100, volume: 1.0, waveform: Waveform::Square };
+    let mut audio = AudioCapture::new(tone, sample_rate);
+    for _ in 0..frames {
+        chip.run_frame(10).unwrap();
+        audio.capture_frame(&chip);
+    }
+    audio
+}
+
+#[test]
+fn the_buzzer_sounds_for_as_many_frames_as_the_sound_timer() {
+    let audio = capture(": main v0 := 3 buzzer := v0 loop again", 60, 1000);
+    // 1000 samples a second do not split evenly into frames, yet none is lost.
+    assert_eq!(audio.samples().len(), 1000);
+    let sounding = audio.samples().iter().rposition(|sample| *sample != 0).unwrap() + 1;
+    assert_eq!(sounding, 3 * 1000 / 60);
+
+    let wav = audio.to_wav();
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(wav.len(), 44 + 2000);
+    assert_eq!(wav[44..46], i16::MAX.to_le_bytes());
+}
+
+#[test]
+fn xo_chip_patterns_play_at_their_pitch() {
+    let source = "
+: pattern
+  0xF0 0x0F 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+: main
+  i := pattern
+  audio
+  v0 := 10
+  buzzer := v0
+  loop again
+";
+    // At the default pitch, 4000 bits a second: one bit per sample here.
+    let audio = capture(source, 1, 4000);
+    let bits: String = audio.samples()[..20].iter().map(|sample| if *sample > 0 { '1' } else { '0' }).collect();
+    assert_eq!(bits, "11110000000011110000");
+}
@@ -0,0 +1,17 @@
+//! Fixtures shared by the integration tests.
+
+use std::path::Path;
+
+use chip8::octo;
+use chip8::rng::XorShiftRng;
+use chip8::{Chip, Quirks};
+
+/// A chip with the Octo program `source` loaded, and a fixed random seed so
+/// that the states of two runs compare equal.
+pub fn boot(source: &str) -> Chip {
+    let assembly = octo::assemble(source, Path::new("test.8o")).unwrap();
+    let mut chip = Chip::new(Quirks::default());
+    chip.set_rng(Box::new(XorShiftRng::new(1)));
+    chip.load_rom(&assembly.rom).unwrap();
+    chip
+}
@@ -0,0 +1,29 @@
+//! The `--debug` prompt of the `chip8` binary, fed commands on stdin.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// The output of `chip8 --headless --debug` on BLINKY given the `commands`.
+fn console(commands: &str) -> String {
+    let rom = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("games/BLINKY");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chip8"))
+        .args(["--headless", "--debug", "--seed", "1", "--frames", "1"])
+        .arg(rom)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(commands.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn listing_stops_at_the_end_of_memory() {
+    let output = console("set pc 0xFFFC\nlist\nl 40000\nq\n");
+    let listing = "  0xFFFC  0000      SYS 0x000\n  0xFFFE  0000      SYS 0x000\n";
+    assert_eq!(output.matches(listing).count(), 2, "{}", output);
+}
@@ -0,0 +1,169 @@
+//! Stepping, breakpoints and watchpoints of `chip8::debugger` on small Octo programs.
+
+mod common;
+
+use std::path::Path;
+
+use chip8::debugger::{Access, Condition, Debugger, Stop, Watchpoint};
+use chip8::error::ChipError;
+use chip8::octo;
+
+use common::boot;
+
+const CYCLES_PER_FRAME: usize = 10;
+
+const PROGRAM: &str = "
+: add-two
+  v1 += 2
+  return
+: main
+  v0 := 60
+  delay := v0
+  add-two
+  add-two
+  loop
+    v2 += 1
+  again
+";
+
+fn label(source: &str, name: &str) -> usize {
+    let assembly = octo::assemble(source, Path::new("test.8o")).unwrap();
+    assembly.labels.iter().find(|(label, _)| label == name).unwrap().1 as usize
+}
+
+#[test]
+fn frames_match_an_undebugged_run() {
+    let mut plain = boot(PROGRAM);
+    let mut debugged = boot(PROGRAM);
+    let mut debugger = Debugger::new(CYCLES_PER_FRAME);
+
+    // Stepping part of a frame then resuming must not move the frame boundaries.
+    for _ in 0..3 {
+        debugger.step(&mut debugged).unwrap();
+    }
+    for _ in 0..20 {
+        plain.run_frame(CYCLES_PER_FRAME).unwrap();
+        assert_eq!(debugger.run_frame(&mut debugged).unwrap(), None);
+        assert_eq!(plain.save_state(), debugged.save_state());
+    }
+}
+
+#[test]
+fn breakpoint_stops_before_the_instruction_and_lets_it_go_on_resume() {
+    let mut chip = boot(PROGRAM);
+    let mut debugger = Debugger::new(CYCLES_PER_FRAME);
+    let add_two = label(PROGRAM, "add-two");
+    debugger.add_breakpoint(add_two);
+
+    assert_eq!(debugger.run_frame(&mut chip).unwrap(), Some(Stop::Breakpoint(add_two)));
+    assert_eq!(chip.pc(), add_two);
+    assert_eq!(chip.registers()[1], 0);
+
+    // The second call stops again, the first one is not reported twice.
+    assert_eq!(debugger.run_frame(&mut chip).unwrap(), Some(Stop::Breakpoint(add_two)));
+    assert_eq!(chip.registers()[1], 2);
+    assert_eq!(debugger.run_frame(&mut chip).unwrap(), None);
+    assert_eq!(chip.registers()[1], 4);
+}
+
+#[test]
+fn step_over_runs_the_whole_subroutine() {
+    let mut chip = boot(PROGRAM);
+    let mut debugger = Debugger::new(CYCLES_PER_FRAME);
+    while !debugger.step_over(&chip) {
+        debugger.step(&mut chip).unwrap();
+    }
+    let call = chip.pc();
+
+    assert_eq!(debugger.run_frame(&mut chip).unwrap(), Some(Stop::StepOver(call + 2)));
+    assert_eq!(chip.registers()[1], 2);
+    assert_eq!(chip.sp(), 0);
+}
+
+const SCORE: &str = "
+: digits
+  0 0 0
+: main
+  loop
+    v5 += 1
+    i := digits
+    bcd v5
+    load v2
+    i := hex v2
+    sprite v0 v0 5
+  again
+";
+
+#[test]
+fn watchpoints_stop_after_the_access() {
+    let mut chip = boot(SCORE);
+    let mut debugger = Debugger::new(CYCLES_PER_FRAME);
+    let ones = label(SCORE, "digits") + 2;
+    debugger.add_watchpoint(Watchpoint { start: ones, end: ones + 1, reads: false, writes: true });
+
+    let stop = debugger.run_frame(&mut chip).unwrap();
+    let bcd = label(SCORE, "main") + 4;
+    assert_eq!(stop, Some(Stop::Watchpoint { pc: bcd, addr: ones, access: Access::Write }));
+    assert_eq!(chip.pc(), bcd + 2);
+    assert_eq!(chip.memory()[ones], 1);
+
+    // Sprite data read by DXYN counts as a read.
+    debugger.clear_watchpoints();
+    // The font sprite of the digit 1, five bytes from 0x005.
+    let one = 5;
+    debugger.add_watchpoint(Watchpoint { start: one + 4, end: one + 5, reads: true, writes: false });
+    let stop = debugger.run_frame(&mut chip).unwrap();
+    assert_eq!(stop, Some(Stop::Watchpoint { pc: bcd + 6, addr: one + 4, access: Access::Read }));
+}
+
+#[test]
+fn conditional_breakpoints_count_every_hit() {
+    let mut chip = boot(SCORE);
+    let mut debugger = Debugger::new(CYCLES_PER_FRAME);
+    let main = label(SCORE, "main");
+    let condition = Condition::parse("v5 >= 0x3 && (hits >= 5 || [0x300] == 7)").unwrap();
+    debugger.add_conditional_breakpoint(main, Some(condition));
+
+    let mut stops = Vec::new();
+    while stops.len() < 2 {
+        if let Some(stop) = debugger.run_frame(&mut chip).unwrap() {
+            stops.push((stop, chip.registers()[5]));
+        }
+    }
+    assert_eq!(stops, [(Stop::Breakpoint(main), 4), (Stop::Breakpoint(main), 5)]);
+    assert_eq!(debugger.breakpoints().next().unwrap().1.hits, 6);
+}
+
+#[test]
+fn conditions_report_the_column_of_errors() {
+    let error = |source: &str| Condition::parse(source).unwrap_err().column;
+    assert_eq!(error("V3 == 0x10 &&"), 14);
+    assert_eq!(error("V3 = 1"), 4);
+    assert_eq!(error("(I > 0x300"), 11);
+    assert_eq!(error("VG == 1"), 1);
+    assert!(Condition::parse("V3 == 0x10 && I > 0x300").is_ok());
+}
+
+#[test]
+fn setters_change_the_state() {
+    let mut chip = boot(PROGRAM);
+    chip.set_register(0xA, 0x42);
+    chip.set_i(0x300);
+    chip.set_pc(0x204);
+    chip.set_stack_entry(0, 0x208);
+    chip.set_sp(1);
+    chip.set_delay_timer(5);
+    chip.set_sound_timer(6);
+
+    assert_eq!(chip.registers()[0xA], 0x42);
+    assert_eq!(chip.i(), 0x300);
+    assert_eq!(chip.pc(), 0x204);
+    assert_eq!(chip.stack()[..chip.sp()], [0x208]);
+    assert_eq!((chip.delay_timer(), chip.sound_timer()), (5, 6));
+
+    chip.set_memory(0xFFFE, &[1, 2]).unwrap();
+    assert_eq!(chip.memory()[0xFFFE..], [1, 2]);
+    let pc = chip.pc();
+    assert_eq!(chip.set_memory(0xFFFE, &[1, 2, 3]), Err(ChipError::MemoryOutOfBounds { pc, addr: 0x10000 }));
+    assert_eq!(chip.set_memory(0x12345, &[1]), Err(ChipError::MemoryOutOfBounds { pc, addr: 0x12345 }));
+}
@@ -0,0 +1,171 @@
+//! `chip8::gdb` driven by a minimal Remote Serial Protocol client over loopback.
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::thread;
+
+use chip8::debugger::{Debugger, Resume};
+use chip8::gdb::GdbStub;
+use chip8::octo;
+
+use common::boot;
+
+const PROGRAM: &str = "
+: digits
+  0 0 0
+: add-two
+  v1 += 2
+  return
+: main
+  loop
+    add-two
+    i := digits
+    bcd v1
+  again
+";
+
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        self.stream.write_all(format!("${}#{:02x}", data, checksum).as_bytes()).unwrap();
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    /// Waits for a packet, checking its checksum and acknowledging it.
+    fn receive(&mut self) -> String {
+        while self.read_byte() != b'$' {}
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        let checksum = [self.read_byte(), self.read_byte()];
+        let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+        assert_eq!(checksum, data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)));
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    fn request(&mut self, data: &str) -> String {
+        self.send(data);
+        assert_eq!(self.read_byte(), b'+', "request {} was not acknowledged", data);
+        self.receive()
+    }
+}
+
+fn label(name: &str) -> u16 {
+    let assembly = octo::assemble(PROGRAM, Path::new("test.8o")).unwrap();
+    assembly.labels.iter().find(|(label, _)| label == name).unwrap().1
+}
+
+/// Serves `PROGRAM` to a connected client until it kills it, then returns
+/// the chip's final state.
+fn start() -> (Client, thread::JoinHandle<Vec<u8>>) {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let mut chip = boot(PROGRAM);
+        let mut stub = GdbStub::accept(&listener, Debugger::new(10)).unwrap();
+        while stub.run_frame(&mut chip).unwrap() == Resume::Continue {}
+        chip.save_state()
+    });
+    let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream.set_nodelay(true).unwrap();
+    (Client { stream }, server)
+}
+
+/// Little-endian hex of a 16-bit register.
+fn word(value: u16) -> String {
+    format!("{:02x}{:02x}", value & 0xFF, value >> 8)
+}
+
+#[test]
+fn describes_the_target() {
+    let (mut client, server) = start();
+    assert!(client.request("qSupported:multiprocess+;swbreak+").contains("qXfer:features:read+"));
+    let xml = client.request("qXfer:features:read:target.xml:0,fff");
+    assert!(xml.starts_with("l<?xml"));
+    for register in ["v0", "vf", "\"i\"", "pc", "sp", "dt", "st"] {
+        assert!(xml.contains(register), "{} missing from the target description", register);
+    }
+    assert_eq!(client.request("?"), "S05");
+    assert_eq!(client.request("vMustReplyEmpty"), "");
+
+    // V0-VF, then I, pc, sp, dt and st.
+    let registers = client.request("g");
+    assert_eq!(registers, format!("{}{}{}{}", "00".repeat(16), word(0), word(0x200), "000000"));
+    client.send("k");
+    server.join().unwrap();
+}
+
+#[test]
+fn reads_and_writes_registers_and_memory() {
+    let (mut client, server) = start();
+    let digits = label("digits");
+    assert_eq!(client.request("P3=7f"), "OK");
+    assert_eq!(client.request("p3"), "7f");
+    assert_eq!(client.request(&format!("P10={}", word(0x345))), "OK");
+    assert_eq!(client.request(&format!("M{:x},3:010203", digits)), "OK");
+    assert_eq!(client.request(&format!("m{:x},3", digits)), "010203");
+    assert_eq!(client.request("mffff,4"), "00");
+    assert_eq!(client.request("m10000,4"), "E01");
+    assert_eq!(client.request("Pff=00"), "E01");
+    client.send("k");
+
+    let mut chip = boot(PROGRAM);
+    chip.load_state(&server.join().unwrap()).unwrap();
+    assert_eq!(chip.registers()[3], 0x7f);
+    assert_eq!(chip.i(), 0x345);
+    assert_eq!(chip.memory()[digits as usize..digits as usize + 3], [1, 2, 3]);
+}
+
+#[test]
+fn stops_at_breakpoints_and_watchpoints() {
+    let (mut client, server) = start();
+    let add_two = label("add-two");
+    let ones = label("digits") + 2;
+
+    assert_eq!(client.request(&format!("Z0,{:x},2", add_two)), "OK");
+    assert_eq!(client.request("c"), "T05swbreak:;");
+    assert_eq!(client.request("p11"), word(add_two));
+    assert_eq!(client.request("s"), "T05");
+    assert_eq!(client.request("p11"), word(add_two + 2));
+    assert_eq!(client.request("p1"), "02");
+    assert_eq!(client.request(&format!("z0,{:x},2", add_two)), "OK");
+
+    assert_eq!(client.request("Z2,10000,2"), "E01");
+    assert_eq!(client.request("Z3,ffff,ffffffffffffffff"), "OK");
+    assert_eq!(client.request("z3,ffff,ffffffffffffffff"), "OK");
+    assert_eq!(client.request(&format!("Z2,{:x},1", ones)), "OK");
+    assert_eq!(client.request("c"), format!("T05watch:{:x};", ones));
+    assert_eq!(client.request(&format!("m{:x},1", ones)), "02");
+    assert_eq!(client.request(&format!("z2,{:x},1", ones)), "OK");
+    client.send("k");
+    server.join().unwrap();
+}
+
+#[test]
+fn interrupts_a_running_program() {
+    let (mut client, server) = start();
+    client.send("c");
+    assert_eq!(client.read_byte(), b'+');
+    client.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(client.receive(), "T02");
+    assert!(client.request("p1") != "00", "the program did not run");
+    client.send("k");
+    server.join().unwrap();
+}
@@ -0,0 +1,130 @@
+//! Golden-image regression suite over the ROMs in `games/`.
+//!
+//! Every ROM is booted with a fixed seed, fed the scripted input from
+//! `tests/golden/input/ROM.txt` (or `tests/golden/default.txt`) and run for
+//! `FRAMES` frames. Hashes of the final screen and registers are compared
+//! against `tests/golden/goldens.txt`.
+//!
+//! When behaviour changes on purpose, re-bless the goldens with
+//!
+//!     CHIP8_BLESS=1 cargo test --no-default-features --test golden
+//!
+//! and review the diff of `goldens.txt`.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip8::headless::{self, InputScript};
+use chip8::rng::XorShiftRng;
+use chip8::savestate::fnv1a;
+use chip8::{Chip, Quirks};
+
+const SEED: u64 = 0x5EED;
+const FRAMES: u32 = 600;
+const CYCLES_PER_FRAME: usize = 10;
+
+/// Final state of one golden run, as stored in `goldens.txt`.
+#[derive(Debug, PartialEq, Eq)]
+struct Outcome {
+    frames: u32,
+    graphics: u64,
+    registers: u64,
+}
+
+fn root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn input_for(rom: &str) -> InputScript {
+    let golden = root().join("tests/golden");
+    let specific = golden.join("input").join(format!("{}.txt", rom));
+    let path = if specific.exists() { specific } else { golden.join("default.txt") };
+    let source = fs::read_to_string(&path).unwrap();
+    InputScript::parse(&source).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+fn registers_hash(chip: &Chip) -> u64 {
+    let mut bytes = chip.registers().to_vec();
+    bytes.extend_from_slice(&chip.i().to_le_bytes());
+    bytes.extend_from_slice(&(chip.pc() as u32).to_le_bytes());
+    for address in &chip.stack()[..chip.sp()] {
+        bytes.extend_from_slice(&(*address as u32).to_le_bytes());
+    }
+    bytes.extend_from_slice(&chip.delay_timer().to_le_bytes());
+    bytes.extend_from_slice(&chip.sound_timer().to_le_bytes());
+    fnv1a(&bytes)
+}
+
+fn run(rom: &Path) -> Result<Outcome, String> {
+    let name = rom.file_name().unwrap().to_string_lossy();
+    let mut chip = Chip::new(Quirks::default());
+    chip.set_rng(Box::new(XorShiftRng::new(SEED)));
+    chip.load_game(rom).map_err(|e| e.to_string())?;
+    let frames = headless::run(&mut chip, FRAMES, CYCLES_PER_FRAME, &input_for(&name), &mut |_| {}).map_err(|e| e.to_string())?;
+    Ok(Outcome {
+        frames,
+        graphics: fnv1a(chip.graphics()),
+        registers: registers_hash(&chip),
+    })
+}
+
+fn parse_goldens(source: &str) -> Vec<(String, Outcome)> {
+    source.lines()
+        .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .map(|line| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let hash = |field: &str| u64::from_str_radix(field, 16).unwrap();
+            (fields[0].to_string(), Outcome {
+                frames: fields[1].parse().unwrap(),
+                graphics: hash(fields[2]),
+                registers: hash(fields[3]),
+            })
+        })
+        .collect()
+}
+
+fn format_goldens(outcomes: &[(String, Outcome)]) -> String {
+    let mut text = String::from("# ROM       frames  graphics          registers\n");
+    for (rom, outcome) in outcomes {
+        text += &format!("{:<10}  {:>6}  {:016x}  {:016x}\n", rom, outcome.frames, outcome.graphics, outcome.registers);
+    }
+    text
+}
+
+#[test]
+fn games_match_goldens() {
+    let mut roms: Vec<_> = fs::read_dir(root().join("games")).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    roms.sort();
+
+    let mut outcomes = Vec::new();
+    let mut failures = Vec::new();
+    for rom in &roms {
+        let name = rom.file_name().unwrap().to_string_lossy().into_owned();
+        match run(rom) {
+            Ok(outcome) => outcomes.push((name, outcome)),
+            Err(e) => failures.push(format!("{}: {}", name, e)),
+        }
+    }
+    assert!(failures.is_empty(), "emulation failed:\n{}", failures.join("\n"));
+
+    let goldens_path = root().join("tests/golden/goldens.txt");
+    if env::var_os("CHIP8_BLESS").is_some() {
+        fs::write(&goldens_path, format_goldens(&outcomes)).unwrap();
+        return;
+    }
+
+    let goldens = parse_goldens(&fs::read_to_string(&goldens_path).unwrap_or_default());
+    for (rom, outcome) in &outcomes {
+        match goldens.iter().find(|(golden_rom, _)| golden_rom == rom) {
+            Some((_, golden)) if golden == outcome => {},
+            Some((_, golden)) => failures.push(format!("{}: expected {:?}, got {:?}", rom, golden, outcome)),
+            None => failures.push(format!("{}: no golden value", rom)),
+        }
+    }
+    assert!(failures.is_empty(),
+            "{} of {} ROMs differ from their goldens (re-bless with CHIP8_BLESS=1 if intended):\n{}",
+            failures.len(), outcomes.len(), failures.join("\n"));
+}
@@ -0,0 +1,55 @@
+//! Presets and config files of `chip8::keymap`.
+
+use chip8::keymap::{Binding, Hotkey, Keymap};
+
+#[test]
+fn presets_lay_the_keypad_over_four_rows() {
+    let qwerty = Keymap::default();
+    assert_eq!(qwerty.binding("Num1"), Some(Binding::Keypad(0x0)));
+    assert_eq!(qwerty.binding("V"), Some(Binding::Keypad(0xF)));
+    assert_eq!(qwerty.binding("Escape"), Some(Binding::Hotkey(Hotkey::Quit)));
+    assert_eq!(qwerty.binding("F3"), Some(Binding::Hotkey(Hotkey::Save(3))));
+
+    let azerty = Keymap::preset("azerty").unwrap();
+    assert_eq!(azerty.keys(Binding::Keypad(0x4)), ["A"]);
+    assert_eq!(azerty.keys(Binding::Keypad(0xC)), ["W"]);
+    let dvorak = Keymap::preset("dvorak").unwrap();
+    assert_eq!(dvorak.keys(Binding::Keypad(0x5)), ["Comma"]);
+    assert_eq!(dvorak.keys(Binding::Hotkey(Hotkey::Pause)), ["Space"]);
+    assert!(Keymap::preset("colemak").is_none());
+}
+
+#[test]
+fn configs_rebind_keys_on_top_of_the_preset() {
+    let mut keymap = Keymap::default();
+    keymap.apply("
+# Arrows move, and W becomes the pause key.
+5 = Up W
+8 = Down
+pause = W P   # W is taken from key 5
+save = F5 F6
+").unwrap();
+
+    assert_eq!(keymap.keys(Binding::Keypad(0x5)), ["Up"]);
+    assert_eq!(keymap.keys(Binding::Keypad(0x8)), ["Down"]);
+    assert_eq!(keymap.binding("A"), None);
+    assert_eq!(keymap.keys(Binding::Hotkey(Hotkey::Pause)), ["P", "W"]);
+    assert_eq!(keymap.binding("Space"), None);
+    assert_eq!(keymap.binding("F1"), None);
+    assert_eq!(keymap.binding("F6"), Some(Binding::Hotkey(Hotkey::Save(2))));
+
+    keymap.apply("preset dvorak\n").unwrap();
+    assert_eq!(keymap, Keymap::preset("dvorak").unwrap());
+}
+
+#[test]
+fn config_errors_give_the_line() {
+    let error = |config: &str| {
+        let error = Keymap::default().apply(config).unwrap_err();
+        (error.line, error.message)
+    };
+    assert_eq!(error("\n5 = Up\n10 = Down"), (3, "unknown CHIP-8 key or hotkey `10`".to_string()));
+    assert_eq!(error("5 = Uppp"), (1, "unknown host key `Uppp`".to_string()));
+    assert_eq!(error("5 Up"), (1, "expected `KEY = HOST_KEYS...` or `preset NAME`, found `5 Up`".to_string()));
+    assert_eq!(error("preset colemak").1, "unknown preset `colemak`, expected one of qwerty, azerty, dvorak");
+}
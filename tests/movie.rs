@@ -0,0 +1,74 @@
+//! Recording and replaying input with `chip8::movie`.
+
+mod common;
+
+use chip8::movie::{Movie, MovieError, MAGIC, VERSION};
+use chip8::{Chip, Quirks};
+
+use common::boot;
+
+const CYCLES_PER_FRAME: u32 = 10;
+
+const PROGRAM: &str = "
+: main
+  v0 := key
+  v1 += v0
+  jump main
+";
+
+/// A movie of `PROGRAM` held `keys[frame]` in every frame.
+fn record(keys: &[u16]) -> Movie {
+    let mut chip = boot(PROGRAM);
+    let mut movie = Movie::new(chip.rom_hash(), 1, Quirks::default(), CYCLES_PER_FRAME);
+    for keys in keys {
+        chip.set_keys(*keys);
+        chip.run_frame(CYCLES_PER_FRAME as usize).unwrap();
+        movie.record_frame(*keys, &chip);
+    }
+    movie
+}
+
+fn replay(movie: &Movie) -> (Option<usize>, Chip) {
+    let mut chip = boot(PROGRAM);
+    let divergence = movie.replay(&mut chip, &mut |_| {}).unwrap();
+    (divergence, chip)
+}
+
+#[test]
+fn a_movie_round_trips_through_bytes() {
+    let movie = record(&[0, 1 << 3, 0, 1 << 5, 1 << 5, 0]);
+    assert_eq!(Movie::from_bytes(&movie.to_bytes()), Ok(movie.clone()));
+
+    let bytes = movie.to_bytes();
+    assert_eq!(Movie::from_bytes(&bytes[..bytes.len() - 1]), Err(MovieError::Truncated));
+    assert_eq!(Movie::from_bytes(b"C8ST"), Err(MovieError::NotAMovie));
+    let mut newer = bytes;
+    newer[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert_eq!(Movie::from_bytes(&newer), Err(MovieError::UnsupportedVersion { found: VERSION + 1, expected: VERSION }));
+}
+
+#[test]
+fn a_zero_speed_is_rejected() {
+    let movie = Movie::new(0, 1, Quirks::default(), 0);
+    assert_eq!(Movie::from_bytes(&movie.to_bytes()), Err(MovieError::InvalidSpeed { cycles_per_frame: 0 }));
+    let movie = Movie::new(0, 1, Quirks::default(), u32::MAX);
+    assert_eq!(Movie::from_bytes(&movie.to_bytes()), Err(MovieError::InvalidSpeed { cycles_per_frame: u32::MAX }));
+}
+
+#[test]
+fn replays_report_the_first_divergent_frame() {
+    let keys = [0, 1 << 3, 0, 1 << 5, 1 << 5, 0, 1 << 2, 0];
+    let movie = record(&keys);
+    let (divergence, chip) = replay(&movie);
+    assert_eq!(divergence, None);
+    assert!(movie.check_frame(keys.len() - 1, &chip));
+    assert!(!movie.check_frame(keys.len() - 2, &chip));
+    assert!(!movie.check_frame(keys.len(), &chip));
+
+    // Holding other keys from frame 3 on changes every state after it.
+    let mut edited = movie.clone();
+    for frame in &mut edited.frames[3..] {
+        frame.keys = 1 << 9;
+    }
+    assert_eq!(replay(&edited).0, Some(3));
+}
@@ -0,0 +1,70 @@
+//! Compiling Octo sources with `chip8::octo`.
+
+use std::path::Path;
+
+use chip8::octo;
+
+fn error(source: &str) -> String {
+    octo::assemble(source, Path::new("test.8o")).unwrap_err().to_string()
+}
+
+#[test]
+fn jumps_past_0xfff_are_out_of_reach() {
+    assert_eq!(error(": main :org 0x1000 : far loop v0 += 1 again"),
+               "test.8o:1:39: `again` jumps out of reach to 0x1000");
+    assert_eq!(error(": main if v0 == 1 begin :org 0x1000 v0 := 1 else v1 := 1 end"),
+               "test.8o:1:45: `else` jumps out of reach to 0x1004");
+    assert_eq!(error(": main if v0 == 1 begin v0 := 1 :org 0x1000 end"),
+               "test.8o:1:45: `end` jumps out of reach to 0x1000");
+    assert_eq!(error(":org 0x1000 : main"), "test.8o:1:1: label `main` is out of reach at 0x1000");
+
+    let assembly = octo::assemble(": main :org 0xFFC loop v0 += 1 again", Path::new("test.8o")).unwrap();
+    assert_eq!(assembly.rom[0xFFE - 0x200..], [0x1F, 0xFC]);
+}
+
+/// The bytes `source` compiles to, starting at `main` in 0x200.
+fn rom(source: &str) -> Vec<u8> {
+    octo::assemble(&format!(": main {}", source), Path::new("test.8o")).unwrap().rom
+}
+
+#[test]
+fn conditions_skip_the_statement_they_guard() {
+    // `then` skips the next statement when the condition is false...
+    assert_eq!(rom("if v0 == 1 then v1 := 2"), [0x40, 0x01, 0x61, 0x02]);
+    assert_eq!(rom("if v0 != v3 then v1 := 2"), [0x50, 0x30, 0x61, 0x02]);
+    assert_eq!(rom("if v0 key then v1 := 2"), [0xE0, 0xA1, 0x61, 0x02]);
+    // ...and `begin` the jump to `else` when it is true.
+    assert_eq!(rom("if v0 == 1 begin v1 := 2 else v1 := 3 end"),
+               [0x30, 0x01, 0x12, 0x08, 0x61, 0x02, 0x12, 0x0A, 0x61, 0x03]);
+    assert_eq!(rom("if v0 -key begin v1 := 2 end"), [0xE0, 0xA1, 0x12, 0x06, 0x61, 0x02]);
+}
+
+#[test]
+fn ordered_comparisons_go_through_vf() {
+    // VF := first - second leaves VF = 1 when first >= second.
+    assert_eq!(rom("if v0 < v1 then v2 := 0"), [0x8F, 0x00, 0x8F, 0x15, 0x4F, 0x00, 0x62, 0x00]);
+    assert_eq!(rom("if v0 > 5 then v2 := 0"), [0x6F, 0x05, 0x8F, 0x05, 0x4F, 0x00, 0x62, 0x00]);
+    assert_eq!(rom("if v0 <= v1 then v2 := 0"), [0x8F, 0x10, 0x8F, 0x05, 0x4F, 0x01, 0x62, 0x00]);
+    assert_eq!(rom("if v0 >= 5 then v2 := 0"), [0x6F, 0x05, 0x8F, 0x07, 0x4F, 0x01, 0x62, 0x00]);
+    assert_eq!(rom("if v0 < v1 begin v2 := 0 end"), [0x8F, 0x00, 0x8F, 0x15, 0x3F, 0x00, 0x12, 0x0A, 0x62, 0x00]);
+}
+
+#[test]
+fn macros_and_calc_expand_at_compile_time() {
+    assert_eq!(rom(":macro bump reg amount { reg += amount reg += amount } bump v3 2 bump v4 1"),
+               [0x73, 0x02, 0x73, 0x02, 0x74, 0x01, 0x74, 0x01]);
+    // Operators apply right to left: 2 * (3 + 1).
+    assert_eq!(rom(":const base 3 :calc size { 2 * base + 1 } v0 := size :byte { size << 4 }"), [0x60, 0x08, 0x80]);
+}
+
+#[test]
+fn unpack_splits_an_address_into_v0_and_v1() {
+    assert_eq!(rom(": data 1 2 :unpack 0xA data"), [1, 2, 0x60, 0xA2, 0x61, 0x00]);
+    assert_eq!(rom(":org 0x3FE : data :unpack 0 data")[0x1FE..], [0x60, 0x03, 0x61, 0xFE]);
+}
+
+#[test]
+fn forward_labels_are_patched_in() {
+    assert_eq!(rom("i := long data jump0 table : table 0 : data 1"), [0xF0, 0x00, 0x02, 0x07, 0xB2, 0x06, 0, 1]);
+    assert_eq!(rom("i := long far :org 0x1234 : far 7")[..4], [0xF0, 0x00, 0x12, 0x34]);
+}
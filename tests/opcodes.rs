@@ -0,0 +1,59 @@
+//! The opcode table of `chip8::opcodes` and the arithmetic it executes to.
+
+use chip8::opcodes::Instruction;
+use chip8::{Chip, Quirks};
+
+/// Runs the single instruction `opcode` with the registers set to `v`.
+fn execute(opcode: u16, v: &[(usize, u8)]) -> Chip {
+    let mut chip = Chip::new(Quirks::default());
+    chip.load_rom(&opcode.to_be_bytes()).unwrap();
+    for (x, value) in v {
+        chip.set_register(*x, *value);
+    }
+    chip.emulate_cycle().unwrap();
+    chip
+}
+
+#[test]
+fn every_opcode_encodes_back_to_itself() {
+    let mut decoded = 0;
+    for opcode in 0..=0xFFFF {
+        if let Ok(instruction) = Instruction::decode(opcode) {
+            assert_eq!(instruction.encode(), opcode, "{:04X} decoded to {:?}", opcode, instruction);
+            assert_eq!(Instruction::decode(instruction.encode()), Ok(instruction));
+            decoded += 1;
+        }
+    }
+    // The 11 whole families 0-4, 6, 7 and A-D, the 3 5XYN, 9 8XYN and 1 9XYN
+    // forms, the 2 EXNN and the 14 FXNN that take any X, plus F000 and F002.
+    assert_eq!(decoded, 11 * 4096 + (3 + 9 + 1) * 256 + (2 + 14) * 16 + 2);
+}
+
+#[test]
+fn arithmetic_advances_pc_once() {
+    for opcode in [0x8014, 0x8015, 0x8016, 0x8017, 0x801E] {
+        assert_eq!(execute(opcode, &[]).pc(), 0x202, "{:04X}", opcode);
+    }
+}
+
+#[test]
+fn flags_follow_the_result() {
+    // VX == VY is no borrow.
+    assert_eq!(execute(0x8015, &[(0, 7), (1, 7)]).registers()[0xF], 1);
+    assert_eq!(execute(0x8017, &[(0, 7), (1, 7)]).registers()[0xF], 1);
+    assert_eq!(execute(0x8017, &[(0, 8), (1, 7)]).registers()[0xF], 0);
+    // The shifted out bit is stored as 0 or 1.
+    let chip = execute(0x800E, &[(0, 0x81)]);
+    assert_eq!((chip.registers()[0], chip.registers()[0xF]), (0x02, 1));
+    let chip = execute(0x8006, &[(0, 0x81)]);
+    assert_eq!((chip.registers()[0], chip.registers()[0xF]), (0x40, 1));
+}
+
+#[test]
+fn flags_win_over_the_result_in_vf() {
+    assert_eq!(execute(0x8F14, &[(0xF, 0xFF), (1, 2)]).registers()[0xF], 1);
+    assert_eq!(execute(0x8F15, &[(0xF, 1), (1, 2)]).registers()[0xF], 0);
+    assert_eq!(execute(0x8F17, &[(0xF, 2), (1, 1)]).registers()[0xF], 0);
+    assert_eq!(execute(0x8F0E, &[(0xF, 0x40)]).registers()[0xF], 0);
+    assert_eq!(execute(0x8F06, &[(0xF, 0x03)]).registers()[0xF], 1);
+}
@@ -0,0 +1,138 @@
+//! Every `Quirks` switch, each turned on alone, and the named presets.
+
+use chip8::quirks::PRESET_NAMES;
+use chip8::{Chip, Quirks};
+
+const DATA: u16 = 0x300;
+
+fn boot(quirks: Quirks, program: &[u16]) -> Chip {
+    let rom: Vec<u8> = program.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+    let mut chip = Chip::new(quirks);
+    chip.load_rom(&rom).unwrap();
+    chip.set_memory(DATA as usize, &[0xFF, 0xFF]).unwrap();
+    chip
+}
+
+/// The chips that ran `cycles` instructions of `program` without quirks, and with the quirk `enable` sets.
+fn run(enable: fn(&mut Quirks), program: &[u16], cycles: usize) -> (Chip, Chip) {
+    let mut quirks = Quirks::default();
+    enable(&mut quirks);
+    let mut chips = (boot(Quirks::default(), program), boot(quirks, program));
+    for _ in 0..cycles {
+        chips.0.emulate_cycle().unwrap();
+        chips.1.emulate_cycle().unwrap();
+    }
+    chips
+}
+
+fn lit(chip: &Chip) -> Vec<(usize, usize)> {
+    let width = chip.width();
+    chip.graphics().iter().enumerate()
+        .filter(|(_, pixel)| **pixel != 0)
+        .map(|(index, _)| (index % width, index / width))
+        .collect()
+}
+
+#[test]
+fn shift_uses_vy() {
+    let (chip, quirky) = run(|quirks| quirks.shift_uses_vy = true, &[0x6010, 0x6181, 0x8016], 3);
+    assert_eq!((chip.registers()[0], chip.registers()[0xF]), (0x08, 0));
+    assert_eq!((quirky.registers()[0], quirky.registers()[0xF]), (0x40, 1));
+
+    let (chip, quirky) = run(|quirks| quirks.shift_uses_vy = true, &[0x6010, 0x6181, 0x801E], 3);
+    assert_eq!((chip.registers()[0], chip.registers()[0xF]), (0x20, 0));
+    assert_eq!((quirky.registers()[0], quirky.registers()[0xF]), (0x02, 1));
+}
+
+#[test]
+fn load_store_increments_i() {
+    for opcode in [0xF255, 0xF265] {
+        let (chip, quirky) = run(|quirks| quirks.load_store_increments_i = true, &[0xA000 | DATA, opcode], 2);
+        assert_eq!((chip.i(), quirky.i()), (DATA as u32, DATA as u32 + 3), "{:04X}", opcode);
+    }
+}
+
+#[test]
+fn jump_uses_vx() {
+    let (chip, quirky) = run(|quirks| quirks.jump_uses_vx = true, &[0x6008, 0x6204, 0xB210], 3);
+    assert_eq!((chip.pc(), quirky.pc()), (0x218, 0x214));
+}
+
+#[test]
+fn logic_resets_vf() {
+    for opcode in [0x8011, 0x8012, 0x8013] {
+        let (chip, quirky) = run(|quirks| quirks.logic_resets_vf = true, &[0x6F05, opcode], 2);
+        assert_eq!((chip.registers()[0xF], quirky.registers()[0xF]), (5, 0), "{:04X}", opcode);
+    }
+}
+
+#[test]
+fn clip_sprites() {
+    // A two-row sprite at (62, 31), off the right and bottom edges.
+    let (chip, quirky) = run(|quirks| quirks.clip_sprites = true, &[0x603E, 0x611F, 0xA000 | DATA, 0xD012], 4);
+    let mut wrapped = lit(&chip);
+    wrapped.sort_unstable();
+    assert_eq!(wrapped, [(0, 0), (0, 31), (1, 0), (1, 31), (2, 0), (2, 31), (3, 0), (3, 31), (4, 0), (4, 31),
+                         (5, 0), (5, 31), (62, 0), (62, 31), (63, 0), (63, 31)]);
+    assert_eq!(lit(&quirky), [(62, 31), (63, 31)]);
+
+    // The origin itself still wraps.
+    let (_, quirky) = run(|quirks| quirks.clip_sprites = true, &[0x6041, 0x6121, 0xA000 | DATA, 0xD011], 4);
+    assert_eq!(lit(&quirky).len(), 8);
+    assert_eq!(lit(&quirky)[0], (1, 1));
+}
+
+#[test]
+fn display_wait() {
+    let program = [0xA000 | DATA, 0xD011, 0x7008, 0xD011, 0x7008, 0xD011];
+    let mut chip = boot(Quirks::default(), &program);
+    let mut quirky = boot(Quirks { display_wait: true, ..Quirks::default() }, &program);
+    chip.run_frame(6).unwrap();
+    quirky.run_frame(6).unwrap();
+    assert_eq!((chip.pc(), quirky.pc()), (0x20C, 0x206));
+    assert_eq!((lit(&chip).len(), lit(&quirky).len()), (24, 8));
+
+    // The next vertical blank lets one more sprite through.
+    quirky.run_frame(6).unwrap();
+    assert_eq!((quirky.pc(), lit(&quirky).len()), (0x20A, 16));
+}
+
+#[test]
+fn presets_are_found_by_name() {
+    assert_eq!(Quirks::preset("default"), Some(Quirks::default()));
+    assert_eq!(Quirks::preset("vip"), Some(Quirks::cosmac_vip()));
+    assert_eq!(Quirks::preset("schip"), Some(Quirks::super_chip()));
+    assert_eq!(Quirks::preset("xochip"), Some(Quirks::xo_chip()));
+    assert_eq!(Quirks::preset("octo"), None);
+
+    for name in PRESET_NAMES.iter() {
+        let quirks = Quirks::preset(name).unwrap();
+        assert_eq!(Quirks::from_bits(quirks.to_bits()), quirks, "{}", name);
+    }
+}
+
+#[test]
+fn presets_set_the_platform_behaviour() {
+    let vip = Quirks::cosmac_vip();
+    assert!(vip.shift_uses_vy && vip.load_store_increments_i && vip.logic_resets_vf && vip.clip_sprites && vip.display_wait);
+    assert!(!vip.jump_uses_vx);
+
+    let schip = Quirks::super_chip();
+    assert!(schip.jump_uses_vx && schip.clip_sprites);
+    assert!(!schip.shift_uses_vy && !schip.load_store_increments_i && !schip.logic_resets_vf && !schip.display_wait);
+
+    let xochip = Quirks::xo_chip();
+    assert!(xochip.shift_uses_vy && xochip.load_store_increments_i);
+    assert!(!xochip.jump_uses_vx && !xochip.logic_resets_vf && !xochip.clip_sprites && !xochip.display_wait);
+
+    // The VIP's shifts and FX55 run differently from SUPER-CHIP's on the same program.
+    let program = [0x6181, 0x8016, 0xA000 | DATA, 0xF055];
+    let mut vip = boot(Quirks::cosmac_vip(), &program);
+    let mut schip = boot(Quirks::super_chip(), &program);
+    for _ in 0..program.len() {
+        vip.emulate_cycle().unwrap();
+        schip.emulate_cycle().unwrap();
+    }
+    assert_eq!((vip.registers()[0], vip.i()), (0x40, DATA as u32 + 1));
+    assert_eq!((schip.registers()[0], schip.i()), (0x00, DATA as u32));
+}
@@ -0,0 +1,72 @@
+//! The delta-compressed history of `chip8::rewind`.
+
+use chip8::rewind::RewindBuffer;
+
+/// A state of `length` bytes that differs from its neighbours in a few bytes.
+fn state(frame: u8, length: usize) -> Vec<u8> {
+    let mut state = vec![0xAA; length];
+    state[frame as usize % length] = frame;
+    state[length - 1] = frame.wrapping_mul(3);
+    state
+}
+
+#[test]
+fn pops_the_states_back_in_reverse_order() {
+    let mut rewind = RewindBuffer::new(100, 1 << 20);
+    // Switching to hi-res halfway grows the state, and switching back shrinks it.
+    let states: Vec<Vec<u8>> = (0..30).map(|frame| state(frame, if (10..20).contains(&frame) { 300 } else { 100 }))
+        .collect();
+    for state in &states {
+        rewind.push(state.clone());
+    }
+    assert_eq!(rewind.len(), 29);
+
+    for expected in states[..29].iter().rev() {
+        assert_eq!(rewind.pop().as_ref(), Some(expected));
+    }
+    assert!(rewind.is_empty());
+    assert_eq!(rewind.used_bytes(), 0);
+    assert_eq!(rewind.pop(), None);
+    assert_eq!(rewind.pop(), None);
+
+    // Recording goes on from the state rewound to.
+    rewind.push(state(40, 100));
+    assert_eq!(rewind.pop(), Some(states[0].clone()));
+}
+
+#[test]
+fn forgets_the_oldest_frames_past_the_frame_cap() {
+    let mut rewind = RewindBuffer::new(5, 1 << 20);
+    for frame in 0..20 {
+        rewind.push(state(frame, 100));
+    }
+    assert_eq!(rewind.len(), 5);
+    for frame in (14..19).rev() {
+        assert_eq!(rewind.pop(), Some(state(frame, 100)));
+    }
+    assert_eq!(rewind.pop(), None);
+}
+
+#[test]
+fn forgets_the_oldest_frames_past_the_byte_cap() {
+    let mut unbounded = RewindBuffer::new(100, usize::MAX);
+    unbounded.push(state(0, 100));
+    unbounded.push(state(1, 100));
+    let delta_bytes = unbounded.used_bytes();
+    assert!(delta_bytes > 0 && delta_bytes < 20, "{} bytes for a delta of two bytes", delta_bytes);
+
+    let mut rewind = RewindBuffer::new(100, delta_bytes * 3);
+    for frame in 0..10 {
+        rewind.push(state(frame, 100));
+        assert!(rewind.used_bytes() <= delta_bytes * 3);
+    }
+    assert_eq!(rewind.len(), 3);
+    assert_eq!(rewind.pop(), Some(state(8, 100)));
+
+    // No room for even one delta: only the latest state is kept.
+    let mut rewind = RewindBuffer::new(100, 0);
+    rewind.push(state(0, 100));
+    rewind.push(state(1, 100));
+    assert_eq!((rewind.len(), rewind.used_bytes()), (0, 0));
+    assert_eq!(rewind.pop(), None);
+}
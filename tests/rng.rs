@@ -0,0 +1,79 @@
+//! The random sources of `chip8::rng` behind CXNN.
+
+use chip8::rng::{RandomSource, ScriptedRng, XorShiftRng};
+use chip8::savestate::SaveStateError;
+use chip8::{Chip, Quirks};
+
+const SCRIPT: [u8; 3] = [0xAB, 0x5C, 0x3D];
+
+/// A chip running `opcodes`, drawing its random bytes from `rng`.
+fn boot(rng: Box<dyn RandomSource>, opcodes: &[u16]) -> Chip {
+    let rom: Vec<u8> = opcodes.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+    let mut chip = Chip::new(Quirks::default());
+    chip.set_rng(rng);
+    chip.load_rom(&rom).unwrap();
+    chip
+}
+
+fn step(chip: &mut Chip, cycles: usize) {
+    for _ in 0..cycles {
+        chip.emulate_cycle().unwrap();
+    }
+}
+
+#[test]
+fn cxnn_masks_the_next_byte_of_the_source() {
+    let mut chip = boot(Box::new(ScriptedRng::new(SCRIPT.to_vec())), &[0xC0FF, 0xC10F, 0xC2F0, 0xC3FF]);
+    step(&mut chip, 4);
+    assert_eq!(chip.registers()[..4], [0xAB, 0x0C, 0x30, 0xAB]);
+
+    let mut chip = boot(Box::new(ScriptedRng::new(Vec::new())), &[0xC0FF]);
+    chip.set_register(0, 0x55);
+    step(&mut chip, 1);
+    assert_eq!(chip.registers()[0], 0);
+}
+
+#[test]
+fn the_random_sequence_survives_save_states() {
+    let program = [0xC0FF, 0xC1FF, 0xC2FF, 0xC3FF];
+    let sources: [fn() -> Box<dyn RandomSource>; 2] = [
+        || Box::new(ScriptedRng::new(SCRIPT.to_vec())),
+        || Box::new(XorShiftRng::new(42)),
+    ];
+    for source in sources.iter() {
+        let mut chip = boot(source(), &program);
+        step(&mut chip, 2);
+        let state = chip.save_state();
+        step(&mut chip, 2);
+
+        // A fresh source picks up where the saved one was.
+        let mut restored = boot(source(), &program);
+        restored.load_state(&state).unwrap();
+        step(&mut restored, 2);
+        assert_eq!(restored.registers(), chip.registers());
+    }
+}
+
+#[test]
+fn a_state_past_the_script_is_rejected() {
+    let mut chip = boot(Box::new(ScriptedRng::new(SCRIPT.to_vec())), &[0xC0FF, 0xC0FF]);
+    step(&mut chip, 2);
+    let state = chip.save_state();
+
+    let mut shorter = boot(Box::new(ScriptedRng::new(SCRIPT[..2].to_vec())), &[0xC0FF, 0xC0FF]);
+    let untouched = shorter.save_state();
+    assert_eq!(shorter.load_state(&state), Err(SaveStateError::Corrupted("random number generator state")));
+    assert_eq!(shorter.save_state(), untouched);
+}
+
+#[test]
+fn xorshift_is_deterministic_per_seed() {
+    let bytes = |seed| {
+        let mut rng = XorShiftRng::new(seed);
+        (0..16).map(|_| rng.next_byte()).collect::<Vec<_>>()
+    };
+    assert_eq!(bytes(7), bytes(7));
+    assert_ne!(bytes(7), bytes(8));
+    // Zero is a valid seed, even though xorshift cannot run from an all-zero state.
+    assert_ne!(bytes(0), [0; 16]);
+}
@@ -0,0 +1,87 @@
+//! Saving and restoring the whole machine with `Chip::save_state` and `Chip::load_state`.
+
+mod common;
+
+use chip8::chip::MEMORY_SIZE;
+use chip8::savestate::{SaveStateError, VERSION};
+
+use common::boot;
+
+const PROGRAM: &str = "
+: main
+  v0 := 30
+  buzzer := v0
+  loop
+    v1 += 1
+    i := 0x300
+    save v1
+  again
+";
+
+// Offsets in a save state: magic, version, ROM hash, opcode, then memory.
+const VERSION_OFFSET: usize = 4;
+const ROM_HASH_OFFSET: usize = 6;
+const MEMORY_OFFSET: usize = 16;
+// After the memory come the registers, I and pc, then the resolution byte.
+const RESOLUTION_OFFSET: usize = MEMORY_OFFSET + MEMORY_SIZE + 16 + 4 + 4;
+
+fn rom_hash(state: &[u8]) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&state[ROM_HASH_OFFSET..ROM_HASH_OFFSET + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+#[test]
+fn a_loaded_state_runs_on_like_the_saved_one() {
+    let mut chip = boot(PROGRAM);
+    chip.run_frame(20).unwrap();
+    chip.tick_timers();
+    let state = chip.save_state();
+
+    let mut restored = boot(PROGRAM);
+    assert!(!restored.is_sounding());
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.save_state(), state);
+    assert!(restored.is_sounding());
+
+    for _ in 0..10 {
+        chip.run_frame(20).unwrap();
+        restored.run_frame(20).unwrap();
+        chip.tick_timers();
+        restored.tick_timers();
+    }
+    assert_eq!(restored.save_state(), chip.save_state());
+}
+
+#[test]
+fn a_rejected_state_leaves_the_chip_untouched() {
+    let mut saved = boot(PROGRAM);
+    saved.run_frame(20).unwrap();
+    let state = saved.save_state();
+    let mut chip = boot(PROGRAM);
+    let untouched = chip.save_state();
+
+    let mut load = |state: &[u8]| {
+        let result = chip.load_state(state);
+        assert_eq!(chip.save_state(), untouched);
+        result
+    };
+    assert_eq!(load(b"not a save state"), Err(SaveStateError::NotASaveState));
+    assert_eq!(load(&state[..state.len() - 1]), Err(SaveStateError::Truncated));
+
+    let mut newer = state.clone();
+    newer[VERSION_OFFSET..ROM_HASH_OFFSET].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert_eq!(load(&newer), Err(SaveStateError::UnsupportedVersion { found: VERSION + 1, expected: VERSION }));
+
+    let mut resolution = state.clone();
+    resolution[RESOLUTION_OFFSET] = 2;
+    assert_eq!(load(&resolution), Err(SaveStateError::Corrupted("resolution")));
+
+    let mut trailing = state.clone();
+    trailing.push(0);
+    assert_eq!(load(&trailing), Err(SaveStateError::Corrupted("length")));
+
+    let mut other = boot(": main v0 := 1");
+    let expected = rom_hash(&other.save_state());
+    assert_eq!(other.load_state(&state), Err(SaveStateError::RomMismatch { expected, found: rom_hash(&state) }));
+}
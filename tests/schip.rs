@@ -0,0 +1,87 @@
+//! The SUPER-CHIP instructions: hi-res mode, scrolling, big sprites and fonts, RPL flags.
+
+use chip8::{Chip, Quirks};
+
+// A single lit pixel, then a 16x16 sprite with its top-left and bottom-right pixels lit.
+const PIXEL: u16 = 0x300;
+const BIG_SPRITE: u16 = 0x301;
+
+/// A chip that ran `program` up to its end.
+fn run(program: &[u16]) -> Chip {
+    let rom: Vec<u8> = program.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+    let mut chip = Chip::new(Quirks::default());
+    chip.load_rom(&rom).unwrap();
+    let mut big_sprite = [0; 32];
+    big_sprite[0] = 0x80;
+    big_sprite[31] = 0x01;
+    chip.set_memory(PIXEL as usize, &[0x80]).unwrap();
+    chip.set_memory(BIG_SPRITE as usize, &big_sprite).unwrap();
+    while chip.pc() < 0x200 + rom.len() {
+        chip.emulate_cycle().unwrap();
+    }
+    chip
+}
+
+/// The coordinates of the lit pixels.
+fn lit(chip: &Chip) -> Vec<(usize, usize)> {
+    let width = chip.width();
+    chip.graphics().iter().enumerate()
+        .filter(|(_, pixel)| **pixel != 0)
+        .map(|(index, _)| (index % width, index / width))
+        .collect()
+}
+
+#[test]
+fn hires_and_lores_switch_the_resolution_and_clear() {
+    let chip = run(&[0x00FF, 0xA000 | PIXEL, 0x600A, 0xD011]);
+    assert!(chip.is_hires());
+    assert_eq!((chip.width(), chip.height()), (128, 64));
+    assert_eq!(lit(&chip), [(10, 0)]);
+
+    let chip = run(&[0x00FF, 0xA000 | PIXEL, 0xD001, 0x00FE]);
+    assert!(!chip.is_hires());
+    assert_eq!((chip.width(), chip.height()), (64, 32));
+    assert_eq!(lit(&chip), []);
+
+    let chip = run(&[0xA000 | PIXEL, 0xD001, 0x00FF]);
+    assert_eq!(lit(&chip), []);
+}
+
+#[test]
+fn scrolling_moves_the_screen_by_pixels() {
+    let draw = [0x00FF, 0xA000 | PIXEL, 0x600A, 0x6105, 0xD011];
+    assert_eq!(lit(&run(&[&draw[..], &[0x00C3]].concat())), [(10, 8)]);
+    assert_eq!(lit(&run(&[&draw[..], &[0x00FB]].concat())), [(14, 5)]);
+    assert_eq!(lit(&run(&[&draw[..], &[0x00FC]].concat())), [(6, 5)]);
+    // Pixels scrolled off the screen are lost.
+    assert_eq!(lit(&run(&[&draw[..], &[0x00CF, 0x00CF, 0x00CF, 0x00CF]].concat())), []);
+    assert_eq!(lit(&run(&[&draw[..], &[0x00FC, 0x00FC, 0x00FC]].concat())), []);
+}
+
+#[test]
+fn dxy0_draws_a_16_by_16_sprite() {
+    let chip = run(&[0x00FF, 0xA000 | BIG_SPRITE, 0x6014, 0x6102, 0xD010]);
+    assert_eq!(lit(&chip), [(20, 2), (35, 17)]);
+    assert_eq!(chip.registers()[0xF], 0);
+
+    let chip = run(&[0x00FF, 0xA000 | BIG_SPRITE, 0xD010, 0xD010]);
+    assert_eq!(lit(&chip), []);
+    assert_eq!(chip.registers()[0xF], 1);
+}
+
+#[test]
+fn fx30_points_i_at_the_big_digit() {
+    let zero = run(&[0x6000, 0xF030]).i();
+    assert_eq!(run(&[0x6007, 0xF030]).i(), zero + 7 * 10);
+    assert_eq!(run(&[0x601F, 0xF030]).i(), zero + 0xF * 10);
+    assert_ne!(run(&[0x6000, 0xF030]).memory()[zero as usize..zero as usize + 10], [0; 10]);
+}
+
+#[test]
+fn fx75_and_fx85_save_and_restore_the_flags() {
+    let chip = run(&[0x6011, 0x6122, 0x6233, 0x6344, 0xF275]);
+    assert_eq!(chip.rpl_flags()[..4], [0x11, 0x22, 0x33, 0]);
+
+    let chip = run(&[0x6011, 0x6122, 0xF175, 0x6000, 0x6100, 0x6255, 0xF185]);
+    assert_eq!(chip.registers()[..3], [0x11, 0x22, 0x55]);
+}
@@ -0,0 +1,100 @@
+//! The per-instruction trace of `chip8::trace` and its log filters.
+
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use chip8::trace::{self, Filter, Level, Step, Target};
+use chip8::Chip;
+
+use common::boot;
+
+const PROGRAM: &str = "
+: main
+  v1 := 2
+  v1 += 3
+  v2 := key
+  loop again
+";
+
+fn traced() -> (Chip, Rc<RefCell<Vec<Step>>>) {
+    let mut chip = boot(PROGRAM);
+    let steps = Rc::new(RefCell::new(Vec::new()));
+    chip.set_tracer(Box::new(steps.clone()));
+    (chip, steps)
+}
+
+#[test]
+fn traces_the_registers_around_each_instruction() {
+    let (mut chip, steps) = traced();
+    for _ in 0..2 {
+        chip.emulate_cycle().unwrap();
+    }
+
+    let steps = steps.borrow();
+    assert_eq!(steps.len(), 2);
+    assert_eq!((steps[1].pc, steps[1].opcode), (0x202, 0x7103));
+    assert_eq!(steps[1].before, steps[0].after);
+    assert_eq!((steps[1].before.v[1], steps[1].after.v[1]), (2, 5));
+    assert_eq!(steps[0].to_string(), format!(
+        "0200 6102 v={} i=0000 sp=00 dt=00 st=00 -> v=0002{} i=0000 sp=00 dt=00 st=00",
+        "00".repeat(16), "00".repeat(14)));
+}
+
+#[test]
+fn traces_a_key_wait_once() {
+    let (mut chip, steps) = traced();
+    for _ in 0..10 {
+        chip.emulate_cycle().unwrap();
+    }
+    assert_eq!(steps.borrow().len(), 3);
+
+    chip.set_key(7, true);
+    chip.emulate_cycle().unwrap();
+    assert_eq!(steps.borrow().last().unwrap().pc, 0x206);
+
+    // Stopping the trace stops the calls, and the emulation runs on.
+    assert!(chip.take_tracer().is_some());
+    chip.emulate_cycle().unwrap();
+    assert_eq!(steps.borrow().len(), 4);
+}
+
+#[test]
+fn filters_apply_levels_in_order() {
+    let filter: Filter = "debug, cpu=trace,input=off".parse().unwrap();
+    assert_eq!(filter.level(Target::Cpu), Some(Level::Trace));
+    assert_eq!(filter.level(Target::Timers), Some(Level::Debug));
+    assert_eq!(filter.level(Target::Input), None);
+    assert_eq!("gpu=trace".parse::<Filter>().unwrap_err(), "unknown target `gpu`");
+    assert_eq!("cpu=loud".parse::<Filter>().unwrap_err(), "unknown level `loud`");
+}
+
+#[test]
+fn traces_read_back_and_report_the_first_divergence() {
+    let (mut chip, steps) = traced();
+    for _ in 0..3 {
+        chip.emulate_cycle().unwrap();
+    }
+    let steps = steps.borrow();
+    let written: String = steps.iter().map(|step| format!("{}\n", step)).collect();
+    let source = format!("# reference\n\n{}", written);
+    let reference = trace::parse(&source).unwrap();
+    assert_eq!(reference.iter().map(|(line, _)| *line).collect::<Vec<_>>(), [3, 4, 5]);
+    let mut expected: Vec<Step> = reference.into_iter().map(|(_, step)| step).collect();
+    assert_eq!(expected, *steps);
+    assert_eq!(trace::first_divergence(&expected, &steps), None);
+
+    // An ADD that also advanced pc twice, say.
+    expected[1].after.v[1] = 6;
+    expected[2].pc = 0x206;
+    let divergence = trace::first_divergence(&expected, &steps).unwrap();
+    assert_eq!(divergence.index, 1);
+    assert_eq!(divergence.differences, ["V1 after: expected 06, got 05"]);
+    assert_eq!(trace::first_divergence(&expected[..1], &steps[..0]).unwrap().differences,
+               ["the run stopped after 0 instructions"]);
+
+    let error = trace::parse("0200 6102 v=00 i=0000 sp=00 dt=00 st=00 -> v=00\n").unwrap_err();
+    assert_eq!((error.line, error.message.as_str()), (1, "v must be 32 hex digits, found `00`"));
+    assert_eq!(trace::parse("0200 6102\n").unwrap_err().message, "missing `->` between the registers");
+}
@@ -0,0 +1,77 @@
+//! The XO-CHIP instructions: long I loads, register ranges, bitplanes and audio.
+
+use chip8::{Chip, Quirks};
+
+const DATA: u16 = 0x300;
+
+/// A chip that ran `program` up to its end, with `data` loaded at `DATA`.
+fn run(program: &[u16], data: &[u8]) -> Chip {
+    let rom: Vec<u8> = program.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+    let mut chip = Chip::new(Quirks::default());
+    chip.load_rom(&rom).unwrap();
+    chip.set_memory(DATA as usize, data).unwrap();
+    while chip.pc() < 0x200 + rom.len() {
+        chip.emulate_cycle().unwrap();
+    }
+    chip
+}
+
+fn pixel(chip: &Chip, x: usize, y: usize) -> u8 {
+    chip.graphics()[y * chip.width() + x]
+}
+
+#[test]
+fn skips_step_over_both_words_of_f000() {
+    // V0 = 1, V1 = 1 and key 0 is up; every skip below is taken.
+    for skip in [0x3001, 0x4002, 0x5010, 0x9020, 0xE2A1] {
+        let chip = run(&[0x6001, 0x6101, skip, 0xF000, 0x1234, 0x6305], &[]);
+        assert_eq!((chip.i(), chip.registers()[3]), (0, 5), "{:04X}", skip);
+    }
+    let chip = run(&[0x6001, 0x3002, 0xF000, 0x1234, 0x6305], &[]);
+    assert_eq!((chip.i(), chip.registers()[3]), (0x1234, 5));
+}
+
+#[test]
+fn register_ranges_count_down_when_x_is_greater_than_y() {
+    let chip = run(&[0x6101, 0x6202, 0x6303, 0xA000 | DATA, 0x5312], &[]);
+    assert_eq!(chip.memory()[DATA as usize..DATA as usize + 4], [3, 2, 1, 0]);
+    assert_eq!(chip.i(), DATA as u32);
+
+    let chip = run(&[0xA000 | DATA, 0x5313], &[7, 8, 9]);
+    assert_eq!(chip.registers()[..5], [0, 9, 8, 7, 0]);
+    let chip = run(&[0xA000 | DATA, 0x5133], &[7, 8, 9]);
+    assert_eq!(chip.registers()[..5], [0, 7, 8, 9, 0]);
+}
+
+#[test]
+fn planes_mask_drawing_clearing_and_scrolling() {
+    // One pixel in the first plane at (0, 0), one in the second at (1, 0).
+    let draw_both = [0xA000 | DATA, 0xD011, 0xF201, 0x6001, 0xD011];
+    let chip = run(&draw_both, &[0x80]);
+    assert_eq!((pixel(&chip, 0, 0), pixel(&chip, 1, 0)), (1, 2));
+
+    let chip = run(&[&draw_both[..], &[0x00E0]].concat(), &[0x80]);
+    assert_eq!((pixel(&chip, 0, 0), pixel(&chip, 1, 0)), (1, 0));
+    let chip = run(&[&draw_both[..], &[0xF301, 0x00E0]].concat(), &[0x80]);
+    assert_eq!((pixel(&chip, 0, 0), pixel(&chip, 1, 0)), (0, 0));
+
+    let chip = run(&[&draw_both[..], &[0x00C1]].concat(), &[0x80]);
+    assert_eq!((pixel(&chip, 0, 0), pixel(&chip, 1, 0), pixel(&chip, 1, 1)), (1, 0, 2));
+    let chip = run(&[&draw_both[..], &[0xF101, 0x00FB]].concat(), &[0x80]);
+    assert_eq!((pixel(&chip, 0, 0), pixel(&chip, 1, 0), pixel(&chip, 4, 0)), (0, 2, 1));
+
+    // With both planes selected the second plane's rows follow the first's.
+    let chip = run(&[0xF301, 0xA000 | DATA, 0xD011], &[0x80, 0x40]);
+    assert_eq!((pixel(&chip, 0, 0), pixel(&chip, 1, 0)), (1, 2));
+    assert_eq!(chip.registers()[0xF], 0);
+    let chip = run(&[0xF001, 0xA000 | DATA, 0xD011], &[0xFF]);
+    assert_eq!(chip.graphics().iter().filter(|pixel| **pixel != 0).count(), 0);
+}
+
+#[test]
+fn audio_loads_the_pattern_and_pitch() {
+    let pattern: Vec<u8> = (0..16).map(|byte| byte * 17).collect();
+    let chip = run(&[0xA000 | DATA, 0xF002, 0x657F, 0xF53A], &pattern);
+    assert_eq!(chip.audio_pattern()[..], pattern[..]);
+    assert_eq!(chip.pitch(), 0x7F);
+}